@@ -6,7 +6,8 @@ use image::io::Reader as ImageReader;
 
 pub struct Animation {
     pub frames: Vec<Rect>,
-    //timings: Vec<usize>,
+    // How many seconds to hold each frame for
+    pub timings: Vec<f32>,
 
     // Do this for the exercise today!
     // You'll want to know the frames involved and the timing for each frame
@@ -15,9 +16,45 @@ pub struct Animation {
     // but there are lots of designs that will work!
 }
 
+/// Times within this many seconds of a frame change count as past it, so elapsed time
+/// built up out of many small steps doesn't lose a frame to float error.
+pub const TIME_SLACK: f32 = 1e-4;
+
+/// Convert a duration in milliseconds (as image formats like to store them)
+/// into seconds, never less than one simulated frame (some GIFs say 0).
+pub fn ms_to_secs(ms: f64) -> f32 {
+    (ms / 1000.0).max(crate::DT) as f32
+}
+
 impl Animation {
+    /// Every frame shows for one simulated frame (DT seconds)
     pub fn new(frames: Vec<Rect>) -> Self {
-        Self { frames }
+        let timings = vec![crate::DT as f32; frames.len()];
+        Self { frames, timings }
+    }
+    /// timings is how long each frame shows, in seconds
+    pub fn with_timings(frames: Vec<Rect>, timings: Vec<f32>) -> Self {
+        assert_eq!(frames.len(), timings.len(), "Every frame needs a timing");
+        assert!(
+            timings.iter().all(|&t| t > 0.0),
+            "Frames must last some time"
+        );
+        Self { frames, timings }
+    }
+    /// How many seconds one full loop takes
+    pub fn duration(&self) -> f32 {
+        self.timings.iter().sum()
+    }
+    /// Which frame is showing `secs` seconds after the animation started (it loops)
+    pub fn frame_at(&self, secs: f32) -> Rect {
+        let mut t = (secs + TIME_SLACK) % self.duration();
+        for (frame, &time) in self.frames.iter().zip(self.timings.iter()) {
+            if t < time {
+                return *frame;
+            }
+            t -= time;
+        }
+        *self.frames.last().unwrap()
     }
 
     //dynamic data can include position?
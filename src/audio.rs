@@ -0,0 +1,261 @@
+use rodio::{OutputStreamHandle, PlayError, Sample, Sink, Source};
+use std::collections::VecDeque;
+
+/// A sound that's playing (or was), for stopping it early
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Voice(usize);
+
+/// Plays sound effects with a cap on how many can overlap.
+/// When a new sound would go over the cap, the oldest one still playing is cut off,
+/// so a big pile-up of effects doesn't turn into clipping mush.
+pub struct Audio {
+    handle: OutputStreamHandle,
+    // Each sound gets its own sink so it can be stopped on its own
+    voices: Voices<Sink>,
+    // Overall loudness, 1.0 being each sound's own volume
+    master: f32,
+    // How far ducked we are; everything plays at master * duck.level
+    duck: Duck,
+}
+
+impl Audio {
+    pub fn new(handle: &OutputStreamHandle, limit: usize) -> Self {
+        Self {
+            handle: handle.clone(),
+            voices: Voices::new(limit),
+            master: 1.0,
+            duck: Duck::new(),
+        }
+    }
+    #[allow(dead_code)]
+    pub fn volume(&self) -> f32 {
+        self.master
+    }
+    /// Set the overall volume for everything, playing or not (1.0 is normal)
+    #[allow(dead_code)]
+    pub fn set_volume(&mut self, volume: f32) {
+        self.master = volume.max(0.0);
+        self.apply_volume();
+    }
+    /// Fade everything down to target (a fraction of the master volume, like 0.3)
+    /// over the next `frames` calls to update, say while paused
+    pub fn duck(&mut self, target: f32, frames: u32) {
+        self.duck.down(target, frames);
+        self.apply_volume();
+    }
+    /// Fade back up to full volume, taking as long as the last duck did
+    pub fn unduck(&mut self) {
+        self.duck.up();
+        self.apply_volume();
+    }
+    /// Call once per frame to move any duck fade along
+    pub fn update(&mut self) {
+        if self.duck.step() {
+            self.apply_volume();
+        }
+    }
+    fn apply_volume(&self) {
+        for sink in self.voices.iter() {
+            sink.set_volume(self.master * self.duck.level);
+        }
+    }
+    /// Start a sound, cutting off the oldest one if we're at the cap.
+    /// Fails if the output device won't give us a sink to play it on.
+    pub fn play_sound<S>(&mut self, source: S) -> Result<Voice, PlayError>
+    where
+        S: Source + Send + 'static,
+        S::Item: Sample + Send,
+    {
+        self.forget_finished();
+        let sink = Sink::try_new(&self.handle)?;
+        sink.set_volume(self.master * self.duck.level);
+        sink.append(source);
+        let (voice, evicted) = self.voices.add(sink);
+        for sink in evicted {
+            sink.stop();
+        }
+        Ok(voice)
+    }
+    /// Stop a sound early; does nothing if it already finished or got cut off
+    #[allow(dead_code)]
+    pub fn stop(&mut self, voice: Voice) {
+        if let Some(sink) = self.voices.remove(voice) {
+            sink.stop();
+        }
+    }
+    /// How many sounds are still playing
+    #[allow(dead_code)]
+    pub fn active_voices(&mut self) -> usize {
+        self.forget_finished();
+        self.voices.len()
+    }
+    fn forget_finished(&mut self) {
+        self.voices.list.retain(|(_, sink)| !sink.empty());
+    }
+}
+
+// The fading side of Audio's ducking, kept apart from the sinks
+struct Duck {
+    // 1.0 is not ducked at all
+    level: f32,
+    // An in-progress fade: (where it's going, change per frame, frames left)
+    ramp: Option<(f32, f32, u32)>,
+    // How long the last duck took, so unduck can take as long coming back
+    frames: u32,
+}
+
+impl Duck {
+    fn new() -> Self {
+        Self {
+            level: 1.0,
+            ramp: None,
+            frames: 0,
+        }
+    }
+    // Start fading toward target (kept between silent and full), remembering how long it takes
+    fn down(&mut self, target: f32, frames: u32) {
+        self.frames = frames;
+        self.start(target.clamp(0.0, 1.0), frames);
+    }
+    // Start fading back to full, as slowly as we went down
+    fn up(&mut self) {
+        self.start(1.0, self.frames);
+    }
+    fn start(&mut self, target: f32, frames: u32) {
+        if frames == 0 {
+            self.level = target;
+            self.ramp = None;
+        } else {
+            let step = (target - self.level) / frames as f32;
+            self.ramp = Some((target, step, frames));
+        }
+    }
+    // Move one frame along the fade, saying whether the level changed
+    fn step(&mut self) -> bool {
+        match self.ramp {
+            // Land exactly on the target at the end instead of wherever rounding leaves us
+            Some((target, _, left)) if left <= 1 => {
+                self.level = target;
+                self.ramp = None;
+                true
+            }
+            Some((target, step, left)) => {
+                self.level += step;
+                self.ramp = Some((target, step, left - 1));
+                true
+            }
+            None => false,
+        }
+    }
+}
+
+// The bookkeeping side of Audio: up to limit things, oldest first, each with a Voice to find it by
+struct Voices<T> {
+    limit: usize,
+    next_voice: usize,
+    list: VecDeque<(Voice, T)>,
+}
+
+impl<T> Voices<T> {
+    fn new(limit: usize) -> Self {
+        Self {
+            limit: limit.max(1),
+            next_voice: 0,
+            list: VecDeque::new(),
+        }
+    }
+    fn len(&self) -> usize {
+        self.list.len()
+    }
+    fn iter(&self) -> impl Iterator<Item = &T> {
+        self.list.iter().map(|(_, t)| t)
+    }
+    // Keep track of item, handing back whichever oldest ones had to go to stay under the limit
+    fn add(&mut self, item: T) -> (Voice, Vec<T>) {
+        let mut evicted = vec![];
+        while self.list.len() >= self.limit {
+            if let Some((_, old)) = self.list.pop_front() {
+                evicted.push(old);
+            }
+        }
+        let voice = Voice(self.next_voice);
+        self.next_voice += 1;
+        self.list.push_back((voice, item));
+        (voice, evicted)
+    }
+    fn remove(&mut self, voice: Voice) -> Option<T> {
+        let i = self.list.iter().position(|(v, _)| *v == voice)?;
+        self.list.remove(i).map(|(_, t)| t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn going_over_the_limit_cuts_off_the_oldest() {
+        let mut voices = Voices::new(3);
+        let (a, _) = voices.add("a");
+        let (b, _) = voices.add("b");
+        voices.add("c");
+        assert_ne!(a, b);
+        for (i, name) in ["d", "e"].iter().enumerate() {
+            let (_, evicted) = voices.add(*name);
+            assert_eq!(evicted, vec![["a", "b"][i]]);
+            assert_eq!(voices.len(), 3);
+        }
+        assert_eq!(
+            voices.iter().copied().collect::<Vec<_>>(),
+            vec!["c", "d", "e"]
+        );
+        // The cut-off ones are gone for good, so stopping them again does nothing
+        assert_eq!(voices.remove(a), None);
+    }
+    #[test]
+    fn stopping_a_voice_frees_its_slot() {
+        let mut voices = Voices::new(2);
+        let (a, _) = voices.add("a");
+        voices.add("b");
+        assert_eq!(voices.remove(a), Some("a"));
+        let (_, evicted) = voices.add("c");
+        assert!(evicted.is_empty());
+        assert_eq!(voices.iter().copied().collect::<Vec<_>>(), vec!["b", "c"]);
+    }
+    #[test]
+    fn ducking_ramps_down_and_unducking_comes_back() {
+        let mut duck = Duck::new();
+        duck.down(0.25, 10);
+        for _ in 0..9 {
+            assert!(duck.step());
+            assert!(duck.level > 0.25 && duck.level < 1.0);
+        }
+        duck.step();
+        assert_eq!(duck.level, 0.25);
+        // Nothing left to fade
+        assert!(!duck.step());
+        // Coming back takes just as long
+        duck.up();
+        for _ in 0..9 {
+            duck.step();
+            assert!(duck.level < 1.0);
+        }
+        duck.step();
+        assert_eq!(duck.level, 1.0);
+    }
+    #[test]
+    fn ducking_stays_between_silent_and_full() {
+        let mut duck = Duck::new();
+        duck.down(-0.5, 0);
+        assert_eq!(duck.level, 0.0);
+        duck.down(3.0, 4);
+        for _ in 0..4 {
+            duck.step();
+        }
+        assert_eq!(duck.level, 1.0);
+        // An instant duck comes back instantly too
+        duck.down(0.5, 0);
+        duck.up();
+        assert_eq!(duck.level, 1.0);
+    }
+}
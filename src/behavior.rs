@@ -0,0 +1,185 @@
+use crate::rng::Rng;
+use crate::tiles::Tilemap;
+use crate::types::Vec2i;
+
+const CARDINALS: [Vec2i; 4] = [Vec2i(0, -1), Vec2i(0, 1), Vec2i(-1, 0), Vec2i(1, 0)];
+
+// Keep whatever speed we were going, but standing still gets a speed of 1
+fn speed_of(vel: Vec2i) -> i32 {
+    vel.0.abs().max(vel.1.abs()).max(1)
+}
+
+/// Aimless enemy movement: keep going the way we're going, except that with probability
+/// change_chance (0.0 to 1.0) we pick a new cardinal direction at the same speed.
+/// A stopped enemy always picks a direction.
+#[allow(dead_code)]
+pub fn wander(rng: &mut Rng, current_vel: Vec2i, change_chance: f32) -> Vec2i {
+    if current_vel != Vec2i(0, 0) && rng.next_f32() >= change_chance {
+        return current_vel;
+    }
+    let speed = speed_of(current_vel);
+    let Vec2i(dx, dy) = CARDINALS[rng.below(CARDINALS.len())];
+    Vec2i(dx * speed, dy * speed)
+}
+
+/// Like wander, but only picks directions whose neighboring tile (from position) is
+/// on the map and not solid. If we're headed into a wall we always turn.
+/// Returns Vec2i(0, 0) if we're boxed in on all four sides.
+#[allow(dead_code)]
+pub fn wander_on(
+    rng: &mut Rng,
+    map: &Tilemap,
+    position: Vec2i,
+    current_vel: Vec2i,
+    change_chance: f32,
+) -> Vec2i {
+    let (tw, th) = map.tile_size();
+    let open = |Vec2i(dx, dy): Vec2i| {
+        let next = Vec2i(
+            position.0 + dx.signum() * tw as i32,
+            position.1 + dy.signum() * th as i32,
+        );
+        map.contains(next) && !map.tile_at(next).solid
+    };
+    let blocked = current_vel == Vec2i(0, 0) || !open(current_vel);
+    if !blocked && rng.next_f32() >= change_chance {
+        return current_vel;
+    }
+    let choices: Vec<Vec2i> = CARDINALS.iter().copied().filter(|&d| open(d)).collect();
+    if choices.is_empty() {
+        return Vec2i(0, 0);
+    }
+    let speed = speed_of(current_vel);
+    let Vec2i(dx, dy) = choices[rng.below(choices.len())];
+    Vec2i(dx * speed, dy * speed)
+}
+
+/// A velocity of about speed pixels per frame from from toward to.
+/// Close enough to get there this frame, it's exactly the rest of the way,
+/// so chasers land on the target instead of jittering around it.
+#[allow(dead_code)]
+pub fn chase(from: Vec2i, to: Vec2i, speed: i32) -> Vec2i {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    let dist = ((dx as f64).powi(2) + (dy as f64).powi(2)).sqrt();
+    if dist <= speed as f64 {
+        return Vec2i(dx, dy);
+    }
+    let scale = speed as f64 / dist;
+    Vec2i(
+        (dx as f64 * scale).round() as i32,
+        (dy as f64 * scale).round() as i32,
+    )
+}
+
+/// Like chase, but only ever along one axis (whichever is farther off), for grid games
+/// and enemies that shouldn't move diagonally.
+#[allow(dead_code)]
+pub fn chase_cardinal(from: Vec2i, to: Vec2i, speed: i32) -> Vec2i {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    if dx.abs() >= dy.abs() {
+        Vec2i(dx.signum() * dx.abs().min(speed), 0)
+    } else {
+        Vec2i(0, dy.signum() * dy.abs().min(speed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::Texture;
+    use crate::tiles::{Tile, Tileset};
+    use std::rc::Rc;
+
+    fn wander_from(seed: u64) -> Vec<Vec2i> {
+        let mut rng = Rng::new(seed);
+        let mut vel = Vec2i(0, 0);
+        (0..20)
+            .map(|_| {
+                vel = wander(&mut rng, vel, 0.3);
+                vel
+            })
+            .collect()
+    }
+
+    #[test]
+    fn wandering_repeats_with_the_same_seed() {
+        let path = wander_from(1234);
+        assert_eq!(path, wander_from(1234));
+        // Always a cardinal direction at speed 1, changing now and then but not every frame
+        assert!(path.iter().all(|v| CARDINALS.contains(v)));
+        let changes = path.windows(2).filter(|w| w[0] != w[1]).count();
+        assert!(0 < changes && changes < 19, "{} changes", changes);
+    }
+    #[test]
+    fn wander_on_never_heads_into_walls() {
+        let tex = Rc::new(Texture::placeholder(16, 8));
+        let tile = |solid| Tile {
+            solid,
+            hitbox: None,
+        };
+        let set = Rc::new(Tileset::new(vec![tile(false), tile(true)], &tex));
+        // A corridor running left and right, walled above and below
+        #[rustfmt::skip]
+        let cells = vec![
+            1, 1, 1,
+            0, 0, 0,
+            1, 1, 1,
+        ];
+        let map = Tilemap::new(Vec2i(0, 0), (3, 3), (8, 8), &set, cells);
+        let mut rng = Rng::new(99);
+        // Headed up into the wall, so we have to turn along the corridor
+        for _ in 0..20 {
+            let vel = wander_on(&mut rng, &map, Vec2i(8, 8), Vec2i(0, -2), 0.0);
+            assert!(vel == Vec2i(2, 0) || vel == Vec2i(-2, 0), "Got {:?}", vel);
+        }
+        // Boxed in on every side
+        let boxed = Tilemap::new(
+            Vec2i(0, 0),
+            (3, 3),
+            (8, 8),
+            &set,
+            vec![1, 1, 1, 1, 0, 1, 1, 1, 1],
+        );
+        assert_eq!(
+            wander_on(&mut rng, &boxed, Vec2i(8, 8), Vec2i(1, 0), 1.0),
+            Vec2i(0, 0)
+        );
+    }
+    #[test]
+    fn chasing_heads_toward_the_target_at_speed() {
+        let from = Vec2i(10, 10);
+        let targets = [
+            Vec2i(100, 10),
+            Vec2i(10, -50),
+            Vec2i(60, 60),
+            Vec2i(-30, 40),
+            Vec2i(13, 90),
+        ];
+        for &to in &targets {
+            let Vec2i(vx, vy) = chase(from, to, 4);
+            let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+            // Same way on each axis, and never backing off
+            assert!(
+                vx * dx >= 0 && vy * dy >= 0,
+                "{:?} toward {:?}",
+                (vx, vy),
+                to
+            );
+            let speed = ((vx * vx + vy * vy) as f64).sqrt();
+            // Rounding each axis can be off by up to half a pixel each way
+            assert!((speed - 4.0).abs() <= 0.75, "{} toward {:?}", speed, to);
+        }
+        assert_eq!(chase(from, Vec2i(100, 10), 4), Vec2i(4, 0));
+        // Nearly there goes exactly there, and there means stop
+        assert_eq!(chase(from, Vec2i(12, 11), 4), Vec2i(2, 1));
+        assert_eq!(chase(from, from, 4), Vec2i(0, 0));
+    }
+    #[test]
+    fn cardinal_chasing_takes_the_longer_axis() {
+        let from = Vec2i(0, 0);
+        assert_eq!(chase_cardinal(from, Vec2i(10, 3), 2), Vec2i(2, 0));
+        assert_eq!(chase_cardinal(from, Vec2i(-1, -30), 2), Vec2i(0, -2));
+        assert_eq!(chase_cardinal(from, Vec2i(1, 0), 2), Vec2i(1, 0));
+        assert_eq!(chase_cardinal(from, from, 2), Vec2i(0, 0));
+    }
+}
@@ -0,0 +1,98 @@
+use crate::screen::{self, Screen};
+use crate::types::{Rect, Vec2f, Vec2i};
+
+/// Where the view is scrolled to, kept between frames so it can move smoothly.
+/// position is the top-left corner of the view in world space, same as Screen::position.
+pub struct Camera {
+    pub position: Vec2i,
+    // Where we really are, fractions and all, so slow catch-ups still creep along
+    // instead of rounding to no movement every frame
+    exact: Vec2f,
+    // Size of the view in pixels
+    view: (usize, usize),
+    // The camera never shows anything outside of these
+    world_bounds: Rect,
+}
+
+#[allow(dead_code)]
+impl Camera {
+    pub fn new(position: Vec2i, view: (usize, usize), world_bounds: Rect) -> Self {
+        let position = screen::clamp_scroll(position, view, world_bounds);
+        Self {
+            position,
+            exact: Vec2f(position.0 as f32, position.1 as f32),
+            view,
+            world_bounds,
+        }
+    }
+    /// Move part of the way to target (a top-left corner; to follow something, subtract
+    /// half the view size from its center). Call once a frame. smoothing is the fraction
+    /// of the remaining distance covered each call: 1.0 snaps right there, smaller trails
+    /// behind more. Once within half a pixel it lands exactly on target and stays put.
+    /// Targets past the edge of the world are pulled back inside it first.
+    pub fn lerp_to(&mut self, target: Vec2i, smoothing: f32) {
+        let t = smoothing.clamp(0.0, 1.0);
+        // Both ends are in bounds, so everything in between is too
+        let Vec2i(tx, ty) = screen::clamp_scroll(target, self.view, self.world_bounds);
+        let step = |from: f32, to: i32| {
+            let to = to as f32;
+            let next = from + (to - from) * t;
+            if (to - next).abs() < 0.5 {
+                to
+            } else {
+                next
+            }
+        };
+        self.exact = Vec2f(step(self.exact.0, tx), step(self.exact.1, ty));
+        self.position = Vec2i(self.exact.0.round() as i32, self.exact.1.round() as i32);
+    }
+    /// Scroll screen to where the camera is
+    pub fn apply(&self, screen: &mut Screen) {
+        screen.position = self.position;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world() -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            w: 1000,
+            h: 1000,
+        }
+    }
+
+    #[test]
+    fn lerping_settles_exactly_on_the_target() {
+        let mut camera = Camera::new(Vec2i(0, 0), (100, 100), world());
+        let target = Vec2i(300, 150);
+        let mut last_gap = i32::MAX;
+        for _ in 0..100 {
+            camera.lerp_to(target, 0.1);
+            // Always closing in, never overshooting
+            let gap = (target.0 - camera.position.0) + (target.1 - camera.position.1);
+            assert!((0..=last_gap).contains(&gap));
+            last_gap = gap;
+        }
+        assert_eq!(camera.position, target);
+        // And it stays put once it's there
+        camera.lerp_to(target, 0.1);
+        assert_eq!(camera.position, target);
+    }
+    #[test]
+    fn full_smoothing_snaps_and_the_world_edge_holds() {
+        let mut camera = Camera::new(Vec2i(0, 0), (100, 100), world());
+        camera.lerp_to(Vec2i(250, 40), 1.0);
+        assert_eq!(camera.position, Vec2i(250, 40));
+        // Past the far corner only goes as far as showing the corner
+        camera.lerp_to(Vec2i(5000, -70), 1.0);
+        assert_eq!(camera.position, Vec2i(900, 0));
+        let mut fb = vec![0_u8; 4];
+        let mut screen = Screen::wrap(&mut fb, 1, 1, 4, Vec2i(0, 0));
+        camera.apply(&mut screen);
+        assert_eq!(screen.position, Vec2i(900, 0));
+    }
+}
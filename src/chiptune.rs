@@ -0,0 +1,263 @@
+use crate::rng::Rng;
+use rodio::Source;
+use std::io;
+use std::time::Duration;
+
+const SAMPLE_RATE: u32 = 44_100;
+// How loud each note is, low enough that a few at once don't clip
+const VOLUME: f32 = 0.2;
+
+/// The shape of a channel's sound, NES style
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Wave {
+    Square,
+    Triangle,
+    Noise,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NoteEvent {
+    /// When the note starts, in ticks from the beginning of the tune
+    pub start: u32,
+    pub wave: Wave,
+    /// MIDI note number (60 is middle C, 69 is A4 = 440Hz); noise ignores it
+    pub pitch: u8,
+    /// How many ticks the note lasts
+    pub length: u32,
+}
+
+/// A tiny tracker-style tune: a list of notes on a tick grid, synthesized on the fly.
+///
+/// The text format is one thing per line, with # starting a comment line:
+/// ```text
+/// # Ticks per second, 8 if not given
+/// tempo 8
+/// # Start tick, wave, note, length in ticks
+/// 0 square C4 2
+/// 0 triangle C3 4
+/// 2 square E4 2
+/// # Noise has no pitch
+/// 4 noise - 1
+/// ```
+/// Notes are a letter, an optional # or b, and an octave.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Chiptune {
+    /// Ticks per second
+    pub tempo: u32,
+    /// Sorted by start tick
+    pub notes: Vec<NoteEvent>,
+}
+
+#[allow(dead_code)]
+impl Chiptune {
+    pub fn parse(text: &str) -> io::Result<Self> {
+        let bad = |line: usize, msg: &str| {
+            io::Error::new(io::ErrorKind::InvalidData, format!("line {}: {}", line + 1, msg))
+        };
+        let mut tempo = 8;
+        let mut notes = vec![];
+        for (i, line) in text.lines().enumerate() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words.as_slice() {
+                [] => {}
+                [first, ..] if first.starts_with('#') => {}
+                ["tempo", t] => {
+                    tempo = t.parse().map_err(|_| bad(i, "tempo isn't a number"))?;
+                    if tempo == 0 {
+                        return Err(bad(i, "tempo can't be 0"));
+                    }
+                }
+                [start, wave, note, length] => {
+                    let start = start.parse().map_err(|_| bad(i, "start isn't a number"))?;
+                    let length = length.parse().map_err(|_| bad(i, "length isn't a number"))?;
+                    let wave = match *wave {
+                        "square" => Wave::Square,
+                        "triangle" => Wave::Triangle,
+                        "noise" => Wave::Noise,
+                        _ => return Err(bad(i, "wave should be square, triangle, or noise")),
+                    };
+                    let pitch = match (wave, *note) {
+                        (Wave::Noise, "-") => 0,
+                        _ => parse_pitch(note).ok_or_else(|| bad(i, "bad note name"))?,
+                    };
+                    notes.push(NoteEvent {
+                        start,
+                        wave,
+                        pitch,
+                        length,
+                    });
+                }
+                _ => return Err(bad(i, "expected `tempo N` or `start wave note length`")),
+            }
+        }
+        // Stable, so notes starting together keep their file order
+        notes.sort_by_key(|n| n.start);
+        Ok(Self { tempo, notes })
+    }
+    /// How many ticks until the last note ends
+    pub fn length(&self) -> u32 {
+        self.notes.iter().map(|n| n.start + n.length).max().unwrap_or(0)
+    }
+    /// Something to hand to Audio::play_sound
+    pub fn source(&self) -> ChiptuneSource {
+        ChiptuneSource {
+            total: self.length() as u64 * SAMPLE_RATE as u64 / self.tempo as u64,
+            notes: self.notes.clone(),
+            tempo: self.tempo,
+            sample: 0,
+            noise: Rng::new(1),
+        }
+    }
+}
+
+// "C4", "F#3", "Bb5" into a MIDI note number
+fn parse_pitch(name: &str) -> Option<u8> {
+    let mut chars = name.chars();
+    let base = match chars.next()? {
+        'C' => 0,
+        'D' => 2,
+        'E' => 4,
+        'F' => 5,
+        'G' => 7,
+        'A' => 9,
+        'B' => 11,
+        _ => return None,
+    };
+    let rest = chars.as_str();
+    let (accidental, octave) = if let Some(o) = rest.strip_prefix('#') {
+        (1, o)
+    } else if let Some(o) = rest.strip_prefix('b') {
+        (-1, o)
+    } else {
+        (0, rest)
+    };
+    let octave: i32 = octave.parse().ok()?;
+    let midi = (octave + 1) * 12 + base + accidental;
+    if (0..=127).contains(&midi) {
+        Some(midi as u8)
+    } else {
+        None
+    }
+}
+
+/// Synthesizes a Chiptune as mono f32 samples
+pub struct ChiptuneSource {
+    notes: Vec<NoteEvent>,
+    tempo: u32,
+    // Which sample we're on, and how many there are
+    sample: u64,
+    total: u64,
+    noise: Rng,
+}
+
+impl Iterator for ChiptuneSource {
+    type Item = f32;
+    fn next(&mut self) -> Option<f32> {
+        if self.sample >= self.total {
+            return None;
+        }
+        let (rate, tempo) = (SAMPLE_RATE as u64, self.tempo as u64);
+        let tick = (self.sample * tempo / rate) as u32;
+        let noise = self.noise.next_f32() * 2.0 - 1.0;
+        let mut out = 0.0;
+        for n in self.notes.iter().take_while(|n| n.start <= tick) {
+            if tick >= n.start + n.length {
+                continue;
+            }
+            // Count from the note's own start so each note begins at the top of its wave
+            let since = self.sample - n.start as u64 * rate / tempo;
+            let freq = 440.0 * 2f64.powf((n.pitch as f64 - 69.0) / 12.0);
+            let phase = (freq * since as f64 / rate as f64).fract() as f32;
+            out += VOLUME
+                * match n.wave {
+                    Wave::Square if phase < 0.5 => 1.0,
+                    Wave::Square => -1.0,
+                    Wave::Triangle => 4.0 * (phase - 0.5).abs() - 1.0,
+                    Wave::Noise => noise,
+                };
+        }
+        self.sample += 1;
+        Some(out)
+    }
+}
+
+impl Source for ChiptuneSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        1
+    }
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        Some(Duration::from_secs_f64(self.total as f64 / SAMPLE_RATE as f64))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TUNE: &str = "
+# A little arpeggio
+tempo 4
+2 square E4 2
+0 square C4 2
+0 triangle C3 4
+4 noise - 1
+";
+
+    #[test]
+    fn short_tune_parses_into_note_events() {
+        let tune = Chiptune::parse(TUNE).unwrap();
+        assert_eq!(tune.tempo, 4);
+        let note = |start, wave, pitch, length| NoteEvent {
+            start,
+            wave,
+            pitch,
+            length,
+        };
+        assert_eq!(
+            tune.notes,
+            vec![
+                note(0, Wave::Square, 60, 2),
+                note(0, Wave::Triangle, 48, 4),
+                note(2, Wave::Square, 64, 2),
+                note(4, Wave::Noise, 0, 1),
+            ]
+        );
+        assert_eq!(tune.length(), 5);
+    }
+    #[test]
+    fn note_names_become_midi_numbers() {
+        assert_eq!(parse_pitch("A4"), Some(69));
+        assert_eq!(parse_pitch("F#3"), Some(54));
+        assert_eq!(parse_pitch("Bb5"), Some(82));
+        assert_eq!(parse_pitch("H2"), None);
+        assert_eq!(parse_pitch("C"), None);
+        assert_eq!(parse_pitch("G9"), Some(127));
+        assert_eq!(parse_pitch("A9"), None);
+    }
+    #[test]
+    fn mistakes_say_which_line() {
+        let err = Chiptune::parse("tempo 8\n0 sawtooth C4 1").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 2:"), "{}", err);
+        assert!(Chiptune::parse("tempo 0").is_err());
+        assert!(Chiptune::parse("0 square C4").is_err());
+    }
+    #[test]
+    fn source_plays_for_the_length_of_the_tune() {
+        let tune = Chiptune::parse("tempo 2\n0 square A4 1").unwrap();
+        let source = tune.source();
+        assert_eq!(source.total_duration(), Some(Duration::from_millis(500)));
+        let samples: Vec<f32> = source.collect();
+        assert_eq!(samples.len(), SAMPLE_RATE as usize / 2);
+        // A square wave starts high and swings between the two levels
+        assert_eq!(samples[0], VOLUME);
+        assert!(samples.iter().all(|&s| s == VOLUME || s == -VOLUME));
+        assert!(samples.contains(&-VOLUME));
+    }
+}
@@ -1,3 +1,4 @@
+use crate::types::{Vec2f, Vec2i};
 use pixels::{Pixels, SurfaceTexture};
 use std::time::Instant;
 use winit::dpi::PhysicalSize;
@@ -41,17 +42,112 @@ pub struct Mobile {
     pub vy: i32,
 }
 
+impl Mobile {
+    #[allow(dead_code)]
+    pub fn bounds(&self) -> Rect {
+        self.rect
+    }
+    // Same touching test as rect_touching, so edges count
+    #[allow(dead_code)]
+    pub fn overlaps(&self, other: Rect) -> bool {
+        rect_touching(self.rect, other)
+    }
+    #[allow(dead_code)]
+    pub fn accelerate(&mut self, dvx: i32, dvy: i32) {
+        self.vx += dvx;
+        self.vy += dvy;
+    }
+    // Respond to hitting a surface facing along normal (pointing out of the surface,
+    // like Vec2i(0, -1) for a floor). restitution is bounciness: 0.0 kills the velocity
+    // into the surface (the slide-along-walls behavior), 1.0 reflects it completely,
+    // and in between bounces back weaker. Motion along the surface is kept either way.
+    pub fn bounce(&mut self, Vec2i(nx, ny): Vec2i, restitution: f32) {
+        let len = ((nx * nx + ny * ny) as f32).sqrt();
+        if len == 0.0 {
+            return;
+        }
+        let (nx, ny) = (nx as f32 / len, ny as f32 / len);
+        let into = self.vx as f32 * nx + self.vy as f32 * ny;
+        // Already moving away (or along it), nothing to push back on
+        if into >= 0.0 {
+            return;
+        }
+        let k = (1.0 + restitution.clamp(0.0, 1.0)) * into;
+        self.vx = (self.vx as f32 - k * nx).round() as i32;
+        self.vy = (self.vy as f32 - k * ny).round() as i32;
+    }
+    // Limit the overall speed (the length of the velocity, not each axis separately)
+    // to max, keeping the direction. Components round toward zero so we never end up over.
+    #[allow(dead_code)]
+    pub fn clamp_speed(&mut self, max: i32) {
+        let max = max.max(0);
+        let speed_sq = self.vx as i64 * self.vx as i64 + self.vy as i64 * self.vy as i64;
+        if speed_sq <= max as i64 * max as i64 {
+            return;
+        }
+        let scale = max as f64 / (speed_sq as f64).sqrt();
+        self.vx = (self.vx as f64 * scale).trunc() as i32;
+        self.vy = (self.vy as f64 * scale).trunc() as i32;
+    }
+}
+
+// A Mobile that can move less than a pixel per frame.
+// Fractional motion piles up in remainder and the rect only moves once it adds up to a whole pixel,
+// so a speed of 0.5 moves one pixel every other frame.
+pub struct SubPixelBody {
+    pub mobile: Mobile,
+    pub vel: Vec2f,
+    remainder: Vec2f,
+}
+
+#[allow(dead_code)]
+impl SubPixelBody {
+    pub fn new(mobile: Mobile, vel: Vec2f) -> Self {
+        Self {
+            mobile,
+            vel,
+            remainder: Vec2f(0.0, 0.0),
+        }
+    }
+    // Move by one frame's worth of vel
+    pub fn update(&mut self) {
+        let Vec2i(dx, dy) = subpixel_step(&mut self.remainder, self.vel);
+        self.mobile.rect.x += dx;
+        self.mobile.rect.y += dy;
+    }
+}
+
+// Add a frame of vel to remainder and take out the whole pixels to move by now;
+// the leftover fraction waits in remainder for next frame.
+pub fn subpixel_step(remainder: &mut Vec2f, vel: Vec2f) -> Vec2i {
+    let x = remainder.0 + vel.0;
+    let y = remainder.1 + vel.1;
+    let (dx, dy) = (x.trunc(), y.trunc());
+    *remainder = Vec2f(x - dx, y - dy);
+    Vec2i(dx as i32, dy as i32)
+}
+
+// A velocity of length speed pointing along dir (like Vec2i(1, -1) for up-right),
+// so going diagonally isn't ~1.41x faster than going straight. No direction means no velocity.
+pub fn directional_velocity(Vec2i(dx, dy): Vec2i, speed: f32) -> Vec2f {
+    let len = ((dx * dx + dy * dy) as f32).sqrt();
+    if len == 0.0 {
+        return Vec2f(0.0, 0.0);
+    }
+    Vec2f(dx as f32 / len * speed, dy as f32 / len * speed)
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
-enum ColliderID {
+pub enum ColliderID {
     Static(usize),
     Dynamic(usize),
 }
 
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
-struct Contact {
-    a: ColliderID,
-    b: ColliderID,
-    mtv: (i32, i32),
+pub struct Contact {
+    pub a: ColliderID,
+    pub b: ColliderID,
+    pub mtv: (i32, i32),
 }
 
 // pixels gives us an rgba8888 framebuffer
@@ -173,7 +269,381 @@ fn rect_displacement(r1: Rect, r2: Rect) -> Option<(i32, i32)> {
     }
 }
 
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TriggerEvent {
+    // Index into the triggers slice
+    Entered(usize),
+    Exited(usize),
+}
+
+// Turns "is the player in this zone" into "did the player just walk in or out of it",
+// so one-shot things (a sound, a message) fire once instead of every frame.
+pub struct TriggerTracker {
+    // Which triggers the player was touching last update
+    inside: Vec<bool>,
+}
+
+#[allow(dead_code)]
+impl TriggerTracker {
+    pub fn new() -> Self {
+        Self { inside: vec![] }
+    }
+    // Call once per frame with the same triggers in the same order each time.
+    // Entered events come before Exited ones, each in trigger order.
+    pub fn update(&mut self, player: Rect, triggers: &[Rect]) -> Vec<TriggerEvent> {
+        // A trigger we haven't seen before starts out not touched
+        self.inside.resize(triggers.len(), false);
+        let mut entered = vec![];
+        let mut exited = vec![];
+        for (i, (&t, was_inside)) in triggers.iter().zip(self.inside.iter_mut()).enumerate() {
+            let now_inside = rect_touching(player, t);
+            if now_inside && !*was_inside {
+                entered.push(TriggerEvent::Entered(i));
+            } else if !now_inside && *was_inside {
+                exited.push(TriggerEvent::Exited(i));
+            }
+            *was_inside = now_inside;
+        }
+        entered.extend(exited);
+        entered
+    }
+}
+
+// Move rect by (dx, dy), stopping flush against any wall in the way.
+// x moves and gets resolved first, then y, so moving diagonally into a wall slides along it.
+// Resting right up against a wall (sharing an edge) doesn't count as being in it.
+pub fn slide_against_walls(rect: Rect, step: Vec2i, walls: &[Wall]) -> Rect {
+    // A do-nothing callback compiles away, so this costs the same as not tracing at all
+    slide_traced(rect, step, walls, |_| {})
+}
+
+/// A wall a move ran into
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct WallHit {
+    /// Index into the walls slice
+    pub wall: usize,
+    /// Which way the wall pushed back, one of the four unit directions
+    pub normal: Vec2i,
+}
+
+/// Same as slide_against_walls, but also says which walls stopped the move
+pub fn slide_against_walls_traced(
+    rect: Rect,
+    step: Vec2i,
+    walls: &[Wall],
+) -> (Rect, Vec<WallHit>) {
+    let mut hits = vec![];
+    let r = slide_traced(rect, step, walls, |hit| hits.push(hit));
+    (r, hits)
+}
+
+fn slide_traced(
+    rect: Rect,
+    Vec2i(dx, dy): Vec2i,
+    walls: &[Wall],
+    mut on_hit: impl FnMut(WallHit),
+) -> Rect {
+    let overlaps = |r: Rect, w: Rect| {
+        r.x < w.x + w.w as i32
+            && w.x < r.x + r.w as i32
+            && r.y < w.y + w.h as i32
+            && w.y < r.y + r.h as i32
+    };
+    let hits = |r: Rect| -> Vec<(usize, Rect)> {
+        walls
+            .iter()
+            .map(|w| w.rect)
+            .enumerate()
+            .filter(|&(_, w)| overlaps(r, w))
+            .collect()
+    };
+    let mut r = rect;
+    r.x += dx;
+    if dx != 0 {
+        // Back off to the nearest wall edge on the side we came from. Nearest first, since
+        // backing off from it can take us out of the farther ones, and those didn't stop us.
+        let mut in_the_way = hits(r);
+        in_the_way.sort_by_key(|&(_, w)| if dx > 0 { w.x } else { -(w.x + w.w as i32) });
+        for (wall, w) in in_the_way {
+            if !overlaps(r, w) {
+                continue;
+            }
+            r.x = if dx > 0 {
+                w.x - r.w as i32
+            } else {
+                w.x + w.w as i32
+            };
+            on_hit(WallHit {
+                wall,
+                normal: Vec2i(-dx.signum(), 0),
+            });
+        }
+    }
+    r.y += dy;
+    if dy != 0 {
+        let mut in_the_way = hits(r);
+        in_the_way.sort_by_key(|&(_, w)| if dy > 0 { w.y } else { -(w.y + w.h as i32) });
+        for (wall, w) in in_the_way {
+            if !overlaps(r, w) {
+                continue;
+            }
+            r.y = if dy > 0 {
+                w.y - r.h as i32
+            } else {
+                w.y + w.h as i32
+            };
+            on_hit(WallHit {
+                wall,
+                normal: Vec2i(0, -dy.signum()),
+            });
+        }
+    }
+    r
+}
+
+/// One step of moving something against walls, for figuring out collision bugs after the fact
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CollisionRecord {
+    /// Which simulated frame it happened on
+    pub frame: usize,
+    /// Whatever the caller uses to tell movers apart (an index, say)
+    pub entity: usize,
+    /// Where it was before moving
+    pub start: Rect,
+    /// How far it tried to move
+    pub step: Vec2i,
+    pub contacts: Vec<WallHit>,
+    /// Where it ended up
+    pub resolved: Vec2i,
+}
+
+impl CollisionRecord {
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "frame": self.frame,
+            "entity": self.entity,
+            "start": [self.start.x, self.start.y, self.start.w, self.start.h],
+            "step": [self.step.0, self.step.1],
+            "contacts": self
+                .contacts
+                .iter()
+                .map(|c| serde_json::json!({ "wall": c.wall, "normal": [c.normal.0, c.normal.1] }))
+                .collect::<Vec<_>>(),
+            "resolved": [self.resolved.0, self.resolved.1],
+        })
+    }
+}
+
+// How many pushes resolved_position will try before settling for where it got to
+const MAX_RESOLVE_PASSES: usize = 16;
+
+/// Where rect would end up (its new top-left) after being pushed out of every wall it overlaps.
+/// Nothing gets moved; this is for trying out a move before committing to it.
+/// Each pass pushes out of the deepest overlap along that overlap's shallower axis,
+/// repeating until nothing overlaps, so corners between walls work out.
+/// Walls that only share an edge with rect don't count as overlapping here.
+#[allow(dead_code)]
+pub fn resolved_position(rect: Rect, walls: &[Wall]) -> Vec2i {
+    let mut r = rect;
+    for _ in 0..MAX_RESOLVE_PASSES {
+        // The push out of each overlapping wall, keeping the deepest (a depth of 0 is no overlap)
+        let mut deepest: (i32, (i32, i32)) = (0, (0, 0));
+        for w in walls {
+            let w = w.rect;
+            let x_overlap = (r.x + r.w as i32).min(w.x + w.w as i32) - r.x.max(w.x);
+            let y_overlap = (r.y + r.h as i32).min(w.y + w.h as i32) - r.y.max(w.y);
+            if x_overlap <= 0 || y_overlap <= 0 {
+                continue;
+            }
+            // Push away from the wall's middle, compared at double scale to stay in integers
+            let away = |rc: i32, wc: i32| if rc < wc { -1 } else { 1 };
+            let push = if x_overlap < y_overlap {
+                (away(2 * r.x + r.w as i32, 2 * w.x + w.w as i32) * x_overlap, 0)
+            } else {
+                (0, away(2 * r.y + r.h as i32, 2 * w.y + w.h as i32) * y_overlap)
+            };
+            let depth = x_overlap.min(y_overlap);
+            if depth > deepest.0 {
+                deepest = (depth, push);
+            }
+        }
+        let (depth, (dx, dy)) = deepest;
+        if depth == 0 {
+            break;
+        }
+        r.x += dx;
+        r.y += dy;
+    }
+    Vec2i(r.x, r.y)
+}
+
+/// A convex polygon, for obstacles that aren't axis-aligned boxes.
+/// Points go around the outside in order; either winding is fine.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Polygon {
+    pub points: Vec<Vec2i>,
+}
+
+impl Polygon {
+    /// A rect is just a four-point polygon
+    pub fn from_rect(r: Rect) -> Self {
+        let (x1, y1) = (r.x + r.w as i32, r.y + r.h as i32);
+        Self {
+            points: vec![Vec2i(r.x, r.y), Vec2i(x1, r.y), Vec2i(x1, y1), Vec2i(r.x, y1)],
+        }
+    }
+    fn center(&self) -> (f64, f64) {
+        let n = self.points.len() as f64;
+        let (sx, sy) = self
+            .points
+            .iter()
+            .fold((0.0, 0.0), |(sx, sy), p| (sx + p.0 as f64, sy + p.1 as f64));
+        (sx / n, sy / n)
+    }
+    // Smallest and largest shadow of the polygon on axis
+    fn project(&self, (ax, ay): (f64, f64)) -> (f64, f64) {
+        self.points
+            .iter()
+            .map(|p| p.0 as f64 * ax + p.1 as f64 * ay)
+            .fold((f64::MAX, f64::MIN), |(lo, hi), d| (lo.min(d), hi.max(d)))
+    }
+}
+
+/// Separating Axis Theorem test for two convex polygons.
+/// If they overlap, returns the minimum translation vector: the shortest move
+/// that pushes a out of b. Touching edges count as overlapping, like rect_touching.
+pub fn poly_overlap(a: &Polygon, b: &Polygon) -> Option<Vec2i> {
+    if a.points.is_empty() || b.points.is_empty() {
+        return None;
+    }
+    let mut depth = f64::MAX;
+    let mut normal = (0.0, 0.0);
+    // For convex shapes the only axes worth checking are the edge normals of each shape.
+    for poly in [a, b].iter() {
+        let n = poly.points.len();
+        for i in 0..n {
+            let p = poly.points[i];
+            let q = poly.points[(i + 1) % n];
+            let (ex, ey) = ((q.0 - p.0) as f64, (q.1 - p.1) as f64);
+            let len = (ex * ex + ey * ey).sqrt();
+            if len == 0.0 {
+                continue;
+            }
+            let axis = (-ey / len, ex / len);
+            let (amin, amax) = a.project(axis);
+            let (bmin, bmax) = b.project(axis);
+            let overlap = amax.min(bmax) - amin.max(bmin);
+            if overlap < 0.0 {
+                // Found a gap, so they can't be touching
+                return None;
+            }
+            if overlap < depth {
+                depth = overlap;
+                normal = axis;
+            }
+        }
+    }
+    // Make the normal point from b toward a so the MTV pushes a away
+    let (ac, bc) = (a.center(), b.center());
+    if (ac.0 - bc.0) * normal.0 + (ac.1 - bc.1) * normal.1 < 0.0 {
+        normal = (-normal.0, -normal.1);
+    }
+    Some(Vec2i(
+        (normal.0 * depth).round() as i32,
+        (normal.1 * depth).round() as i32,
+    ))
+}
+
+/// A round collider: everything within radius of center
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Circle {
+    pub center: Vec2i,
+    pub radius: u32,
+}
+
+/// Any of the collider shapes, for things that aren't all boxes
+/// (round enemies, sloped platforms) but still need to be checked against each other
+#[derive(Clone, PartialEq, Eq, Debug)]
+#[allow(dead_code)]
+pub enum Shape {
+    Rect(Rect),
+    Circle(Circle),
+    Poly(Polygon),
+}
+
+/// Do a and b touch? Picks the right test for the pair of shapes.
+/// Like rect_touching, just touching at the edge counts.
+#[allow(dead_code)]
+pub fn shapes_touching(a: &Shape, b: &Shape) -> bool {
+    match (a, b) {
+        (Shape::Rect(a), Shape::Rect(b)) => rect_touching(*a, *b),
+        (Shape::Circle(a), Shape::Circle(b)) => {
+            let (dx, dy) = (
+                (a.center.0 - b.center.0) as i64,
+                (a.center.1 - b.center.1) as i64,
+            );
+            let r = a.radius as i64 + b.radius as i64;
+            dx * dx + dy * dy <= r * r
+        }
+        (Shape::Rect(r), Shape::Circle(c)) | (Shape::Circle(c), Shape::Rect(r)) => {
+            rect_circle_touching(*r, *c)
+        }
+        (Shape::Poly(p), Shape::Poly(q)) => poly_overlap(p, q).is_some(),
+        (Shape::Poly(p), Shape::Rect(r)) | (Shape::Rect(r), Shape::Poly(p)) => {
+            poly_overlap(p, &Polygon::from_rect(*r)).is_some()
+        }
+        (Shape::Poly(p), Shape::Circle(c)) | (Shape::Circle(c), Shape::Poly(p)) => {
+            poly_circle_touching(p, *c)
+        }
+    }
+}
+
+// The closest point of r to the circle's center is within the radius
+fn rect_circle_touching(r: Rect, c: Circle) -> bool {
+    let Vec2i(cx, cy) = c.center;
+    let nx = cx.max(r.x).min(r.x + r.w as i32);
+    let ny = cy.max(r.y).min(r.y + r.h as i32);
+    let (dx, dy) = ((cx - nx) as i64, (cy - ny) as i64);
+    dx * dx + dy * dy <= c.radius as i64 * c.radius as i64
+}
+
+// Either the center is inside the polygon, or some edge comes within the radius of it
+fn poly_circle_touching(p: &Polygon, c: Circle) -> bool {
+    let n = p.points.len();
+    if n == 0 {
+        return false;
+    }
+    let (cx, cy) = (c.center.0 as f64, c.center.1 as f64);
+    let r = c.radius as f64;
+    // Inside a convex polygon means being on the same side of every edge
+    let mut sides = (false, false);
+    for i in 0..n {
+        let (a, b) = (p.points[i], p.points[(i + 1) % n]);
+        let (ax, ay, bx, by) = (a.0 as f64, a.1 as f64, b.0 as f64, b.1 as f64);
+        let cross = (bx - ax) * (cy - ay) - (by - ay) * (cx - ax);
+        if cross > 0.0 {
+            sides.0 = true;
+        } else if cross < 0.0 {
+            sides.1 = true;
+        }
+        // Distance from the center to the closest point on this edge
+        let (ex, ey) = (bx - ax, by - ay);
+        let len_sq = ex * ex + ey * ey;
+        let t = if len_sq == 0.0 {
+            0.0
+        } else {
+            (((cx - ax) * ex + (cy - ay) * ey) / len_sq).clamp(0.0, 1.0)
+        };
+        let (dx, dy) = (cx - (ax + t * ex), cy - (ay + t * ey));
+        if dx * dx + dy * dy <= r * r {
+            return true;
+        }
+    }
+    !(sides.0 && sides.1)
+}
+
 // Here we will be using push() on into, so it can't be a slice
+#[allow(dead_code)]
 fn gather_contacts(statics: &[Wall], dynamics: &[Mobile], into: &mut Vec<Contact>) {
     // collide mobiles against mobiles
     for (ai, a) in dynamics.iter().enumerate() {
@@ -201,28 +671,60 @@ fn gather_contacts(statics: &[Wall], dynamics: &[Mobile], into: &mut Vec<Contact
     }
 }
 
-fn restitute(statics: &[Wall], dynamics: &mut [Mobile], contacts: &mut [Contact]) {
+// Put the deepest overlaps first. A contact's depth is its smaller overlap,
+// since that's how far the body has to move to get out along the cheaper axis.
+// Fixing those first means the smaller ones are often already fixed by the time we get to them,
+// instead of a shallow contact shoving the body into some other wall.
+pub fn sort_contacts(contacts: &mut [Contact]) {
+    contacts.sort_by_key(|c| std::cmp::Reverse(c.mtv.0.min(c.mtv.1)));
+}
+
+// How far to move r out of wall along whichever axis overlaps less, or None if
+// they're only touching edges (or not even that).
+fn push_out(r: Rect, wall: Rect) -> Option<Vec2i> {
+    let (dx, dy) = rect_displacement(r, wall)?;
+    if dx == 0 || dy == 0 {
+        return None;
+    }
+    // Doubled centers, so there's no rounding
+    let (cx, cy) = (2 * r.x + r.w as i32, 2 * r.y + r.h as i32);
+    let (wx, wy) = (2 * wall.x + wall.w as i32, 2 * wall.y + wall.h as i32);
+    if dx < dy {
+        Some(Vec2i(if cx < wx { -dx } else { dx }, 0))
+    } else {
+        Some(Vec2i(0, if cy < wy { -dy } else { dy }))
+    }
+}
+
+// restitution is how bouncy the walls are, as in Mobile::bounce: 0.0 just stops each body
+// against the wall it hit, 1.0 sends it back the way it came.
+#[allow(dead_code)]
+fn restitute(
+    statics: &[Wall],
+    dynamics: &mut [Mobile],
+    contacts: &mut [Contact],
+    restitution: f32,
+) {
     // handle restitution of dynamics against dynamics and dynamics against statics wrt contacts.
     // You could instead make contacts `Vec<Contact>` if you think you might remove contacts.
     // You could also add an additional parameter, a slice or vec representing how far we've displaced each dynamic, to avoid allocations if you track a vec of how far things have been moved.
     // You might also want to pass in another &mut Vec<Contact> to be filled in with "real" touches that actually happened.
-    contacts.sort_unstable_by_key(|c| -(c.mtv.0 * c.mtv.0 + c.mtv.1 * c.mtv.1));
+    sort_contacts(contacts);
     for contact in contacts.iter() {
-        match contact {
-            Contact {
-                a: ColliderID::Dynamic(f),
-                b: ColliderID::Static(g),
-                mtv,
-            } => {
-                let f: usize = f.to_owned();
-                let g: usize = g.to_owned();
-
-                if rect_touching(dynamics[f].rect, statics[g].rect) {
-                    dynamics[f].rect.x = 170;
-                    dynamics[f].rect.y = 170;
-                }
+        if let Contact {
+            a: ColliderID::Dynamic(f),
+            b: ColliderID::Static(g),
+            ..
+        } = *contact
+        {
+            // An earlier, deeper contact may have already moved us clear of this wall,
+            // so measure again instead of trusting the mtv from before
+            if let Some(Vec2i(dx, dy)) = push_out(dynamics[f].rect, statics[g].rect) {
+                dynamics[f].rect.x += dx;
+                dynamics[f].rect.y += dy;
+                // The wall pushed us out along its face, so that's its normal
+                dynamics[f].bounce(Vec2i(dx.signum(), dy.signum()), restitution);
             }
-            _ => {}
         }
     }
     // Keep going!  Note that you can assume every contact has a dynamic object in .a.
@@ -231,3 +733,352 @@ fn restitute(statics: &[Wall], dynamics: &mut [Mobile], contacts: &mut [Contact]
 }
 
 fn main() {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tri(points: &[(i32, i32)]) -> Polygon {
+        Polygon {
+            points: points.iter().map(|&(x, y)| Vec2i(x, y)).collect(),
+        }
+    }
+
+    #[test]
+    fn overlapping_triangles_get_pushed_apart() {
+        let a = tri(&[(0, 0), (10, 0), (0, 10)]);
+        let b = tri(&[(4, 4), (14, 4), (4, 14)]);
+        let mtv = poly_overlap(&a, &b).expect("Triangles should overlap");
+        assert_ne!(mtv, Vec2i(0, 0));
+        // a moved by the MTV only touches b, and it moved away from b
+        let moved = tri(&[(mtv.0, mtv.1), (10 + mtv.0, mtv.1), (mtv.0, 10 + mtv.1)]);
+        let rest = poly_overlap(&moved, &b).unwrap_or(Vec2i(0, 0));
+        assert!(
+            rest.0.abs() <= 1 && rest.1.abs() <= 1,
+            "Still overlapping by {:?}",
+            rest
+        );
+        assert!(mtv.0 <= 0 && mtv.1 <= 0);
+    }
+    #[test]
+    fn disjoint_triangles_dont_overlap() {
+        let a = tri(&[(0, 0), (10, 0), (0, 10)]);
+        // Past a's hypotenuse, though their bounding boxes overlap
+        let b = tri(&[(10, 10), (6, 10), (10, 6)]);
+        assert_eq!(poly_overlap(&a, &b), None);
+        assert_eq!(poly_overlap(&b, &a), None);
+    }
+    #[test]
+    fn rects_work_as_polygons() {
+        let wedge = tri(&[(0, 0), (20, 0), (0, 20)]);
+        let near = Rect {
+            x: 2,
+            y: 2,
+            w: 4,
+            h: 4,
+        };
+        let far = Rect {
+            x: 15,
+            y: 15,
+            w: 4,
+            h: 4,
+        };
+        assert!(poly_overlap(&wedge, &Polygon::from_rect(near)).is_some());
+        assert_eq!(poly_overlap(&wedge, &Polygon::from_rect(far)), None);
+    }
+    fn wall(x: i32, y: i32, w: u16, h: u16) -> Wall {
+        Wall {
+            rect: Rect { x, y, w, h },
+        }
+    }
+    #[test]
+    fn deepest_contacts_resolve_first_and_leave_no_overlap() {
+        let body = Mobile {
+            rect: Rect {
+                x: 10,
+                y: 10,
+                w: 20,
+                h: 20,
+            },
+            vx: 0,
+            vy: 0,
+        };
+        let walls = [
+            // Barely clips the bottom left corner; fixed for free by the floor
+            wall(12, 29, 4, 11),
+            // Floor, 5 deep
+            wall(0, 25, 60, 20),
+            // Right wall, 3 deep
+            wall(27, 0, 20, 60),
+        ];
+        let mut dynamics = [body];
+        let mut contacts = vec![];
+        gather_contacts(&walls, &dynamics, &mut contacts);
+        sort_contacts(&mut contacts);
+        let depths: Vec<i32> = contacts.iter().map(|c| c.mtv.0.min(c.mtv.1)).collect();
+        assert_eq!(depths, vec![5, 3, 1]);
+        let order: Vec<ColliderID> = contacts.iter().map(|c| c.b).collect();
+        let expected = [1, 2, 0]
+            .iter()
+            .map(|&i| ColliderID::Static(i))
+            .collect::<Vec<_>>();
+        assert_eq!(order, expected);
+
+        restitute(&walls, &mut dynamics, &mut contacts, 0.0);
+        // Pushed up out of the floor and left out of the wall, not teleported anywhere
+        assert_eq!(
+            dynamics[0].rect,
+            Rect {
+                x: 7,
+                y: 5,
+                w: 20,
+                h: 20
+            }
+        );
+        for w in walls.iter() {
+            let (dx, dy) = rect_displacement(dynamics[0].rect, w.rect).unwrap_or((0, 0));
+            assert!(
+                dx == 0 || dy == 0,
+                "Still {}x{} inside {:?}",
+                dx,
+                dy,
+                w.rect
+            );
+        }
+    }
+    fn moving(vx: i32, vy: i32) -> Mobile {
+        Mobile {
+            rect: Rect {
+                x: 0,
+                y: 0,
+                w: 4,
+                h: 4,
+            },
+            vx,
+            vy,
+        }
+    }
+    #[test]
+    fn clamp_speed_limits_the_whole_velocity() {
+        let mut m = moving(0, 0);
+        m.accelerate(30, 20);
+        m.accelerate(0, 20);
+        assert_eq!((m.vx, m.vy), (30, 40));
+        // Speed 50 down to 10, same direction
+        m.clamp_speed(10);
+        assert_eq!((m.vx, m.vy), (6, 8));
+        // A diagonal that's only over when both axes count
+        let mut m = moving(-8, 8);
+        m.clamp_speed(10);
+        assert!(m.vx * m.vx + m.vy * m.vy <= 100);
+        assert_eq!(m.vx, -m.vy);
+    }
+    #[test]
+    fn clamp_speed_leaves_slow_things_alone() {
+        let mut m = moving(3, -4);
+        m.clamp_speed(5);
+        assert_eq!((m.vx, m.vy), (3, -4));
+        m.clamp_speed(100);
+        assert_eq!((m.vx, m.vy), (3, -4));
+    }
+    #[test]
+    fn half_a_pixel_a_frame_moves_every_other_tick() {
+        let mut body = SubPixelBody::new(moving(0, 0), Vec2f(0.5, -0.25));
+        let mut xs = vec![];
+        let mut ys = vec![];
+        for _ in 0..8 {
+            body.update();
+            xs.push(body.mobile.rect.x);
+            ys.push(body.mobile.rect.y);
+        }
+        assert_eq!(xs, vec![0, 1, 1, 2, 2, 3, 3, 4]);
+        // Slower still, and backwards
+        assert_eq!(ys, vec![0, 0, 0, -1, -1, -1, -1, -2]);
+    }
+    #[test]
+    fn rect_in_a_corner_resolves_out_of_both_walls() {
+        // A floor along y = 20 and a wall along x = 20 meeting in an L
+        let walls = [wall(0, 20, 40, 8), wall(20, 0, 8, 28)];
+        // 8x8, sunk 3 into the floor and 2 into the wall
+        let r = Rect {
+            x: 14,
+            y: 15,
+            w: 8,
+            h: 8,
+        };
+        assert_eq!(resolved_position(r, &walls), Vec2i(12, 12));
+        // Already free rects stay put, even touching the walls
+        let free = Rect { x: 12, y: 12, ..r };
+        assert_eq!(resolved_position(free, &walls), Vec2i(12, 12));
+    }
+    #[test]
+    fn diagonals_cover_the_same_distance_as_straight_lines() {
+        // Where 60 frames of holding dir at 1.5 pixels a frame gets you
+        let travel = |dir: Vec2i| {
+            let vel = directional_velocity(dir, 1.5);
+            let mut remainder = Vec2f(0.0, 0.0);
+            let mut pos = Vec2i(0, 0);
+            for _ in 0..60 {
+                let Vec2i(dx, dy) = subpixel_step(&mut remainder, vel);
+                pos = Vec2i(pos.0 + dx, pos.1 + dy);
+            }
+            pos
+        };
+        assert_eq!(travel(Vec2i(1, 0)), Vec2i(90, 0));
+        assert_eq!(travel(Vec2i(0, -1)), Vec2i(0, -90));
+        for &dir in &[Vec2i(1, 1), Vec2i(-1, 1), Vec2i(1, -1), Vec2i(-1, -1)] {
+            let Vec2i(x, y) = travel(dir);
+            assert_eq!((x.signum(), y.signum()), (dir.0, dir.1));
+            let dist = ((x * x + y * y) as f32).sqrt();
+            // Each axis can be at most a pixel short
+            assert!((dist - 90.0).abs() <= 1.5, "{:?} went {}", dir, dist);
+        }
+        assert_eq!(travel(Vec2i(0, 0)), Vec2i(0, 0));
+    }
+    #[test]
+    fn triggers_report_each_entry_and_exit_once() {
+        let zones = [
+            Rect {
+                x: 20,
+                y: 0,
+                w: 10,
+                h: 10,
+            },
+            // Far away, never touched
+            Rect {
+                x: 100,
+                y: 100,
+                w: 5,
+                h: 5,
+            },
+        ];
+        let mut tracker = TriggerTracker::new();
+        let at = |x| Rect {
+            x,
+            y: 2,
+            w: 4,
+            h: 4,
+        };
+        // Walk right through the first zone, stopping in the middle for a while
+        let path = [0, 8, 22, 24, 24, 24, 28, 40, 40];
+        let events: Vec<Vec<TriggerEvent>> = path
+            .iter()
+            .map(|&x| tracker.update(at(x), &zones))
+            .collect();
+        let mut expected = vec![vec![]; path.len()];
+        expected[2] = vec![TriggerEvent::Entered(0)];
+        expected[7] = vec![TriggerEvent::Exited(0)];
+        assert_eq!(events, expected);
+    }
+    #[test]
+    fn mobile_overlaps_like_rect_touching() {
+        let body = moving(1, 0);
+        assert_eq!(body.bounds(), body.rect);
+        for &(x, y) in &[(2, 2), (4, 0), (0, 4), (5, 0), (-5, -5), (-4, -4)] {
+            let other = Rect { x, y, w: 4, h: 4 };
+            assert_eq!(body.overlaps(other), rect_touching(body.rect, other));
+        }
+        assert!(body.overlaps(Rect {
+            x: 4,
+            y: 0,
+            w: 4,
+            h: 4
+        }));
+        assert!(!body.overlaps(Rect {
+            x: 5,
+            y: 0,
+            w: 4,
+            h: 4
+        }));
+    }
+    #[test]
+    fn restitution_scales_the_bounce_off_a_wall() {
+        // Moving right at 4 and down at 1, and 1 pixel into a wall on the right
+        let after = |restitution| {
+            let mut dynamics = [Mobile {
+                rect: Rect {
+                    x: 7,
+                    y: 0,
+                    w: 4,
+                    h: 4,
+                },
+                vx: 4,
+                vy: 1,
+            }];
+            let walls = [wall(10, -10, 10, 30)];
+            let mut contacts = vec![];
+            gather_contacts(&walls, &dynamics, &mut contacts);
+            restitute(&walls, &mut dynamics, &mut contacts, restitution);
+            assert_eq!(dynamics[0].rect.x, 6);
+            (dynamics[0].vx, dynamics[0].vy)
+        };
+        // Sliding along the wall is never affected
+        assert_eq!(after(0.0), (0, 1));
+        assert_eq!(after(1.0), (-4, 1));
+        assert_eq!(after(0.5), (-2, 1));
+    }
+    #[test]
+    fn only_the_walls_that_stop_a_move_are_reported() {
+        // Two overlapping walls in the way, and one off to the side
+        let walls = [wall(14, -10, 4, 30), wall(10, 0, 6, 4), wall(0, 20, 10, 10)];
+        let start = moving(0, 0).rect;
+        // Lands inside both, but backing off from the near one clears the far one too
+        let (r, hits) = slide_against_walls_traced(start, Vec2i(12, 0), &walls);
+        assert_eq!((r.x, r.y), (6, 0));
+        assert_eq!(
+            hits,
+            vec![WallHit {
+                wall: 1,
+                normal: Vec2i(-1, 0)
+            }]
+        );
+        // Coming back the other way, the other one is nearer
+        let from = Rect { x: 24, ..start };
+        let (r, hits) = slide_against_walls_traced(from, Vec2i(-12, 0), &walls);
+        assert_eq!(r.x, 18);
+        assert_eq!(hits.iter().map(|h| h.wall).collect::<Vec<_>>(), vec![0]);
+        assert_eq!(r, slide_against_walls(from, Vec2i(-12, 0), &walls));
+    }
+    #[test]
+    fn every_pair_of_shapes_touches_or_not() {
+        let rect = |x, y, w, h| Shape::Rect(Rect { x, y, w, h });
+        let circle = |x, y, radius| {
+            Shape::Circle(Circle {
+                center: Vec2i(x, y),
+                radius,
+            })
+        };
+        let wedge = |dx| Shape::Poly(tri(&[(dx, 0), (dx + 10, 0), (dx, 10)]));
+        let base = [rect(0, 0, 10, 10), circle(5, 5, 5), wedge(0)];
+        // (a, something touching it, something not), for each kind of b
+        let cases = [
+            (&base[0], rect(8, 8, 4, 4), rect(20, 0, 4, 4)),
+            (&base[0], circle(13, 5, 4), circle(14, 14, 4)),
+            (&base[0], wedge(6), wedge(20)),
+            (&base[1], circle(12, 5, 3), circle(13, 13, 3)),
+            // Just inside and just outside the corner of a box
+            (&base[1], rect(8, 8, 4, 4), rect(9, 9, 4, 4)),
+            (&base[1], wedge(8), wedge(14)),
+            (&base[2], wedge(8), wedge(11)),
+            // The rect's corner pokes over the wedge's slope, or stays past it
+            (&base[2], rect(4, 4, 4, 4), rect(6, 6, 4, 4)),
+            (&base[2], circle(8, 8, 5), circle(10, 10, 5)),
+        ];
+        for (a, near, far) in cases.iter() {
+            assert!(shapes_touching(a, near), "{:?} should touch {:?}", a, near);
+            assert!(shapes_touching(near, a), "{:?} should touch {:?}", near, a);
+            assert!(
+                !shapes_touching(a, far),
+                "{:?} shouldn't touch {:?}",
+                a,
+                far
+            );
+            assert!(
+                !shapes_touching(far, a),
+                "{:?} shouldn't touch {:?}",
+                far,
+                a
+            );
+        }
+    }
+}
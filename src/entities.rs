@@ -0,0 +1,211 @@
+use crate::sprite::Sprite;
+use crate::types::Vec2i;
+
+/// A handle to something in an Entities. Unlike a plain index it stays pointing at the
+/// same thing when others are despawned, and stops working once its own thing is despawned
+/// (even if the slot gets reused by something spawned later).
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct EntityId {
+    index: usize,
+    generation: u32,
+}
+
+// One place an entity can live. generation goes up every time the slot is emptied,
+// which is what makes old handles to it stale.
+struct Slot<T> {
+    generation: u32,
+    value: Option<T>,
+}
+
+/// A bag of entities (T is whatever an entity is) that hands out EntityIds,
+/// so things like "the player" or "what this enemy is chasing" can be held onto safely.
+pub struct Entities<T> {
+    slots: Vec<Slot<T>>,
+    // Empty slots, to fill before growing
+    free: Vec<usize>,
+}
+
+#[allow(dead_code)]
+impl<T> Entities<T> {
+    pub fn new() -> Self {
+        Self {
+            slots: vec![],
+            free: vec![],
+        }
+    }
+    pub fn spawn(&mut self, value: T) -> EntityId {
+        match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index];
+                slot.value = Some(value);
+                EntityId {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    value: Some(value),
+                });
+                EntityId {
+                    index: self.slots.len() - 1,
+                    generation: 0,
+                }
+            }
+        }
+    }
+    /// Take id's entity out, handing it back. None if it was already gone.
+    pub fn despawn(&mut self, id: EntityId) -> Option<T> {
+        let slot = self.slots.get_mut(id.index)?;
+        if slot.generation != id.generation {
+            return None;
+        }
+        let value = slot.value.take()?;
+        slot.generation += 1;
+        self.free.push(id.index);
+        Some(value)
+    }
+    /// id's entity, or None if it's been despawned
+    pub fn get(&self, id: EntityId) -> Option<&T> {
+        self.slots
+            .get(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.value.as_ref())
+    }
+    pub fn get_mut(&mut self, id: EntityId) -> Option<&mut T> {
+        self.slots
+            .get_mut(id.index)
+            .filter(|slot| slot.generation == id.generation)
+            .and_then(|slot| slot.value.as_mut())
+    }
+    /// Every live entity with its handle, in no particular order
+    pub fn iter(&self) -> impl Iterator<Item = (EntityId, &T)> {
+        self.slots.iter().enumerate().filter_map(|(index, slot)| {
+            slot.value.as_ref().map(|v| {
+                (
+                    EntityId {
+                        index,
+                        generation: slot.generation,
+                    },
+                    v,
+                )
+            })
+        })
+    }
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The per-frame upkeep for a group of animated entities: tick each sprite's animation
+/// by dt seconds, and mirror the ones moving left (sprite art faces right).
+/// velocities[i] is how sprites[i] moved this frame; standing still, or moving
+/// straight up or down, keeps whichever way the sprite was already facing.
+pub fn update_sprites(sprites: &mut [Sprite], velocities: &[Vec2i], dt: f64) {
+    assert_eq!(
+        sprites.len(),
+        velocities.len(),
+        "Every sprite needs a velocity"
+    );
+    for (s, v) in sprites.iter_mut().zip(velocities.iter()) {
+        s.advance(dt);
+        if v.0 != 0 {
+            s.flip_x = v.0 < 0;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::animation::Animation;
+    use crate::texture::Texture;
+    use crate::types::Rect;
+    use std::rc::Rc;
+
+    fn walker(tex: &Rc<Texture>, anim: &Rc<Animation>) -> Sprite {
+        Sprite::new(tex, anim, anim.frames[0], 0.0, Vec2i(0, 0))
+    }
+
+    #[test]
+    fn update_sprites_ticks_everyone() {
+        let tex = Rc::new(Texture::placeholder(32, 16));
+        let frames = vec![
+            Rect {
+                x: 0,
+                y: 0,
+                w: 16,
+                h: 16,
+            },
+            Rect {
+                x: 16,
+                y: 0,
+                w: 16,
+                h: 16,
+            },
+        ];
+        let anim = Rc::new(Animation::new(frames.clone()));
+        let mut sprites = vec![
+            walker(&tex, &anim),
+            walker(&tex, &anim),
+            walker(&tex, &anim),
+        ];
+        let velocities = [Vec2i(-1, 0), Vec2i(0, 0), Vec2i(2, 1)];
+        update_sprites(&mut sprites, &velocities, crate::DT);
+        assert!(sprites.iter().all(|s| s.current_frame == frames[1]));
+        let flipped: Vec<bool> = sprites.iter().map(|s| s.flip_x).collect();
+        assert_eq!(flipped, vec![true, false, false]);
+        // Stopping doesn't turn anyone around, and it's one frame per tick
+        update_sprites(&mut sprites, &[Vec2i(0, 0); 3], crate::DT);
+        assert!(sprites.iter().all(|s| s.current_frame == frames[0]));
+        assert!(sprites[0].flip_x);
+    }
+    #[test]
+    fn despawning_leaves_other_handles_working() {
+        let mut entities = Entities::new();
+        let player = entities.spawn("player");
+        let goblin = entities.spawn("goblin");
+        let bat = entities.spawn("bat");
+        assert_eq!(entities.despawn(goblin), Some("goblin"));
+        assert_eq!(entities.get(player), Some(&"player"));
+        assert_eq!(entities.get(bat), Some(&"bat"));
+        assert_eq!(entities.len(), 2);
+        // The goblin's slot gets reused, but its old handle doesn't see the newcomer
+        let slime = entities.spawn("slime");
+        assert_eq!(entities.get(goblin), None);
+        assert_eq!(entities.get(slime), Some(&"slime"));
+        assert_eq!(entities.despawn(goblin), None);
+        assert_eq!(entities.len(), 3);
+    }
+    #[test]
+    fn iter_skips_despawned_slots() {
+        let mut entities = Entities::new();
+        assert!(entities.is_empty());
+        let ids: Vec<EntityId> = (1..=4).map(|hp| entities.spawn(hp)).collect();
+        entities.despawn(ids[1]);
+        entities.despawn(ids[3]);
+        let live: Vec<(EntityId, i32)> = entities.iter().map(|(id, &hp)| (id, hp)).collect();
+        assert_eq!(live, vec![(ids[0], 1), (ids[2], 3)]);
+        entities.despawn(ids[0]);
+        entities.despawn(ids[2]);
+        assert!(entities.is_empty());
+        assert_eq!(entities.iter().count(), 0);
+    }
+    #[test]
+    fn get_mut_only_takes_live_handles() {
+        let mut entities = Entities::new();
+        let goblin = entities.spawn(10);
+        *entities.get_mut(goblin).unwrap() -= 3;
+        assert_eq!(entities.get(goblin), Some(&7));
+        entities.despawn(goblin);
+        // Something new in the goblin's old slot is out of reach of its stale handle
+        let slime = entities.spawn(5);
+        assert_eq!(entities.get_mut(goblin), None);
+        *entities.get_mut(slime).unwrap() += 1;
+        assert_eq!(entities.get(slime), Some(&6));
+    }
+}
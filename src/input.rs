@@ -0,0 +1,330 @@
+use std::collections::HashSet;
+use std::ops::Deref;
+use winit::event::{ElementState, Event, KeyboardInput, VirtualKeyCode, WindowEvent};
+use winit_input_helper::WinitInputHelper;
+
+/// What update_game gets to look at each frame.
+/// It derefs to WinitInputHelper so key_held and friends work as before,
+/// and adds the bits the helper doesn't track across simulation frames.
+pub struct Input {
+    helper: WinitInputHelper,
+    // Mouse wheel movement (in lines) since the last simulated frame
+    scroll: f32,
+    // Did the window get a close request during this pass through the event loop?
+    quit: bool,
+    // Does the window have keyboard focus?
+    focused: bool,
+    // Keys that are down right now, so OS key repeat doesn't look like new presses
+    down: HashSet<VirtualKeyCode>,
+    // Keys that went down since the last simulated frame
+    pressed: HashSet<VirtualKeyCode>,
+    // Same as down and pressed but by physical scancode, which doesn't depend on layout
+    scancodes_down: HashSet<u32>,
+    scancodes_pressed: HashSet<u32>,
+}
+
+/// Something a control can be bound to.
+/// Key follows the keyboard layout; Scancode is a physical key position,
+/// so e.g. WASD stays under the left hand on AZERTY keyboards.
+/// Scancodes are platform specific, so they're best captured from a keypress
+/// rather than hard-coded.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Binding {
+    Key(VirtualKeyCode),
+    Scancode(u32),
+}
+
+impl Input {
+    pub fn new() -> Self {
+        Self {
+            helper: WinitInputHelper::new(),
+            scroll: 0.0,
+            quit: false,
+            // Windows start out focused
+            focused: true,
+            down: HashSet::new(),
+            pressed: HashSet::new(),
+            scancodes_down: HashSet::new(),
+            scancodes_pressed: HashSet::new(),
+        }
+    }
+    /// Same contract as WinitInputHelper::update: returns true once all the events
+    /// for this pass through the event loop have been seen.
+    pub fn update<T>(&mut self, event: Event<T>) -> bool {
+        match &event {
+            Event::NewEvents(_) => self.quit = false,
+            Event::WindowEvent {
+                event: WindowEvent::CloseRequested,
+                ..
+            }
+            | Event::WindowEvent {
+                event: WindowEvent::Destroyed,
+                ..
+            } => self.quit = true,
+            Event::WindowEvent {
+                event: WindowEvent::Focused(focused),
+                ..
+            } => {
+                self.focused = *focused;
+                // We won't hear about keys let go while we're in the background
+                if !focused {
+                    self.down.clear();
+                    self.scancodes_down.clear();
+                }
+            }
+            Event::WindowEvent {
+                event: WindowEvent::KeyboardInput { input, .. },
+                ..
+            } => self.key_event(input),
+            _ => {}
+        }
+        if self.helper.update(event) {
+            // The helper forgets its scroll amount every pass, but several passes
+            // can go by between simulated frames, so we keep our own running total.
+            self.scroll += self.helper.scroll_diff();
+            true
+        } else {
+            false
+        }
+    }
+    fn key_event(&mut self, input: &KeyboardInput) {
+        // Some keys have no virtual keycode at all, but they all have a scancode
+        match input.state {
+            ElementState::Pressed => {
+                if let Some(key) = input.virtual_keycode {
+                    if self.down.insert(key) {
+                        self.pressed.insert(key);
+                    }
+                }
+                if self.scancodes_down.insert(input.scancode) {
+                    self.scancodes_pressed.insert(input.scancode);
+                }
+            }
+            ElementState::Released => {
+                if let Some(key) = input.virtual_keycode {
+                    self.down.remove(&key);
+                }
+                self.scancodes_down.remove(&input.scancode);
+            }
+        }
+    }
+    /// How far the wheel moved this frame; 0.0 if there was no scrolling
+    #[allow(dead_code)]
+    pub fn scroll_delta(&self) -> f32 {
+        self.scroll
+    }
+    /// Was the window asked to close during this pass?
+    /// Unlike WinitInputHelper::quit this doesn't stay set forever,
+    /// so a close that the game decides to ignore won't keep coming back.
+    pub fn quit_requested(&self) -> bool {
+        self.quit
+    }
+    /// False while the window is in the background (alt-tabbed away, minimized),
+    /// which is a good time to pause and go quiet
+    pub fn focused(&self) -> bool {
+        self.focused
+    }
+    /// True for exactly one simulated frame per press of key.
+    /// key_pressed stays true for a whole pass through the event loop, which can run
+    /// several simulated frames, so menus should use this instead.
+    /// A press that comes in while no frames run waits for the next one.
+    pub fn just_pressed(&self, key: VirtualKeyCode) -> bool {
+        self.pressed.contains(&key)
+    }
+    /// Is the physical key with this scancode down right now?
+    pub fn scancode_held(&self, scancode: u32) -> bool {
+        self.scancodes_down.contains(&scancode)
+    }
+    /// Like just_pressed, but for a physical key
+    pub fn scancode_just_pressed(&self, scancode: u32) -> bool {
+        self.scancodes_pressed.contains(&scancode)
+    }
+    /// Is whatever the binding points at held down?
+    pub fn binding_held(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.down.contains(&key),
+            Binding::Scancode(code) => self.scancode_held(code),
+        }
+    }
+    /// True for one simulated frame per press of whatever the binding points at
+    #[allow(dead_code)]
+    pub fn binding_just_pressed(&self, binding: Binding) -> bool {
+        match binding {
+            Binding::Key(key) => self.just_pressed(key),
+            Binding::Scancode(code) => self.scancode_just_pressed(code),
+        }
+    }
+    /// Call after each simulated frame so per-frame values start over
+    pub fn end_frame(&mut self) {
+        self.scroll = 0.0;
+        self.pressed.clear();
+        self.scancodes_pressed.clear();
+    }
+}
+
+impl Deref for Input {
+    type Target = WinitInputHelper;
+    fn deref(&self) -> &Self::Target {
+        &self.helper
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use winit::event::{DeviceId, ModifiersState, MouseScrollDelta, StartCause, TouchPhase};
+    use winit::window::WindowId;
+
+    fn window_event(event: WindowEvent<'static>) -> Event<'static, ()> {
+        Event::WindowEvent {
+            window_id: unsafe { WindowId::dummy() },
+            event,
+        }
+    }
+    // One pass through the event loop: NewEvents, the given window events, then MainEventsCleared
+    fn pass(input: &mut Input, events: Vec<WindowEvent<'static>>) {
+        assert!(!input.update(Event::<()>::NewEvents(StartCause::Poll)));
+        for event in events {
+            assert!(!input.update(window_event(event)));
+        }
+        assert!(input.update(Event::<()>::MainEventsCleared));
+    }
+    #[allow(deprecated)]
+    fn wheel(lines: f32) -> WindowEvent<'static> {
+        WindowEvent::MouseWheel {
+            device_id: unsafe { DeviceId::dummy() },
+            delta: MouseScrollDelta::LineDelta(0.0, lines),
+            phase: TouchPhase::Moved,
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    #[allow(deprecated)]
+    fn key(key: VirtualKeyCode, state: ElementState) -> WindowEvent<'static> {
+        WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: key as u32,
+                state,
+                virtual_keycode: Some(key),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        }
+    }
+
+    #[test]
+    fn scroll_delta_sums_within_a_frame() {
+        let mut input = Input::new();
+        pass(&mut input, vec![]);
+        assert_eq!(input.scroll_delta(), 0.0);
+        pass(&mut input, vec![wheel(1.0), wheel(2.0)]);
+        // A second pass before the frame is simulated still counts
+        pass(&mut input, vec![wheel(-0.5)]);
+        assert_eq!(input.scroll_delta(), 2.5);
+        input.end_frame();
+        assert_eq!(input.scroll_delta(), 0.0);
+        pass(&mut input, vec![]);
+        assert_eq!(input.scroll_delta(), 0.0);
+    }
+    #[test]
+    fn close_requests_last_one_pass() {
+        let mut input = Input::new();
+        pass(&mut input, vec![]);
+        assert!(!input.quit_requested());
+        pass(&mut input, vec![WindowEvent::CloseRequested]);
+        assert!(input.quit_requested());
+        pass(&mut input, vec![]);
+        assert!(!input.quit_requested());
+    }
+    #[test]
+    fn focus_follows_focus_events() {
+        let mut input = Input::new();
+        pass(&mut input, vec![]);
+        assert!(input.focused());
+        // Alt-tab away, then minimize and restore with no focus change in between
+        pass(&mut input, vec![WindowEvent::Focused(false)]);
+        assert!(!input.focused());
+        pass(&mut input, vec![]);
+        assert!(!input.focused());
+        pass(&mut input, vec![WindowEvent::Focused(true)]);
+        assert!(input.focused());
+        // Only the last one in a pass counts
+        pass(
+            &mut input,
+            vec![WindowEvent::Focused(false), WindowEvent::Focused(true)],
+        );
+        assert!(input.focused());
+    }
+    #[test]
+    fn held_keys_press_once_across_several_frames() {
+        use ElementState::*;
+        let mut input = Input::new();
+        pass(&mut input, vec![key(VirtualKeyCode::Down, Pressed)]);
+        // Two simulated frames in this pass: only the first sees the press
+        assert!(input.just_pressed(VirtualKeyCode::Down));
+        input.end_frame();
+        assert!(!input.just_pressed(VirtualKeyCode::Down));
+        assert!(input.key_held(VirtualKeyCode::Down));
+        input.end_frame();
+        // Key repeat from the OS while it's held isn't a new press
+        pass(&mut input, vec![key(VirtualKeyCode::Down, Pressed)]);
+        assert!(!input.just_pressed(VirtualKeyCode::Down));
+        input.end_frame();
+        // Letting go and pressing again is
+        pass(
+            &mut input,
+            vec![
+                key(VirtualKeyCode::Down, Released),
+                key(VirtualKeyCode::Down, Pressed),
+            ],
+        );
+        assert!(input.just_pressed(VirtualKeyCode::Down));
+    }
+    #[test]
+    #[allow(deprecated)]
+    fn scancode_bindings_follow_the_physical_key() {
+        // On AZERTY the key where QWERTY has W types a Z
+        let azerty_w = WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                scancode: 17,
+                state: ElementState::Pressed,
+                virtual_keycode: Some(VirtualKeyCode::Z),
+                modifiers: ModifiersState::empty(),
+            },
+            is_synthetic: false,
+        };
+        let mut input = Input::new();
+        pass(&mut input, vec![azerty_w]);
+        assert!(input.binding_held(Binding::Scancode(17)));
+        assert!(input.binding_just_pressed(Binding::Scancode(17)));
+        assert!(input.binding_held(Binding::Key(VirtualKeyCode::Z)));
+        assert!(!input.binding_held(Binding::Key(VirtualKeyCode::W)));
+        input.end_frame();
+        assert!(!input.binding_just_pressed(Binding::Scancode(17)));
+        assert!(input.binding_held(Binding::Scancode(17)));
+    }
+    #[test]
+    fn losing_focus_lets_go_of_every_binding() {
+        let mut input = Input::new();
+        let (left, w) = (VirtualKeyCode::Left, VirtualKeyCode::W);
+        pass(
+            &mut input,
+            vec![
+                key(left, ElementState::Pressed),
+                key(w, ElementState::Pressed),
+            ],
+        );
+        assert!(input.binding_held(Binding::Key(left)));
+        assert!(input.binding_held(Binding::Scancode(w as u32)));
+        // Alt-tab away with both still down; their releases go to some other window
+        pass(&mut input, vec![WindowEvent::Focused(false)]);
+        assert!(!input.binding_held(Binding::Key(left)));
+        assert!(!input.binding_held(Binding::Scancode(w as u32)));
+        // Nothing comes back on its own when we get focus again
+        pass(&mut input, vec![WindowEvent::Focused(true)]);
+        assert!(!input.binding_held(Binding::Key(left)));
+        assert!(!input.binding_held(Binding::Scancode(w as u32)));
+    }
+}
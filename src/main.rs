@@ -1,7 +1,6 @@
 use crate::types::{Rect, Rgba, Vec2i};
-use pixels::{Pixels, SurfaceTexture};
+use pixels::{wgpu, PixelsBuilder, SurfaceTexture};
 use std::rc::Rc;
-use rodio::{Source, Sink};
 use std::io::{Write, stdout, BufReader};
 use std::fs::File;
 use std::time::Instant;
@@ -9,8 +8,7 @@ use std::{borrow::Borrow, os::macos::raw::stat, path::Path, task::RawWakerVTable
 use winit::event::{Event, VirtualKeyCode};
 use winit::event_loop::{ControlFlow, EventLoop};
 use winit::window::WindowBuilder;
-use winit::{dpi::LogicalSize, event};
-use winit_input_helper::WinitInputHelper;
+use winit::{dpi::LogicalSize, dpi::PhysicalPosition, dpi::PhysicalSize, event};
 
 // Whoa what's this?
 // Mod without brackets looks for a nearby file.
@@ -39,8 +37,43 @@ use sprite::*;
 mod types;
 use types::*;
 
+// Bitmap fonts and word-wrapped text boxes
+mod text;
+
 mod collision;
 use collision::{rect_touching, Mobile, Wall};
+// Input wraps winit_input_helper with a few per-frame extras
+mod input;
+use input::{Binding, Input};
+// Frame-counting timers for cooldowns and the like
+mod timer;
+// Sound effect playback with a voice limit
+mod audio;
+// Little synthesized tunes, no audio files needed
+mod chiptune;
+// A small seeded random number generator, so runs can be reproduced
+mod rng;
+// Reusable bits of enemy movement
+mod behavior;
+// Scheduling spawns for later frames
+mod spawn;
+// Parallax star backgrounds
+mod starfield;
+// A view that follows things around smoothly
+mod camera;
+// Menu widgets
+mod ui;
+// Entity storage with handles that survive removals
+mod entities;
+// Recent history for rewinding time
+mod rewind;
+use rewind::RewindBuffer;
+// Where finished frames go (the window, or memory when testing)
+mod presenter;
+use presenter::Presenter;
+// Fitting the framebuffer into whatever size the window is
+mod scaler;
+use scaler::{letterbox, LetterboxStyle, ScaledPixels, ScalingFilter};
 type Color = [u8; DEPTH];
 
 const CLEAR_COL: Color = [32, 32, 64, 255];
@@ -48,12 +81,84 @@ const WALL_COL: Color = [200, 200, 200, 255];
 const PLAYER_COL: Color = [255, 128, 128, 255];
 const NEXT_COL: Color = [255, 0, 0, 255];
 const ARROW_COL: Color = [0, 255, 0, 255];
+// Pixels per frame
+const PLAYER_SPEED: f32 = 1.0;
+const PLAYER_SIZE: (u16, u16) = (11, 11);
+// One entry per player: two players sharing the keyboard, one on each side
+const PLAYER_CONTROLS: &[Controls] = &[Controls::ARROWS, Controls::WASD];
+// Clearing this many levels wins; the fourth isn't ready to play yet
+const LEVELS_TO_WIN: usize = 3;
+// How many frames of history we keep for rewinding (five seconds at 60fps)
+const REWIND_FRAMES: usize = 300;
+// Frames after arriving in a level during which its exit doesn't count,
+// so a spawn point sitting on (or next to) the exit can't bounce us straight through
+const LEVEL_GRACE_FRAMES: usize = 30;
+// Sounds allowed to overlap before the oldest gets cut off
+const MAX_VOICES: usize = 8;
+// While the window's in the background the game pauses and the sound fades to this,
+// taking this many frames to fade each way
+const PAUSED_VOLUME: f32 = 0.3;
+const PAUSE_FADE_FRAMES: u32 = 30;
 
 
 struct Level {
     gamemap: Vec<Wall>,
     exit: collision::Rect,
-    position: Vec2i,
+    // Where the players start out when the level begins
+    spawn: Vec2i,
+}
+
+// Ways a level can be broken so the player can't finish it, or not be there at all
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum LevelError {
+    // There's no level with this index
+    Missing(usize),
+    // The player would start out stuck inside this wall (index into gamemap)
+    SpawnInWall(usize),
+    // This wall completely covers the exit, so it can never be reached
+    ExitInWall(usize),
+}
+
+impl std::fmt::Display for LevelError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            LevelError::Missing(i) => write!(f, "there is no level {}", i),
+            LevelError::SpawnInWall(w) => write!(f, "spawn point is inside wall {}", w),
+            LevelError::ExitInWall(w) => write!(f, "exit is buried inside wall {}", w),
+        }
+    }
+}
+
+impl Level {
+    // Check for softlocks: a spawn point inside a wall, or an exit that's covered up
+    fn validate(&self) -> Result<(), LevelError> {
+        let player = collision::Rect {
+            x: self.spawn.0,
+            y: self.spawn.1,
+            w: PLAYER_SIZE.0,
+            h: PLAYER_SIZE.1,
+        };
+        let exit = self.exit;
+        for (i, w) in self.gamemap.iter().enumerate() {
+            // Starting flush against a wall is fine, starting inside one isn't
+            let r = w.rect;
+            if player.x < r.x + r.w as i32
+                && r.x < player.x + player.w as i32
+                && player.y < r.y + r.h as i32
+                && r.y < player.y + player.h as i32
+            {
+                return Err(LevelError::SpawnInWall(i));
+            }
+            if r.x <= exit.x
+                && exit.x + exit.w as i32 <= r.x + r.w as i32
+                && r.y <= exit.y
+                && exit.y + exit.h as i32 <= r.y + r.h as i32
+            {
+                return Err(LevelError::ExitInWall(i));
+            }
+        }
+        Ok(())
+    }
 }
 
 /*
@@ -61,23 +166,258 @@ struct Sound{
     stream: (rodio::OutputStream, rodio::OutputStreamHandle),
     source: rodio::Decoder<BufReader<File>>,
 }*/
+// Scancodes of the keys where W, A, S, and D are on a QWERTY keyboard.
+// Windows and Linux number physical keys the same way; macOS has its own numbering.
+#[cfg(target_os = "macos")]
+const WASD_SCANCODES: [u32; 4] = [13, 0, 1, 2];
+#[cfg(not(target_os = "macos"))]
+const WASD_SCANCODES: [u32; 4] = [17, 30, 31, 32];
+
+// Which keys move a player around
+#[derive(Clone, Copy, Debug)]
+struct Controls {
+    up: Binding,
+    down: Binding,
+    left: Binding,
+    right: Binding,
+}
+impl Controls {
+    const ARROWS: Controls = Controls {
+        up: Binding::Key(VirtualKeyCode::Up),
+        down: Binding::Key(VirtualKeyCode::Down),
+        left: Binding::Key(VirtualKeyCode::Left),
+        right: Binding::Key(VirtualKeyCode::Right),
+    };
+    // By key position rather than letter, so it's still a WASD-shaped cluster on AZERTY
+    const WASD: Controls = Controls {
+        up: Binding::Scancode(WASD_SCANCODES[0]),
+        left: Binding::Scancode(WASD_SCANCODES[1]),
+        down: Binding::Scancode(WASD_SCANCODES[2]),
+        right: Binding::Scancode(WASD_SCANCODES[3]),
+    };
+    // Which way these controls are pushing, -1 to 1 on each axis
+    fn direction(&self, input: &Input) -> Vec2i {
+        let held = |b| input.binding_held(b) as i32;
+        Vec2i(held(self.right) - held(self.left), held(self.down) - held(self.up))
+    }
+}
+
+// Someone at the keyboard and the box they're steering
+struct Player {
+    body: Mobile,
+    // Fraction of a pixel the player has moved but not been drawn at yet
+    remainder: Vec2f,
+    // Index into GameState::sprites of the sprite that follows this player around
+    sprite: usize,
+    controls: Controls,
+}
+
 // Now this main module is just for the run-loop and rules processing.
 struct GameState {
     // What data do we need for this game?  Wall positions?
     // Colliders?  Sprites and stuff?
-    player: Mobile,
-    animations: Vec<Animation>,
-    textures: Vec<Rc<Texture>>,
+    players: Vec<Player>,
+    // Sprites share their textures and animations, so a hundred walkers are one walk cycle
     sprites: Vec<Sprite>,
     //maps: Vec<Tilemap>,
     //scroll: Vec2i,
     levels: Vec<Level>,
     current_level: usize,
     mode: Mode,
+    // Frames left before the current level's exit starts working (see LEVEL_GRACE_FRAMES)
+    exit_grace: usize,
+    // Snapshots of recent gameplay frames, for rewinding with R
+    rewind: RewindBuffer<Snapshot>,
+    // Every move against the walls, when LoopConfig::collision_log is on; None otherwise,
+    // and then nothing gets recorded. It grows until someone takes it (see dump_collision_log).
+    collision_log: Option<Vec<collision::CollisionRecord>>,
+    // Seconds of game time simulated so far, counting the frame that just ran.
+    // It only moves when the simulation does, so pausing would hold it still,
+    // and rewinding doesn't take it back (it's not part of a Snapshot).
+    time_secs: f64,
+}
+
+// The parts of GameState that change while playing, for save states and rewinding.
+// Sprites' textures and animations, and level layouts, never change,
+// so a restored state keeps its own.
+#[derive(Clone, Debug)]
+struct Snapshot {
+    // (position, velocity, subpixel remainder) of each player, in order
+    players: Vec<(collision::Rect, (i32, i32), Vec2f)>,
+    // (position, current frame, elapsed time) of each sprite, in order
+    sprites: Vec<(Vec2i, Rect, f32)>,
+    current_level: usize,
+    mode: Mode,
+    exit_grace: usize,
+}
+
+impl GameState {
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            players: self
+                .players
+                .iter()
+                .map(|p| (p.body.rect, (p.body.vx, p.body.vy), p.remainder))
+                .collect(),
+            sprites: self
+                .sprites
+                .iter()
+                .map(|s| (s.position, s.current_frame, s.elapsed_time))
+                .collect(),
+            current_level: self.current_level,
+            mode: self.mode,
+            exit_grace: self.exit_grace,
+        }
+    }
+    // Move on to levels[index]: every player goes to its spawn point, stopped.
+    // An index past the end is an error, and nothing changes.
+    fn goto_level(&mut self, index: usize) -> Result<(), LevelError> {
+        if index >= self.levels.len() {
+            return Err(LevelError::Missing(index));
+        }
+        self.current_level = index;
+        self.exit_grace = LEVEL_GRACE_FRAMES;
+        for p in 0..self.players.len() {
+            self.players[p].body.vx = 0;
+            self.players[p].body.vy = 0;
+            respawn_player(self, p);
+        }
+        Ok(())
+    }
+    // Put everything back how it was when snap was taken.
+    // snap has to come from this same game, so the player and sprite lists line up.
+    fn restore(&mut self, snap: &Snapshot) {
+        assert_eq!(
+            self.sprites.len(),
+            snap.sprites.len(),
+            "Snapshot is from a different set of sprites"
+        );
+        assert_eq!(
+            self.players.len(),
+            snap.players.len(),
+            "Snapshot is from a different set of players"
+        );
+        for (p, &(rect, (vx, vy), remainder)) in self.players.iter_mut().zip(snap.players.iter()) {
+            p.body.rect = rect;
+            p.body.vx = vx;
+            p.body.vy = vy;
+            p.remainder = remainder;
+        }
+        for (s, &(position, frame, elapsed)) in self.sprites.iter_mut().zip(snap.sprites.iter()) {
+            s.position = position;
+            s.current_frame = frame;
+            s.elapsed_time = elapsed;
+        }
+        self.current_level = snap.current_level;
+        self.mode = snap.mode;
+        self.exit_grace = snap.exit_grace;
+    }
 }
 
 // seconds per frame
 const DT: f64 = 1.0 / 60.0;
+// If rendering falls this many frames behind, we give up on catching up
+// rather than simulating more and more each time (the "spiral of death")
+const MAX_UPDATES_PER_FRAME: usize = 5;
+
+// Settings for the window and the fixed-timestep loop
+struct LoopConfig {
+    // seconds per simulated frame, e.g. 1/120 for finer physics or 1/30 for a retro feel
+    timestep: f64,
+    // Most simulated frames to run per rendered frame before dropping the backlog
+    max_updates_per_frame: usize,
+    // Fifo is vsync; Immediate and Mailbox trade tearing or power for lower latency
+    present_mode: wgpu::PresentMode,
+    // Asked before quitting on Escape or window close; returning false cancels the quit
+    // (say, to ask about saving first). No hook means just quit.
+    on_quit: Option<fn(&mut GameState) -> bool>,
+    // Runs over the finished frame (raw rgba8888 bytes, width, height) right before it's shown,
+    // for whole-screen effects like screen::scanlines or color grading. No hook leaves it alone.
+    post_process: Option<fn(&mut [u8], usize, usize)>,
+    // Where the window opens; see WindowPlacement::from_args for picking it at launch
+    placement: WindowPlacement,
+    // What goes around the game's image when the window isn't an exact multiple of it
+    letterbox: LetterboxStyle,
+    // Crisp (Nearest) or smoothed (Linear) when the frame gets scaled up to the window
+    scaling_filter: ScalingFilter,
+    // Keep a CollisionRecord for every player move (see GameState::collision_log);
+    // (--collision-log turns it on, along with dump_collision_log to save them on the way out)
+    collision_log: bool,
+}
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum WindowPlacement {
+    // Wherever the OS likes
+    OsDefault,
+    // In the middle of whichever monitor the window opened on
+    Centered,
+    // Top-left corner of the window frame at this spot on the desktop, in physical pixels
+    At(i32, i32),
+}
+impl WindowPlacement {
+    // Pick a placement from the command line: --centered, or --at X,Y.
+    // Without either (or with an --at we can't read) the OS decides.
+    fn from_args(args: impl Iterator<Item = String>) -> Self {
+        let args: Vec<String> = args.collect();
+        for (i, arg) in args.iter().enumerate() {
+            match arg.as_str() {
+                "--centered" => return WindowPlacement::Centered,
+                "--at" => {
+                    let xy = args.get(i + 1).and_then(|xy| {
+                        let (x, y) = xy.split_at(xy.find(',')?);
+                        Some((x.trim().parse().ok()?, y[1..].trim().parse().ok()?))
+                    });
+                    if let Some((x, y)) = xy {
+                        return WindowPlacement::At(x, y);
+                    }
+                }
+                _ => {}
+            }
+        }
+        WindowPlacement::OsDefault
+    }
+}
+impl Default for LoopConfig {
+    fn default() -> Self {
+        Self {
+            timestep: DT,
+            max_updates_per_frame: MAX_UPDATES_PER_FRAME,
+            present_mode: wgpu::PresentMode::Fifo,
+            on_quit: None,
+            post_process: None,
+            placement: WindowPlacement::OsDefault,
+            letterbox: LetterboxStyle::default(),
+            scaling_filter: ScalingFilter::default(),
+            collision_log: false,
+        }
+    }
+}
+
+// Which present modes the surface offers. wgpu 0.6 can't be asked, and if we request one
+// the surface lacks it quietly switches to Fifo (vsync), so we check against what each
+// platform's backend offers: Metal has no Mailbox, and DX12 only does Fifo so far.
+// Vulkan depends on the driver; wgpu's own Fifo fallback covers the ones that lack a mode.
+#[cfg(target_os = "macos")]
+const SUPPORTED_PRESENT_MODES: &[wgpu::PresentMode] =
+    &[wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate];
+#[cfg(target_os = "windows")]
+const SUPPORTED_PRESENT_MODES: &[wgpu::PresentMode] = &[wgpu::PresentMode::Fifo];
+#[cfg(not(any(target_os = "macos", target_os = "windows")))]
+const SUPPORTED_PRESENT_MODES: &[wgpu::PresentMode] = &[
+    wgpu::PresentMode::Fifo,
+    wgpu::PresentMode::Immediate,
+    wgpu::PresentMode::Mailbox,
+];
+// The requested present mode if it's supported, otherwise Fifo, which every surface has
+fn present_mode_or_vsync(
+    requested: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    if supported.contains(&requested) {
+        requested
+    } else {
+        wgpu::PresentMode::Fifo
+    }
+}
 
 const WIDTH: usize = 700;
 const HEIGHT: usize = 550;
@@ -101,13 +441,19 @@ fn main() {
     let gameover_file = File::open("gameover.wav").unwrap();
     let source = rodio::Decoder::new(BufReader::new(file)).unwrap();
     let gameover_source = rodio::Decoder::new(BufReader::new(gameover_file)).unwrap();
-    stream_handle.play_raw(source.convert_samples());
-    //stream_handle.play_raw(gameover_source.convert_samples());
+    let mut audio = audio::Audio::new(&stream_handle, MAX_VOICES);
+    // No music is sad but playable, so just say so and carry on
+    if let Err(err) = audio.play_sound(source) {
+        eprintln!("Couldn't play music: {}", err);
+    }
+    //audio.play_sound(gameover_source);
 
 
     let mut rsrc = Resources::new();
-    let startscreen_tex = rsrc.load_texture(Path::new("start.png"));
-    let endscreen_tex = rsrc.load_texture(Path::new("end.jpg"));
+    // A missing title card shouldn't stop the game, so these fall back to a full-screen placeholder
+    let screen_size = (WIDTH as u32, HEIGHT as u32);
+    let startscreen_tex = rsrc.load_texture_or_placeholder(Path::new("start.png"), screen_size);
+    let endscreen_tex = rsrc.load_texture_or_placeholder(Path::new("end.jpg"), screen_size);
 
     let tex = Rc::new(Texture::with_file(Path::new("king.png")));
     let frame1 = Rect {
@@ -358,22 +704,67 @@ fn main() {
         },
     ];
 
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    // --collision-log records every move against the walls and saves them when quitting
+    let collision_log = args.iter().any(|a| a == "--collision-log");
+    let config = LoopConfig {
+        placement: WindowPlacement::from_args(args.into_iter()),
+        collision_log,
+        on_quit: if collision_log {
+            Some(dump_collision_log)
+        } else {
+            None
+        },
+        ..LoopConfig::default()
+    };
     let event_loop = EventLoop::new();
-    let mut input = WinitInputHelper::new();
+    let mut input = Input::new();
     let mut window = {
         let size = LogicalSize::new(WIDTH as f64, HEIGHT as f64);
         WindowBuilder::new()
             .with_title("Maze Game")
             .with_inner_size(size)
             .with_min_inner_size(size)
-            .with_resizable(false)
+            .with_resizable(true)
             .build(&event_loop)
             .unwrap()
     };
+    match config.placement {
+        WindowPlacement::OsDefault => {}
+        WindowPlacement::Centered => {
+            let monitor = window.current_monitor();
+            let (mx, my) = (monitor.position().x, monitor.position().y);
+            let (x, y) = centered_position(monitor.size().into(), window.outer_size().into());
+            window.set_outer_position(PhysicalPosition::new(mx + x, my + y));
+        }
+        WindowPlacement::At(x, y) => window.set_outer_position(PhysicalPosition::new(x, y)),
+    }
     let mut pixels = {
         let window_size = window.inner_size();
-        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-        Pixels::new(WIDTH as u32, HEIGHT as u32, surface_texture).unwrap()
+        let build = |present_mode| {
+            let surface_texture =
+                SurfaceTexture::new(window_size.width, window_size.height, &window);
+            PixelsBuilder::new(WIDTH as u32, HEIGHT as u32, surface_texture)
+                .present_mode(present_mode)
+                .build()
+        };
+        // Not every adapter supports every present mode, but vsync always works
+        let present_mode = present_mode_or_vsync(config.present_mode, SUPPORTED_PRESENT_MODES);
+        if present_mode != config.present_mode {
+            eprintln!(
+                "Present mode {:?} isn't available here, using {:?}",
+                config.present_mode, present_mode
+            );
+        }
+        let pixels = build(present_mode).unwrap();
+        // We do the scaling ourselves, so the image is exactly where letterbox says
+        let window_size = (window_size.width, window_size.height);
+        ScaledPixels::new(
+            pixels,
+            window_size,
+            config.letterbox.clone(),
+            config.scaling_filter,
+        )
     };
 
     let level = Level {
@@ -384,7 +775,7 @@ fn main() {
             w: 68,
             h: 175,
         },
-        position: Vec2i(170, 500),
+        spawn: Vec2i(170, 500),
     };
     let level2 = Level {
         gamemap: walls2,
@@ -394,7 +785,7 @@ fn main() {
             w: 30,
             h: 60,
         },
-        position: Vec2i(WIDTH as i32 - 55, 15),
+        spawn: Vec2i(WIDTH as i32 - 55, 15),
     };
     let level3 = Level {
         gamemap: walls3,
@@ -405,7 +796,7 @@ fn main() {
             w: 43,
             h: 10,
         },
-        position: Vec2i(110, 463),
+        spawn: Vec2i(110, 463),
     };
     let level4 = Level {
         gamemap: walls4,
@@ -416,27 +807,55 @@ fn main() {
             w: 43,
             h: 10,
         },
-        position: Vec2i(110, 463),
+        spawn: Vec2i(110, 463),
     };
 
+    let levels = vec![level, level2, level3, level4];
+    // Not fatal, since a broken level might never be reached, but worth hearing about
+    for (i, l) in levels.iter().enumerate() {
+        if let Err(e) = l.validate() {
+            eprintln!("Level {}: {}", i + 1, e);
+        }
+    }
+
+    // Everyone starts at the first level's spawn, each with their own sprite
+    let Vec2i(spawn_x, spawn_y) = levels[0].spawn;
     let mut state = GameState {
         // initial game state...
-        player: Mobile {
-            rect: collision::Rect {
-                x: 170,
-                y: 500,
-                w: 11,
-                h: 11,
-            },
-            vx: 0,
-            vy: 0,
-        },
-        levels: vec![level, level2, level3, level4],
+        players: PLAYER_CONTROLS
+            .iter()
+            .enumerate()
+            .map(|(i, &controls)| Player {
+                body: Mobile {
+                    rect: collision::Rect {
+                        x: spawn_x,
+                        y: spawn_y,
+                        w: PLAYER_SIZE.0,
+                        h: PLAYER_SIZE.1,
+                    },
+                    vx: 0,
+                    vy: 0,
+                },
+                remainder: Vec2f(0.0, 0.0),
+                sprite: i,
+                controls,
+            })
+            .collect(),
+        levels,
         current_level: 0,
         mode: Mode::TitleScreen,
-        animations: vec![],
-        sprites: vec![Sprite::new(&tex, &anim, frame1, 0, Vec2i(170, 500))],
-        textures: vec![tex],
+        exit_grace: 0,
+        rewind: RewindBuffer::new(REWIND_FRAMES),
+        collision_log: if config.collision_log {
+            Some(vec![])
+        } else {
+            None
+        },
+        time_secs: 0.0,
+        sprites: PLAYER_CONTROLS
+            .iter()
+            .map(|_| Sprite::new(&tex, &anim, frame1, 0.0, Vec2i(spawn_x, spawn_y)))
+            .collect(),
     };
     
 
@@ -448,23 +867,22 @@ fn main() {
     let start = Instant::now();
     // Track end of the last frame
     let mut since = Instant::now();
+    // Starts out as configured; F2 flips it while playing
+    let mut scaling_filter = config.scaling_filter;
+    // Paused whenever the window loses focus
+    let mut paused = false;
     event_loop.run(move |event, _, control_flow| {
         // Draw the current frame
         if let Event::RedrawRequested(_) = event {
-            let fb = pixels.get_frame();
+            let fb = pixels.frame_buffer();
 
             collision::clear(fb, CLEAR_COL);
 
             match state.mode {
                 Mode::TitleScreen => {
-                    Screen::wrap(pixels.get_frame(), WIDTH, HEIGHT, DEPTH, Vec2i(0, 0)).bitblt(
+                    Screen::wrap(pixels.frame_buffer(), WIDTH, HEIGHT, DEPTH, Vec2i(0, 0)).bitblt(
                         &startscreen_tex,
-                        Rect {
-                            x: 0,
-                            y: 0,
-                            w: 700,
-                            h: 550,
-                        },
+                        startscreen_tex.bounds(),
                         Vec2i(0, 0),
                     )
                 }
@@ -476,8 +894,10 @@ fn main() {
 
                     //draw the exit
                     collision::rect(fb, state.levels[state.current_level].exit, NEXT_COL);
-                    // Draw the player
-                    collision::frameRect(fb, state.player.rect, PLAYER_COL);
+                    // Draw the players
+                    for p in state.players.iter() {
+                        collision::frameRect(fb, p.body.rect, PLAYER_COL);
+                    }
                     if (state.current_level != 2) {
                         // Draw the triangle
                         collision::triangle(
@@ -494,26 +914,21 @@ fn main() {
 
                     let mut screen = Screen::wrap(fb, WIDTH, HEIGHT, DEPTH, Vec2i(0, 0));
 
-                    for s in state.sprites.iter() {
-                        screen.draw_sprite(s);
-                    }
+                    draw_sprites(&state.sprites, &mut screen);
                 }
                 Mode::EndGame => {
-                    Screen::wrap(pixels.get_frame(), WIDTH, HEIGHT, DEPTH, Vec2i(0, 0)).bitblt(
+                    Screen::wrap(pixels.frame_buffer(), WIDTH, HEIGHT, DEPTH, Vec2i(0, 0)).bitblt(
                         &endscreen_tex,
-                        Rect {
-                            x: 0,
-                            y: 0,
-                            w: 700,
-                            h: 550,
-                        },
+                        endscreen_tex.bounds(),
                         Vec2i(0, 0),
                     )
                 }
             }
-            
+            if let Some(post_process) = config.post_process {
+                post_process(pixels.frame_buffer(), WIDTH, HEIGHT);
+            }
             // Flip buffers
-            if pixels.render().is_err() {
+            if pixels.present().is_err() {
                 *control_flow = ControlFlow::Exit;
                 return;
             }
@@ -525,25 +940,54 @@ fn main() {
         // Handle input events
         if input.update(event) {
             // Close events
-            if input.key_pressed(VirtualKeyCode::Escape) || input.quit() {
+            let wants_quit = input.key_pressed(VirtualKeyCode::Escape) || input.quit_requested();
+            if wants_quit && confirm_quit(config.on_quit, &mut state) {
                 *control_flow = ControlFlow::Exit;
                 return;
             }
-            // Resize the window if needed
+            // Resize the window if needed.
+            // The framebuffer gets scaled to fit (see scaler::letterbox);
+            // scaler::window_to_buffer maps window spots back onto it the same way.
             if let Some(size) = input.window_resized() {
                 pixels.resize(size.width, size.height);
+                // Ask for a crisp whole-number scale; if the window manager goes along with it
+                // we'll get another resize event with the snapped size
+                let scale_factor = window.scale_factor();
+                let snapped = snap_window_size(size, scale_factor, (WIDTH as u32, HEIGHT as u32));
+                if snapped.to_physical::<u32>(scale_factor) != size {
+                    window.set_inner_size(snapped);
+                }
+            }
+            if input.key_pressed(VirtualKeyCode::F2) {
+                scaling_filter = match scaling_filter {
+                    ScalingFilter::Nearest => ScalingFilter::Linear,
+                    ScalingFilter::Linear => ScalingFilter::Nearest,
+                };
+                pixels.set_filter(scaling_filter);
+            }
+            if paused == input.focused() {
+                paused = !paused;
+                if paused {
+                    audio.duck(PAUSED_VOLUME, PAUSE_FADE_FRAMES);
+                } else {
+                    audio.unduck();
+                }
             }
         }
         // And the simulation "consumes" it
-        while available_time >= DT {
-            let mut screen = Screen::wrap(pixels.get_frame(), WIDTH, HEIGHT, DEPTH, Vec2i(0, 0));
-            // Eat up one frame worth of time
-            available_time -= DT;
+        let steps = steps_due(&mut available_time, config.timestep, config.max_updates_per_frame);
+        for _ in 0..steps {
+            audio.update();
+            // Paused, the clock keeps going (so the fade does too) but the game holds still
+            if !paused {
+                // Simulated time only moves when the simulation does, so it's steady
+                let time_secs = frame_count as f64 * config.timestep;
+                update_game(&mut state, &input, frame_count, time_secs, config.timestep);
 
-            update_game(&mut state, &input, frame_count);
-
-            // Increment the frame counter
-            frame_count += 1;
+                // Increment the frame counter
+                frame_count += 1;
+            }
+            input.end_frame();
         }
         // Request redraw
         window.request_redraw();
@@ -551,33 +995,114 @@ fn main() {
         since = Instant::now();
     });
 }
-fn update_game(state: &mut GameState, input: &WinitInputHelper, frame: usize) {
-    let mut level_index: usize = state.current_level;
+// Whether to go ahead with a quit: the hook gets a say if there is one, otherwise yes
+fn confirm_quit(on_quit: Option<fn(&mut GameState) -> bool>, state: &mut GameState) -> bool {
+    match on_quit {
+        Some(hook) => hook(state),
+        None => true,
+    }
+}
+// Eats up as many whole timesteps of available_time as we should simulate right now.
+// Past max_steps the leftover whole steps are dropped (keeping the fraction),
+// so one long hitch can't snowball into ever longer frames.
+fn steps_due(available_time: &mut f64, timestep: f64, max_steps: usize) -> usize {
+    let mut steps = 0;
+    while *available_time >= timestep && steps < max_steps {
+        *available_time -= timestep;
+        steps += 1;
+    }
+    if *available_time >= timestep {
+        *available_time %= timestep;
+    }
+    steps
+}
+
+// Where to put a window's top-left so it sits in the middle of a monitor,
+// relative to the monitor's top-left. A window bigger than the monitor hangs off both sides.
+fn centered_position((mon_w, mon_h): (u32, u32), (win_w, win_h): (u32, u32)) -> (i32, i32) {
+    ((mon_w as i32 - win_w as i32) / 2, (mon_h as i32 - win_h as i32) / 2)
+}
+
+// The window size to snap a resize to: the biggest whole multiple of the buffer size
+// that fits in what was asked for (but at least 1x), so there are never any bars.
+// Works in logical units, the same as the window's min_inner_size, so a HiDPI screen's
+// scale_factor doesn't make the smallest snap smaller than the smallest window.
+fn snap_window_size(
+    requested: PhysicalSize<u32>,
+    scale_factor: f64,
+    (buf_w, buf_h): (u32, u32),
+) -> LogicalSize<u32> {
+    let logical = requested.to_logical::<f64>(scale_factor);
+    // A hair of slack so an exact multiple doesn't get floored away by float error
+    let (w, h) = (
+        (logical.width + 1e-6).floor() as u32,
+        (logical.height + 1e-6).floor() as u32,
+    );
+    let (scale, _) = letterbox((w, h), (buf_w, buf_h));
+    LogicalSize::new(buf_w * scale, buf_h * scale)
+}
 
+// frame counts simulated frames from 0; time_secs is how much game time they add up to,
+// and dt is how much game time this one frame covers
+fn update_game(state: &mut GameState, input: &Input, frame: usize, time_secs: f64, dt: f64) {
+    state.time_secs = time_secs + dt;
 
     match state.mode {
         Mode::TitleScreen => {
-            if input.key_held(VirtualKeyCode::Return) {
+            if input.just_pressed(VirtualKeyCode::Return) {
                 state.mode = Mode::GamePlay
             }
         }
         Mode::GamePlay => {
-            // Player control goes here
-            if input.key_held(VirtualKeyCode::Right) {
-                state.player.rect.x += 1;
-                state.sprites[0].position.0 += 1;
-            }
-            if input.key_held(VirtualKeyCode::Left) {
-                state.player.rect.x -= 1;
-                state.sprites[0].position.0 -= 1;
-            }
-            if input.key_held(VirtualKeyCode::Up) {
-                state.player.rect.y -= 1;
-                state.sprites[0].position.1 -= 1;
+            // Holding R runs time backward a frame at a time, as far back as we remember
+            if input.key_held(VirtualKeyCode::R) {
+                if let Some(snap) = state.rewind.rewind_one() {
+                    state.restore(&snap);
+                }
+                return;
             }
-            if input.key_held(VirtualKeyCode::Down) {
-                state.player.rect.y += 1;
-                state.sprites[0].position.1 += 1;
+            let snap = state.snapshot();
+            state.rewind.push(snap);
+            // Player control goes here; everyone moves by their own controls
+            let walls = &state.levels[state.current_level].gamemap;
+            // How far each sprite moved this frame, for facing it the right way
+            let mut velocities = vec![Vec2i(0, 0); state.sprites.len()];
+            for (i, p) in state.players.iter_mut().enumerate() {
+                let dir = p.controls.direction(input);
+                // One pixel a frame whichever way we're going, diagonals included
+                let vel = collision::directional_velocity(dir, PLAYER_SPEED);
+                let step = collision::subpixel_step(&mut p.remainder, vel);
+                // Walls stop players (and they can slide along them) instead of sending them back
+                let before = p.body.rect;
+                let after = match &mut state.collision_log {
+                    None => collision::slide_against_walls(before, step, walls),
+                    Some(log) => {
+                        let (after, contacts) =
+                            collision::slide_against_walls_traced(before, step, walls);
+                        log.push(collision::CollisionRecord {
+                            frame,
+                            entity: i,
+                            start: before,
+                            step,
+                            contacts,
+                            resolved: Vec2i(after.x, after.y),
+                        });
+                        after
+                    }
+                };
+                // Blocked on an axis means any partial pixel on it is gone too
+                if after.x - before.x != step.0 {
+                    p.remainder.0 = 0.0;
+                }
+                if after.y - before.y != step.1 {
+                    p.remainder.1 = 0.0;
+                }
+                p.body.rect = after;
+                let moved = Vec2i(after.x - before.x, after.y - before.y);
+                let sprite = &mut state.sprites[p.sprite];
+                sprite.position.0 += moved.0;
+                sprite.position.1 += moved.1;
+                velocities[p.sprite] = moved;
             }
             /*
             yes,
@@ -596,37 +1121,545 @@ fn update_game(state: &mut GameState, input: &WinitInputHelper, frame: usize) {
             // Update player position
 
             // Detect collisions: Generate contacts
-            for w in state.levels[state.current_level].gamemap.iter() {
-                if collision::rect_touching(state.player.rect, w.rect) {
-                    state.current_level = level_index;
-                    state.player.rect.x = state.levels[state.current_level].position.0;
-                    state.player.rect.y = state.levels[state.current_level].position.1;
-                    state.sprites[0].position.0 = state.player.rect.x;
-                    state.sprites[0].position.1 = state.player.rect.y;
-
+            let level = &state.levels[state.current_level];
+            let events: Vec<CollisionEvent> = state
+                .players
+                .iter()
+                .flat_map(|p| detect_collisions(p.body.rect, level))
+                .collect();
+            // Then respond to them. Any one player reaching the exit brings everybody along
+            // to the next level.
+            let mut reached_exit = false;
+            for event in events {
+                match event {
+                    CollisionEvent::ReachedExit => reached_exit = true,
+                    // Walls already stopped the player while moving, and resting against one
+                    // (or sliding along it) is harmless, so there's nothing more to do
+                    CollisionEvent::HitWall(_) => {}
                 }
             }
- 
-            if collision::rect_touching(state.player.rect, state.levels[state.current_level].exit) {
-                //change level here
-                level_index += 1;
-                state.current_level = level_index;
-                state.player.rect.x = state.levels[state.current_level].position.0;
-                state.player.rect.y = state.levels[state.current_level].position.1;
-                state.sprites[0].position.0 = state.player.rect.x;
-                state.sprites[0].position.1 = state.player.rect.y;
-                if (level_index == 3) {
+            if state.exit_grace > 0 {
+                state.exit_grace -= 1;
+            } else if reached_exit {
+                // Finishing the last playable level (or running out of levels) wins
+                let next = state.current_level + 1;
+                if next >= LEVELS_TO_WIN || state.goto_level(next).is_err() {
                     state.mode = Mode::EndGame;
                 }
             }
-            state.sprites[0].update_anim();
+            // Tick every sprite's animation, not just the player's.
+            // Going by time rather than frames keeps them at the right speed whatever the timestep.
+            entities::update_sprites(&mut state.sprites, &velocities, dt);
         }
 
         Mode::EndGame => {
-            if input.key_held(VirtualKeyCode::Return) {
-                state.current_level = 0;
+            if input.just_pressed(VirtualKeyCode::Return) {
+                state.goto_level(0).expect("There's no first level");
+                // A fresh game shouldn't be able to rewind into the last one
+                state.rewind.clear();
                 state.mode = Mode::GamePlay
             }
         }
     }
 }
+
+// Something the player ran into this frame
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum CollisionEvent {
+    // Index into the level's gamemap
+    HitWall(usize),
+    ReachedExit,
+}
+
+// Just reports what the player is touching; update_game decides what that means
+fn detect_collisions(player: collision::Rect, level: &Level) -> Vec<CollisionEvent> {
+    let mut events: Vec<CollisionEvent> = level
+        .gamemap
+        .iter()
+        .enumerate()
+        .filter(|(_, w)| rect_touching(player, w.rect))
+        .map(|(i, _)| CollisionEvent::HitWall(i))
+        .collect();
+    if rect_touching(player, level.exit) {
+        events.push(CollisionEvent::ReachedExit);
+    }
+    events
+}
+
+// Works as a LoopConfig::on_quit hook: writes the collision log (if there is one)
+// to collisions.json as an array of records, then lets the quit go ahead
+fn dump_collision_log(state: &mut GameState) -> bool {
+    if let Some(log) = state.collision_log.take() {
+        let records: Vec<_> = log.iter().map(|r| r.to_json()).collect();
+        let json = serde_json::Value::Array(records).to_string();
+        if let Err(e) = std::fs::write("collisions.json", json) {
+            eprintln!("Couldn't save collision log: {}", e);
+        }
+    }
+    true
+}
+
+// Put players[p] (and their sprite) back at the current level's spawn point
+fn respawn_player(state: &mut GameState, p: usize) {
+    let Vec2i(x, y) = state.levels[state.current_level].spawn;
+    let player = &mut state.players[p];
+    player.body.rect.x = x;
+    player.body.rect.y = y;
+    player.remainder = Vec2f(0.0, 0.0);
+    state.sprites[player.sprite].position = Vec2i(x, y);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A level with no walls and a small exit in the corner, starting players at spawn
+    fn open_level(spawn: Vec2i) -> Level {
+        Level {
+            gamemap: vec![],
+            exit: collision::Rect {
+                x: 0,
+                y: 0,
+                w: 4,
+                h: 4,
+            },
+            spawn,
+        }
+    }
+    // Input with keys held down, as if they were pressed in the last pass through the event loop
+    fn holding(keys: &[VirtualKeyCode]) -> Input {
+        let presses: Vec<(VirtualKeyCode, u32)> = keys.iter().map(|&k| (k, k as u32)).collect();
+        holding_scancodes(&presses)
+    }
+    // Same, but each key comes with the scancode of where it is on the keyboard
+    fn holding_scancodes(keys: &[(VirtualKeyCode, u32)]) -> Input {
+        use winit::event::{DeviceId, ElementState, KeyboardInput, StartCause, WindowEvent};
+        let mut input = Input::new();
+        input.update(Event::<()>::NewEvents(StartCause::Poll));
+        for &(key, scancode) in keys {
+            #[allow(deprecated)]
+            let press = WindowEvent::KeyboardInput {
+                device_id: unsafe { DeviceId::dummy() },
+                input: KeyboardInput {
+                    scancode,
+                    state: ElementState::Pressed,
+                    virtual_keycode: Some(key),
+                    modifiers: Default::default(),
+                },
+                is_synthetic: false,
+            };
+            let event: Event<()> = Event::WindowEvent {
+                window_id: unsafe { winit::window::WindowId::dummy() },
+                event: press,
+            };
+            input.update(event);
+        }
+        input.update(Event::<()>::MainEventsCleared);
+        input
+    }
+    // A game with one player per entry in PLAYER_CONTROLS, in the first of levels,
+    // without loading anything from disk
+    fn test_state(levels: Vec<Level>) -> GameState {
+        let tex = Rc::new(Texture::placeholder(16, 16));
+        let frame = tex.bounds();
+        let anim = Rc::new(Animation::new(vec![frame]));
+        let Vec2i(x, y) = levels[0].spawn;
+        GameState {
+            players: PLAYER_CONTROLS
+                .iter()
+                .enumerate()
+                .map(|(i, &controls)| Player {
+                    body: Mobile {
+                        rect: collision::Rect {
+                            x,
+                            y,
+                            w: PLAYER_SIZE.0,
+                            h: PLAYER_SIZE.1,
+                        },
+                        vx: 0,
+                        vy: 0,
+                    },
+                    remainder: Vec2f(0.0, 0.0),
+                    sprite: i,
+                    controls,
+                })
+                .collect(),
+            sprites: PLAYER_CONTROLS
+                .iter()
+                .map(|_| Sprite::new(&tex, &anim, frame, 0.0, Vec2i(x, y)))
+                .collect(),
+            levels,
+            current_level: 0,
+            mode: Mode::GamePlay,
+            exit_grace: 0,
+            rewind: RewindBuffer::new(REWIND_FRAMES),
+            collision_log: None,
+            time_secs: 0.0,
+        }
+    }
+
+    #[test]
+    fn respawn_uses_the_current_levels_spawn() {
+        let mut state = test_state(vec![open_level(Vec2i(10, 20)), open_level(Vec2i(300, 40))]);
+        state.current_level = 1;
+        state.players[0].body.rect.x = 123;
+        state.players[0].remainder = Vec2f(0.5, 0.5);
+        respawn_player(&mut state, 0);
+        let p = &state.players[0];
+        assert_eq!((p.body.rect.x, p.body.rect.y), (300, 40));
+        assert_eq!(p.remainder, Vec2f(0.0, 0.0));
+        assert_eq!(state.sprites[p.sprite].position, Vec2i(300, 40));
+    }
+    // How many updates a second of frames taking frame_secs each adds up to
+    fn updates_in_a_second(timestep: f64, frame_secs: f64) -> usize {
+        let mut available_time = 0.0;
+        let mut updates = 0;
+        let mut elapsed = 0.0;
+        while elapsed < 1.0 - 1e-9 {
+            available_time += frame_secs;
+            elapsed += frame_secs;
+            updates += steps_due(&mut available_time, timestep, usize::MAX);
+        }
+        updates
+    }
+
+    #[test]
+    fn halving_the_timestep_doubles_the_updates() {
+        for &frame_secs in &[0.01, 1.0 / 60.0, 0.05] {
+            let at_60 = updates_in_a_second(1.0 / 60.0, frame_secs);
+            let at_120 = updates_in_a_second(1.0 / 120.0, frame_secs);
+            // Give or take the last update, which rounding can push into the next second
+            assert!((59..=60).contains(&at_60), "{} updates at 60Hz", at_60);
+            assert!((119..=120).contains(&at_120), "{} updates at 120Hz", at_120);
+        }
+    }
+    #[test]
+    fn quit_hook_can_cancel_the_quit() {
+        let mut state = test_state(vec![open_level(Vec2i(10, 10))]);
+        assert!(confirm_quit(None, &mut state));
+        // Say a save prompt popped up: the hook switches modes and keeps the game running
+        fn ask_first(state: &mut GameState) -> bool {
+            state.mode = Mode::EndGame;
+            false
+        }
+        assert!(!confirm_quit(Some(ask_first), &mut state));
+        assert_eq!(state.mode, Mode::EndGame);
+        assert!(confirm_quit(Some(|_| true), &mut state));
+    }
+    #[test]
+    fn present_mode_defaults_to_vsync_and_falls_back_to_it() {
+        use wgpu::PresentMode::*;
+        assert_eq!(LoopConfig::default().present_mode, Fifo);
+        let config = LoopConfig {
+            present_mode: Immediate,
+            ..LoopConfig::default()
+        };
+        assert_eq!(config.present_mode, Immediate);
+        assert_eq!(
+            present_mode_or_vsync(Immediate, &[Fifo, Immediate]),
+            Immediate
+        );
+        assert_eq!(present_mode_or_vsync(Mailbox, &[Fifo, Immediate]), Fifo);
+        assert_eq!(present_mode_or_vsync(Fifo, &[Fifo]), Fifo);
+        // Vsync is always on the list
+        assert!(SUPPORTED_PRESENT_MODES.contains(&Fifo));
+    }
+    fn rect(x: i32, y: i32, w: u16, h: u16) -> collision::Rect {
+        collision::Rect { x, y, w, h }
+    }
+    #[test]
+    fn collisions_come_back_as_events() {
+        let mut level = open_level(Vec2i(50, 50));
+        level.gamemap = vec![
+            Wall {
+                rect: rect(100, 0, 10, 100),
+            },
+            Wall {
+                rect: rect(0, 100, 100, 10),
+            },
+        ];
+        // Only touching the exit
+        assert_eq!(
+            detect_collisions(rect(2, 2, 4, 4), &level),
+            vec![CollisionEvent::ReachedExit]
+        );
+        // Only touching the second wall
+        assert_eq!(
+            detect_collisions(rect(50, 95, 10, 10), &level),
+            vec![CollisionEvent::HitWall(1)]
+        );
+        assert_eq!(detect_collisions(rect(50, 50, 10, 10), &level), vec![]);
+    }
+    #[test]
+    fn scanline_post_process_clears_every_other_row() {
+        assert!(LoopConfig::default().post_process.is_none());
+        let config = LoopConfig {
+            post_process: Some(screen::scanlines),
+            ..LoopConfig::default()
+        };
+        let (w, h) = (5, 4);
+        let mut fb = vec![200_u8; w * h * 4];
+        if let Some(post_process) = config.post_process {
+            post_process(&mut fb, w, h);
+        }
+        for (y, row) in fb.chunks_exact(w * 4).enumerate() {
+            let expected = if y % 2 == 0 { [200; 4] } else { [0, 0, 0, 200] };
+            assert!(row.chunks_exact(4).all(|px| px == expected), "row {}", y);
+        }
+    }
+    #[test]
+    fn restoring_a_snapshot_undoes_later_changes() {
+        let mut state = test_state(vec![open_level(Vec2i(10, 10)), open_level(Vec2i(50, 50))]);
+        state.players[0].body.vx = 2;
+        state.sprites[0].elapsed_time = 0.05;
+        let before = state.snapshot();
+        let animation = Rc::clone(&state.sprites[0].animation);
+
+        state.players[0].body.rect.x += 7;
+        state.players[0].body.vx = -1;
+        state.players[0].remainder = Vec2f(0.5, 0.25);
+        state.sprites[0].position = Vec2i(99, 99);
+        state.sprites[0].elapsed_time = 0.0;
+        state.goto_level(1).unwrap();
+        state.mode = Mode::EndGame;
+
+        state.restore(&before);
+        let p = &state.players[0];
+        assert_eq!((p.body.rect.x, p.body.rect.y), (10, 10));
+        assert_eq!((p.body.vx, p.body.vy), (2, 0));
+        assert_eq!((p.remainder.0, p.remainder.1), (0.0, 0.0));
+        assert_eq!(state.sprites[0].position, Vec2i(10, 10));
+        assert_eq!(state.sprites[0].elapsed_time, 0.05);
+        assert_eq!(state.current_level, 0);
+        assert_eq!(state.mode, Mode::GamePlay);
+        assert_eq!(state.exit_grace, 0);
+        // The live sprites keep their own animations
+        assert!(Rc::ptr_eq(&state.sprites[0].animation, &animation));
+    }
+    #[test]
+    fn scaling_filter_defaults_to_crisp_pixels() {
+        assert_eq!(LoopConfig::default().scaling_filter, ScalingFilter::Nearest);
+        let config = LoopConfig {
+            scaling_filter: ScalingFilter::Linear,
+            ..LoopConfig::default()
+        };
+        let sampler = config.scaling_filter.sampler_descriptor();
+        assert_eq!(sampler.mag_filter, wgpu::FilterMode::Linear);
+    }
+    #[test]
+    fn levels_with_a_buried_spawn_or_exit_fail_validation() {
+        // An 11x11 player starting at (20, 20), right up against a wall on its left
+        let mut level = open_level(Vec2i(20, 20));
+        level.exit = rect(100, 100, 8, 8);
+        level.gamemap = vec![Wall {
+            rect: rect(0, 0, 20, 40),
+        }];
+        assert_eq!(level.validate(), Ok(()));
+        // Nudged a pixel into the wall
+        level.spawn = Vec2i(19, 20);
+        assert_eq!(level.validate(), Err(LevelError::SpawnInWall(0)));
+        assert_eq!(
+            LevelError::SpawnInWall(0).to_string(),
+            "spawn point is inside wall 0"
+        );
+        // An exit only partly under a wall can still be reached
+        level.spawn = Vec2i(20, 20);
+        level.gamemap.push(Wall {
+            rect: rect(96, 96, 8, 8),
+        });
+        assert_eq!(level.validate(), Ok(()));
+        level.gamemap.push(Wall {
+            rect: rect(90, 90, 30, 30),
+        });
+        assert_eq!(level.validate(), Err(LevelError::ExitInWall(2)));
+    }
+    #[test]
+    fn resizes_snap_to_whole_multiples_in_logical_pixels() {
+        let snap = |w, h, scale_factor| {
+            let size = snap_window_size(PhysicalSize::new(w, h), scale_factor, (100, 60));
+            (size.width, size.height)
+        };
+        assert_eq!(snap(100, 60, 1.0), (100, 60));
+        assert_eq!(snap(250, 200, 1.0), (200, 120));
+        // The narrower way decides
+        assert_eq!(snap(500, 130, 1.0), (200, 120));
+        // Too small for even 1x still gets 1x, like min_inner_size
+        assert_eq!(snap(30, 20, 1.0), (100, 60));
+        // At 2x DPI, 400x240 physical is only 200x120 logical
+        assert_eq!(snap(400, 240, 2.0), (200, 120));
+        assert_eq!(snap(399, 240, 2.0), (100, 60));
+        assert_eq!(snap(375, 225, 1.25), (300, 180));
+    }
+    #[test]
+    fn game_time_is_steps_times_the_timestep() {
+        let input = Input::new();
+        for &timestep in &[DT, 1.0 / 30.0] {
+            let mut state = test_state(vec![open_level(Vec2i(10, 10))]);
+            // The same times the run loop hands out
+            for frame in 0..90 {
+                update_game(&mut state, &input, frame, frame as f64 * timestep, timestep);
+            }
+            assert!((state.time_secs - 90.0 * timestep).abs() < 1e-9);
+        }
+    }
+    #[test]
+    fn rewinding_steps_back_through_each_frame() {
+        let mut state = test_state(vec![open_level(Vec2i(10, 10))]);
+        let mut recorded = vec![];
+        // Walk right a pixel a frame, saving each frame like update_game does
+        for _ in 0..5 {
+            let snap = state.snapshot();
+            recorded.push(state.players[0].body.rect.x);
+            state.rewind.push(snap);
+            state.players[0].body.rect.x += 1;
+        }
+        let mut rewound = vec![];
+        while let Some(snap) = state.rewind.rewind_one() {
+            state.restore(&snap);
+            rewound.push(state.players[0].body.rect.x);
+        }
+        recorded.reverse();
+        assert_eq!(rewound, recorded);
+        assert_eq!(state.players[0].body.rect.x, 10);
+    }
+    #[test]
+    fn walking_into_a_wall_at_an_angle_slides_along_it() {
+        let mut level = open_level(Vec2i(20, 50));
+        level.gamemap = vec![Wall {
+            rect: rect(40, 0, 10, 200),
+        }];
+        let mut state = test_state(vec![level]);
+        let input = holding(&[VirtualKeyCode::Right, VirtualKeyCode::Down]);
+        for frame in 0..60 {
+            update_game(&mut state, &input, frame, frame as f64 * DT, DT);
+        }
+        let body = state.players[0].body.rect;
+        // Stopped flush against the wall's left side instead of going back to the spawn point...
+        assert_eq!(body.x, 40 - PLAYER_SIZE.0 as i32);
+        // ...and kept going down the whole time
+        assert!(body.y > 50 + 30, "only got down to {}", body.y);
+        assert_eq!(state.mode, Mode::GamePlay);
+        assert_eq!(state.sprites[0].position, Vec2i(body.x, body.y));
+    }
+    #[test]
+    fn a_long_hitch_runs_a_capped_number_of_updates() {
+        assert_eq!(
+            LoopConfig::default().max_updates_per_frame,
+            MAX_UPDATES_PER_FRAME
+        );
+        // The window was dragged around for ten seconds
+        let mut available_time = 10.0 + DT / 2.0;
+        let steps = steps_due(&mut available_time, DT, MAX_UPDATES_PER_FRAME);
+        assert_eq!(steps, MAX_UPDATES_PER_FRAME);
+        // The rest of the backlog is dropped, but not the fraction of a step
+        assert!(
+            (available_time - DT / 2.0).abs() < 1e-9,
+            "{}",
+            available_time
+        );
+        // So the next frame is back to normal
+        available_time += DT;
+        assert_eq!(steps_due(&mut available_time, DT, MAX_UPDATES_PER_FRAME), 1);
+        // Short of the cap, everything that's due runs
+        let mut available_time = 3.0 * DT;
+        assert_eq!(steps_due(&mut available_time, DT, MAX_UPDATES_PER_FRAME), 3);
+    }
+    #[test]
+    fn going_past_the_last_level_errors_instead_of_panicking() {
+        let mut state = test_state(vec![open_level(Vec2i(10, 10)), open_level(Vec2i(50, 60))]);
+        state.players[0].body.vx = 3;
+        assert_eq!(state.goto_level(2), Err(LevelError::Missing(2)));
+        assert_eq!(state.current_level, 0);
+
+        state.goto_level(1).unwrap();
+        assert_eq!(state.current_level, 1);
+        let p = &state.players[0];
+        assert_eq!((p.body.rect.x, p.body.rect.y), (50, 60));
+        assert_eq!((p.body.vx, p.body.vy), (0, 0));
+        assert_eq!(state.sprites[p.sprite].position, Vec2i(50, 60));
+        assert_eq!(state.exit_grace, LEVEL_GRACE_FRAMES);
+    }
+    #[test]
+    fn windows_center_on_the_monitor() {
+        assert_eq!(centered_position((1920, 1080), (700, 550)), (610, 265));
+        // Too big to fit hangs off both sides evenly
+        assert_eq!(centered_position((640, 480), (700, 550)), (-30, -35));
+        let args = |list: &[&str]| {
+            WindowPlacement::from_args(list.iter().map(|a| a.to_string()))
+        };
+        assert_eq!(args(&[]), WindowPlacement::OsDefault);
+        assert_eq!(args(&["--centered"]), WindowPlacement::Centered);
+        assert_eq!(args(&["--at", "100, -20"]), WindowPlacement::At(100, -20));
+        assert_eq!(args(&["--at", "nowhere"]), WindowPlacement::OsDefault);
+    }
+    #[test]
+    fn spawning_on_the_next_exit_waits_out_the_grace_period() {
+        // Every spawn sits right on its level's exit
+        let on_exit = || open_level(Vec2i(0, 0));
+        let mut state = test_state(vec![on_exit(), on_exit(), on_exit()]);
+        let input = holding(&[]);
+        update_game(&mut state, &input, 0, 0.0, DT);
+        assert_eq!(state.current_level, 1);
+        for frame in 1..=LEVEL_GRACE_FRAMES {
+            update_game(&mut state, &input, frame, frame as f64 * DT, DT);
+            assert_eq!(state.current_level, 1, "frame {}", frame);
+        }
+        // Once it's over the exit works again
+        let frame = LEVEL_GRACE_FRAMES + 1;
+        update_game(&mut state, &input, frame, frame as f64 * DT, DT);
+        assert_eq!(state.current_level, 2);
+    }
+    #[test]
+    fn each_player_moves_by_their_own_keys() {
+        let mut state = test_state(vec![open_level(Vec2i(100, 100))]);
+        assert_eq!(state.players.len(), 2);
+        // Arrows for the first player, and the keys under W and D for the second
+        let [w, _, _, d] = WASD_SCANCODES;
+        let input = holding_scancodes(&[
+            (VirtualKeyCode::Left, VirtualKeyCode::Left as u32),
+            (VirtualKeyCode::W, w),
+            (VirtualKeyCode::D, d),
+        ]);
+        for frame in 0..10 {
+            update_game(&mut state, &input, frame, frame as f64 * DT, DT);
+        }
+        let at = |p: &Player| (p.body.rect.x, p.body.rect.y);
+        assert_eq!(at(&state.players[0]), (90, 100));
+        let (x, y) = at(&state.players[1]);
+        assert!(x > 100 && y < 100, "Second player at {:?}", (x, y));
+        // Each sprite follows its own player
+        for p in state.players.iter() {
+            assert_eq!(state.sprites[p.sprite].position, Vec2i(at(p).0, at(p).1));
+        }
+    }
+    #[test]
+    fn walking_into_a_wall_logs_the_contact_and_where_it_stopped() {
+        let mut level = open_level(Vec2i(20, 50));
+        level.gamemap.push(Wall {
+            rect: rect(32, 0, 10, 200),
+        });
+        let mut state = test_state(vec![level]);
+        state.collision_log = Some(vec![]);
+        // One pixel a frame, so the first player meets the wall on the second frame
+        let input = holding(&[VirtualKeyCode::Right]);
+        for frame in 0..3 {
+            update_game(&mut state, &input, frame, frame as f64 * DT, DT);
+        }
+        let log = state.collision_log.take().unwrap();
+        let mine: Vec<_> = log.iter().filter(|r| r.entity == 0).collect();
+        assert_eq!(mine.len(), 3);
+        assert!(mine[0].contacts.is_empty());
+        let hit = mine[1];
+        assert_eq!(hit.frame, 1);
+        assert_eq!(hit.step, Vec2i(1, 0));
+        assert_eq!(
+            hit.contacts,
+            vec![collision::WallHit {
+                wall: 0,
+                normal: Vec2i(-1, 0)
+            }]
+        );
+        assert_eq!(hit.resolved, Vec2i(21, 50));
+        assert_eq!(hit.to_json()["resolved"], serde_json::json!([21, 50]));
+    }
+}
@@ -0,0 +1,82 @@
+use pixels::raw_window_handle::HasRawWindowHandle;
+use pixels::Pixels;
+
+/// Somewhere finished frames go. The game draws into frame_buffer (wrapped in a Screen)
+/// and then calls present; pixels puts that on the window, but nothing in the drawing
+/// code needs to know that.
+pub trait Presenter {
+    type Error;
+    /// The rgba8888 buffer to draw the next frame into
+    fn frame_buffer(&mut self) -> &mut [u8];
+    /// Show whatever's in the frame buffer now
+    fn present(&mut self) -> Result<(), Self::Error>;
+}
+
+impl<W: HasRawWindowHandle> Presenter for Pixels<W> {
+    type Error = pixels::Error;
+    fn frame_buffer(&mut self) -> &mut [u8] {
+        self.get_frame()
+    }
+    fn present(&mut self) -> Result<(), Self::Error> {
+        self.render()
+    }
+}
+
+/// A Presenter that just keeps frames in memory, for running without a window or GPU
+/// and checking what got drawn. Like pixels, the buffer isn't cleared between frames.
+pub struct MemoryPresenter {
+    buffer: Vec<u8>,
+    presented: usize,
+}
+
+#[allow(dead_code)]
+impl MemoryPresenter {
+    pub fn new(width: usize, height: usize, depth: usize) -> Self {
+        Self {
+            buffer: vec![0; width * height * depth],
+            presented: 0,
+        }
+    }
+    /// How many times present has been called
+    pub fn frames_presented(&self) -> usize {
+        self.presented
+    }
+}
+
+impl Presenter for MemoryPresenter {
+    type Error = std::convert::Infallible;
+    fn frame_buffer(&mut self) -> &mut [u8] {
+        &mut self.buffer
+    }
+    fn present(&mut self) -> Result<(), Self::Error> {
+        self.presented += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::Screen;
+    use crate::types::{Rgba, Vec2i};
+
+    // Draws a frame the way the game does, without caring where it ends up
+    fn clear_and_present<P: Presenter>(presenter: &mut P, (w, h): (usize, usize), col: Rgba) {
+        Screen::wrap(presenter.frame_buffer(), w, h, 4, Vec2i(0, 0)).clear(col);
+        assert!(presenter.present().is_ok());
+    }
+
+    #[test]
+    fn memory_presenter_keeps_the_cleared_frame() {
+        let mut presenter = MemoryPresenter::new(3, 2, 4);
+        assert_eq!(presenter.frames_presented(), 0);
+        assert!(presenter.frame_buffer().iter().all(|&b| b == 0));
+        clear_and_present(&mut presenter, (3, 2), Rgba(10, 20, 30, 255));
+        assert_eq!(presenter.frames_presented(), 1);
+        let frame = presenter.frame_buffer();
+        assert_eq!(frame.len(), 3 * 2 * 4);
+        assert!(frame.chunks_exact(4).all(|p| p == [10, 20, 30, 255]));
+        clear_and_present(&mut presenter, (3, 2), Rgba(0, 0, 0, 0));
+        assert_eq!(presenter.frames_presented(), 2);
+    }
+}
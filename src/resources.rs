@@ -1,13 +1,266 @@
-use crate::texture::Texture;
+use crate::animation::{ms_to_secs, Animation};
+use crate::chiptune::Chiptune;
+use crate::texture::{LoadError, Texture};
+use crate::types::Rect;
+use image::error::{DecodingError, ImageError, ImageFormatHint};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::fs;
+use std::io;
 use std::path::Path;
 use std::rc::Rc;
 pub struct Resources();
 
+/// A sprite sheet plus the named animations cut out of it
+#[allow(dead_code)]
+pub struct Atlas {
+    pub texture: Rc<Texture>,
+    pub animations: HashMap<String, Rc<Animation>>,
+}
+
 impl Resources {
     pub fn new() -> Self {
         Self()
     }
-    pub fn load_texture(&self, p: impl AsRef<Path>) -> Rc<Texture> {
-        Rc::new(Texture::with_file(p.as_ref()))
+    pub fn load_texture(&self, p: impl AsRef<Path>) -> Result<Rc<Texture>, LoadError> {
+        Texture::try_with_file(p.as_ref()).map(Rc::new)
+    }
+    /// Load a texture, or if that fails, say why on stderr and use a
+    /// (w, h) placeholder checkerboard so the game can keep going
+    pub fn load_texture_or_placeholder(
+        &self,
+        p: impl AsRef<Path>,
+        (w, h): (u32, u32),
+    ) -> Rc<Texture> {
+        self.load_texture(p).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            Rc::new(Texture::placeholder(w, h))
+        })
+    }
+    /// Load every file in dir ending in .ext (like "png"), keyed by file name without the extension.
+    /// Files that aren't images or won't decode are reported on stderr and skipped,
+    /// so one bad file doesn't stop the rest from loading.
+    #[allow(dead_code)]
+    pub fn load_dir(&self, dir: &Path, ext: &str) -> HashMap<String, Rc<Texture>> {
+        let mut textures = HashMap::new();
+        let entries = match fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Couldn't read {}: {}", dir.display(), e);
+                return textures;
+            }
+        };
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            let ext_matches = |e: &OsStr| e.to_string_lossy().eq_ignore_ascii_case(ext);
+            let matches = matches!(path.extension(), Some(e) if ext_matches(e));
+            let stem = match path.file_stem() {
+                Some(stem) if matches && path.is_file() => stem.to_string_lossy().into_owned(),
+                _ => continue,
+            };
+            match self.load_texture(&path) {
+                Ok(tex) => {
+                    textures.insert(stem, tex);
+                }
+                Err(e) => eprintln!("Skipping: {}", e),
+            }
+        }
+        textures
+    }
+    /// Load a tune in the Chiptune text format; play it with Audio::play_sound(tune.source())
+    #[allow(dead_code)]
+    pub fn load_chiptune(&self, path: impl AsRef<Path>) -> io::Result<Chiptune> {
+        Chiptune::parse(&fs::read_to_string(path)?)
+    }
+    /// Load an Aseprite export: the sheet PNG and its JSON data file.
+    /// Every frame tag becomes an Animation under the tag's name.
+    /// Works with both the "Array" and "Hash" frame layouts.
+    #[allow(dead_code)]
+    pub fn load_aseprite(
+        &self,
+        png: impl AsRef<Path>,
+        json: impl AsRef<Path>,
+    ) -> Result<Rc<Atlas>, LoadError> {
+        let json = json.as_ref();
+        let animations = fs::read_to_string(json)
+            .map_err(ImageError::IoError)
+            .and_then(|text| {
+                parse_aseprite(&text).map_err(|e| {
+                    ImageError::Decoding(DecodingError::new(
+                        ImageFormatHint::Name("aseprite json".to_string()),
+                        e,
+                    ))
+                })
+            })
+            .map_err(|cause| LoadError {
+                path: json.to_path_buf(),
+                cause,
+            })?;
+        Ok(Rc::new(Atlas {
+            texture: self.load_texture(png)?,
+            animations,
+        }))
+    }
+}
+
+// The animations in an Aseprite json file, or what's wrong with it
+fn parse_aseprite(text: &str) -> Result<HashMap<String, Rc<Animation>>, String> {
+    let data: Value = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    // Array layout is a list of frames; hash layout maps filenames to frames in file order
+    let frames: Vec<&Value> = match &data["frames"] {
+        Value::Array(frames) => frames.iter().collect(),
+        Value::Object(frames) => frames.values().collect(),
+        _ => return Err("no frames".to_string()),
+    };
+    let frames = frames
+        .iter()
+        .map(|f| {
+            let r = &f["frame"];
+            let field = |name: &str| r[name].as_i64().ok_or("bad frame rect");
+            let rect = Rect {
+                x: field("x")? as i32,
+                y: field("y")? as i32,
+                w: field("w")? as u16,
+                h: field("h")? as u16,
+            };
+            let duration = f["duration"].as_f64().unwrap_or(100.0);
+            Ok((rect, ms_to_secs(duration)))
+        })
+        .collect::<Result<Vec<(Rect, f32)>, &str>>()?;
+    let mut animations = HashMap::new();
+    for tag in data["meta"]["frameTags"].as_array().into_iter().flatten() {
+        let name = tag["name"].as_str().ok_or("frame tag has no name")?;
+        let from = tag["from"].as_u64().ok_or("frame tag has no start")? as usize;
+        let to = tag["to"].as_u64().ok_or("frame tag has no end")? as usize;
+        if from > to || to >= frames.len() {
+            return Err(format!("frame tag {} out of range", name));
+        }
+        let mut order: Vec<usize> = (from..=to).collect();
+        match tag["direction"].as_str() {
+            Some("reverse") => order.reverse(),
+            // There and back again, without doubling up the end frames
+            Some("pingpong") => order.extend((from + 1..to).rev()),
+            _ => {}
+        }
+        let anim = Animation::with_timings(
+            order.iter().map(|&i| frames[i].0).collect(),
+            order.iter().map(|&i| frames[i].1).collect(),
+        );
+        animations.insert(name.to_string(), Rc::new(anim));
+    }
+    Ok(animations)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::Rgba;
+
+    #[test]
+    fn aseprite_tags_become_animations() {
+        for json in &["fixtures/king_array.json", "fixtures/king_hash.json"] {
+            let atlas = Resources::new()
+                .load_aseprite("king.png", json)
+                .unwrap_or_else(|e| panic!("{}", e));
+            assert_eq!(atlas.texture.size(), (32, 32));
+            assert_eq!(atlas.animations.len(), 2, "in {}", json);
+            let idle = &atlas.animations["idle"];
+            assert_eq!(idle.frames.len(), 2, "in {}", json);
+            assert_eq!(
+                idle.frames[1],
+                Rect {
+                    x: 16,
+                    y: 0,
+                    w: 16,
+                    h: 16
+                }
+            );
+            // Ping-pong over frames 1 to 3 goes 1, 2, 3, 2
+            let walk = &atlas.animations["walk"];
+            assert_eq!(walk.frames.len(), 4, "in {}", json);
+            assert_eq!(walk.frames[3], walk.frames[1]);
+        }
+    }
+    #[test]
+    fn broken_aseprite_json_is_an_error() {
+        let err = parse_aseprite(
+            r#"{"frames": [], "meta": {"frameTags": [{"name": "x", "from": 0, "to": 2}]}}"#,
+        );
+        assert!(err.is_err());
+        assert!(parse_aseprite("not json").is_err());
+        let missing = Resources::new().load_aseprite("king.png", "fixtures/nothing.json");
+        assert_eq!(
+            missing.err().map(|e| e.path),
+            Some(Path::new("fixtures/nothing.json").into())
+        );
+    }
+    #[test]
+    fn load_dir_skips_what_it_cant_load() {
+        let dir = std::env::temp_dir().join(format!("game2-load-dir-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let red = image::RgbaImage::from_pixel(2, 3, image::Rgba([255, 0, 0, 255]));
+        red.save(dir.join("red.png")).unwrap();
+        let blue = image::RgbaImage::from_pixel(4, 1, image::Rgba([0, 0, 255, 255]));
+        blue.save(dir.join("blue.PNG")).unwrap();
+        fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        fs::write(dir.join("broken.png"), "not an image either").unwrap();
+
+        let textures = Resources::new().load_dir(&dir, "png");
+        fs::remove_dir_all(&dir).unwrap();
+        let mut names: Vec<&str> = textures.keys().map(|k| k.as_str()).collect();
+        names.sort_unstable();
+        assert_eq!(names, vec!["blue", "red"]);
+        assert_eq!(textures["red"].size(), (2, 3));
+        assert_eq!(textures["blue"].size(), (4, 1));
+    }
+    #[test]
+    fn missing_textures_name_the_path() {
+        let res = Resources::new();
+        let path = Path::new("no/such/dir/typo.png");
+        let err = res.load_texture(path).err().unwrap();
+        assert_eq!(err.path, path);
+        let message = err.to_string();
+        assert!(message.contains("no/such/dir/typo.png"), "{}", message);
+        // Or carry on with the checkerboard
+        let stand_in = res.load_texture_or_placeholder(path, (16, 16));
+        assert_eq!(stand_in.size(), (16, 16));
+        let magenta = Rgba(255, 0, 255, 255);
+        let black = Rgba(0, 0, 0, 255);
+        assert_eq!(stand_in.pixel(0, 0), Some(magenta));
+        assert_eq!(stand_in.pixel(8, 0), Some(black));
+        assert_eq!(stand_in.pixel(8, 8), Some(magenta));
+    }
+    #[test]
+    fn chiptune_fixture_loads_in_start_order() {
+        use crate::chiptune::{NoteEvent, Wave};
+        let tune = Resources::new()
+            .load_chiptune("fixtures/jingle.tune")
+            .unwrap();
+        assert_eq!(tune.tempo, 4);
+        let note = |start, wave, pitch, length| NoteEvent {
+            start,
+            wave,
+            pitch,
+            length,
+        };
+        assert_eq!(
+            tune.notes,
+            vec![
+                note(0, Wave::Square, 60, 1),
+                note(0, Wave::Triangle, 48, 4),
+                note(1, Wave::Square, 64, 1),
+                note(2, Wave::Square, 67, 2),
+            ]
+        );
+        assert_eq!(tune.length(), 4);
+    }
+    #[test]
+    fn bad_chiptune_file_is_an_error() {
+        let rsrc = Resources::new();
+        let err = rsrc.load_chiptune("fixtures/broken.tune").unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("line 3:"), "{}", err);
+        let missing = rsrc.load_chiptune("fixtures/nothing.tune").unwrap_err();
+        assert_eq!(missing.kind(), io::ErrorKind::NotFound);
     }
 }
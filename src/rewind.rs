@@ -0,0 +1,71 @@
+use std::collections::VecDeque;
+
+/// The last few frames' worth of saved states, for stepping time backward.
+/// Once it's full, each new state pushes out the oldest.
+pub struct RewindBuffer<T> {
+    capacity: usize,
+    // Oldest at the front, newest at the back
+    states: VecDeque<T>,
+}
+
+impl<T> RewindBuffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "RewindBuffer needs room for at least one state");
+        Self {
+            capacity,
+            states: VecDeque::with_capacity(capacity),
+        }
+    }
+    /// Remember this frame's state
+    pub fn push(&mut self, state: T) {
+        if self.states.len() == self.capacity {
+            self.states.pop_front();
+        }
+        self.states.push_back(state);
+    }
+    /// Take back the most recent state, to restore it; None once we've run out of history
+    pub fn rewind_one(&mut self) -> Option<T> {
+        self.states.pop_back()
+    }
+    #[allow(dead_code)]
+    pub fn len(&self) -> usize {
+        self.states.len()
+    }
+    #[allow(dead_code)]
+    pub fn is_empty(&self) -> bool {
+        self.states.is_empty()
+    }
+    pub fn clear(&mut self) {
+        self.states.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rewinding_retraces_recorded_states() {
+        let mut buffer = RewindBuffer::new(10);
+        for frame in 0..5 {
+            buffer.push(frame * 10);
+        }
+        let rewound: Vec<i32> = std::iter::from_fn(|| buffer.rewind_one()).collect();
+        assert_eq!(rewound, vec![40, 30, 20, 10, 0]);
+        assert!(buffer.is_empty());
+        assert_eq!(buffer.rewind_one(), None);
+    }
+    #[test]
+    fn full_buffers_drop_the_oldest() {
+        let mut buffer = RewindBuffer::new(3);
+        for frame in 0..5 {
+            buffer.push(frame);
+        }
+        assert_eq!(buffer.len(), 3);
+        assert_eq!(buffer.rewind_one(), Some(4));
+        // Rewinding partway then playing on records from the rewound point
+        buffer.push(7);
+        let rewound: Vec<i32> = std::iter::from_fn(|| buffer.rewind_one()).collect();
+        assert_eq!(rewound, vec![7, 3, 2]);
+    }
+}
@@ -0,0 +1,62 @@
+/// A tiny xorshift random number generator.
+/// It's not good enough for anything serious, but the same seed always gives the
+/// same numbers, which is exactly what we want for replays and reproducible behavior.
+#[derive(Clone, Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at zero forever, so nudge that seed to something else
+        Self {
+            state: if seed == 0 { 0x9E37_79B9_7F4A_7C15 } else { seed },
+        }
+    }
+    pub fn next_u64(&mut self) -> u64 {
+        let mut x = self.state;
+        x ^= x << 13;
+        x ^= x >> 7;
+        x ^= x << 17;
+        self.state = x;
+        x
+    }
+    /// A float in [0, 1)
+    pub fn next_f32(&mut self) -> f32 {
+        // The top 24 bits fit exactly in an f32's mantissa
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+    /// An index in 0..n; n must not be 0
+    pub fn below(&mut self, n: usize) -> usize {
+        assert!(n > 0, "Rng::below needs a nonempty range");
+        (self.next_u64() % n as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_numbers() {
+        let mut a = Rng::new(42);
+        let mut b = Rng::new(42);
+        let mut c = Rng::new(43);
+        let xs: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        let ys: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+        let zs: Vec<u64> = (0..8).map(|_| c.next_u64()).collect();
+        assert_eq!(xs, ys);
+        assert_ne!(xs, zs);
+        // Zero would be stuck there forever
+        assert_ne!(Rng::new(0).next_u64(), 0);
+    }
+    #[test]
+    fn floats_and_indices_stay_in_range() {
+        let mut rng = Rng::new(7);
+        for _ in 0..1000 {
+            let f = rng.next_f32();
+            assert!((0.0..1.0).contains(&f));
+            assert!(rng.below(3) < 3);
+        }
+    }
+}
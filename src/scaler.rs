@@ -0,0 +1,555 @@
+use pixels::raw_window_handle::HasRawWindowHandle;
+use pixels::wgpu::util::DeviceExt;
+use pixels::{wgpu, Pixels, PixelsContext};
+use std::rc::Rc;
+
+use crate::presenter::Presenter;
+use crate::texture::Texture;
+use crate::types::Rgba;
+
+/// How the buffer_size framebuffer gets fit into a window of window_size:
+/// the largest whole-number scale that fits (never below 1x), centered, with bars around it.
+/// Returns the scale and the top-left corner of the scaled image in window pixels.
+pub fn letterbox((win_w, win_h): (u32, u32), (buf_w, buf_h): (u32, u32)) -> (u32, (i32, i32)) {
+    let scale = (win_w / buf_w).min(win_h / buf_h).max(1);
+    let offset_x = (win_w as i32 - (buf_w * scale) as i32) / 2;
+    let offset_y = (win_h as i32 - (buf_h * scale) as i32) / 2;
+    (scale, (offset_x, offset_y))
+}
+
+/// Which framebuffer pixel is under a spot in the window (in physical pixels),
+/// going by the same letterbox the frame is drawn with. None over the bars.
+#[allow(dead_code)]
+pub fn window_to_buffer(
+    (x, y): (f64, f64),
+    window_size: (u32, u32),
+    buffer_size: (u32, u32),
+) -> Option<(u32, u32)> {
+    let (scale, (ox, oy)) = letterbox(window_size, buffer_size);
+    let bx = ((x - ox as f64) / scale as f64).floor();
+    let by = ((y - oy as f64) / scale as f64).floor();
+    if bx < 0.0 || by < 0.0 || bx >= buffer_size.0 as f64 || by >= buffer_size.1 as f64 {
+        None
+    } else {
+        Some((bx as u32, by as u32))
+    }
+}
+
+// The shader's quad covers all of clip space (-1 to 1 each way), so this squashes it
+// down to the letterboxed image and moves it into place. Column-major, like GLSL wants.
+// The corner lands on a whole window pixel, which keeps every framebuffer pixel the same size.
+fn quad_transform(window_size: (u32, u32), buffer_size: (u32, u32)) -> [f32; 16] {
+    let (scale, (ox, oy)) = letterbox(window_size, buffer_size);
+    let (win_w, win_h) = (window_size.0 as f32, window_size.1 as f32);
+    let (img_w, img_h) = (
+        (buffer_size.0 * scale) as f32,
+        (buffer_size.1 * scale) as f32,
+    );
+    let (sw, sh) = (img_w / win_w, img_h / win_h);
+    // Where the middle of the image goes, in clip space (y goes up there, down in the window)
+    let cx = (2.0 * ox as f32 + img_w) / win_w - 1.0;
+    let cy = 1.0 - (2.0 * oy as f32 + img_h) / win_h;
+    // The texture's first row is the top, so the quad gets flipped vertically
+    #[rustfmt::skip]
+    let transform = [
+        sw,  0.0, 0.0, 0.0,
+        0.0, -sh, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        cx,  cy,  0.0, 1.0,
+    ];
+    transform
+}
+
+fn transform_bytes(transform: &[f32; 16]) -> Vec<u8> {
+    transform
+        .iter()
+        .flat_map(|f| f.to_ne_bytes().to_vec())
+        .collect()
+}
+
+/// What fills the window around the letterboxed image
+#[derive(Clone)]
+pub enum LetterboxStyle {
+    Solid(Rgba),
+    /// Repeated across the whole window from its top-left corner
+    #[allow(dead_code)]
+    Tiled(Rc<Texture>),
+}
+impl Default for LetterboxStyle {
+    fn default() -> Self {
+        LetterboxStyle::Solid(Rgba(0, 0, 0, 255))
+    }
+}
+
+/// A window_size rgba8888 image with the bars painted in style.
+/// The part the framebuffer covers is left transparent, since it gets drawn over anyway.
+pub fn bar_image(
+    style: &LetterboxStyle,
+    window_size: (u32, u32),
+    buffer_size: (u32, u32),
+) -> Vec<u8> {
+    let (win_w, win_h) = (window_size.0 as i32, window_size.1 as i32);
+    let (scale, (ox, oy)) = letterbox(window_size, buffer_size);
+    let (img_w, img_h) = (
+        (buffer_size.0 * scale) as i32,
+        (buffer_size.1 * scale) as i32,
+    );
+    let mut image = vec![0; (win_w * win_h * 4) as usize];
+    for y in 0..win_h {
+        for x in 0..win_w {
+            if ox <= x && x < ox + img_w && oy <= y && y < oy + img_h {
+                continue;
+            }
+            let Rgba(r, g, b, a) = match style {
+                LetterboxStyle::Solid(col) => *col,
+                LetterboxStyle::Tiled(tex) => {
+                    let (tw, th) = (tex.width() as i32, tex.height() as i32);
+                    tex.pixel_clamped(x % tw, y % th)
+                }
+            };
+            let i = ((y * win_w + x) * 4) as usize;
+            image[i..(i + 4)].copy_from_slice(&[r, g, b, a]);
+        }
+    }
+    image
+}
+
+/// How the framebuffer's pixels get stretched over the window
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ScalingFilter {
+    /// Every framebuffer pixel becomes a crisp square block, for pixel art
+    #[default]
+    Nearest,
+    /// Neighboring pixels blend together, for smoother-looking hi-res content
+    Linear,
+}
+impl ScalingFilter {
+    /// The sampler the scaled image is drawn with
+    pub fn sampler_descriptor(self) -> wgpu::SamplerDescriptor<'static> {
+        let filter = match self {
+            ScalingFilter::Nearest => wgpu::FilterMode::Nearest,
+            ScalingFilter::Linear => wgpu::FilterMode::Linear,
+        };
+        wgpu::SamplerDescriptor {
+            label: Some("scaler_sampler"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: filter,
+            min_filter: filter,
+            mipmap_filter: filter,
+            ..Default::default()
+        }
+    }
+}
+
+// The bars as a window-sized texture, drawn across the whole window under the framebuffer
+struct Bars {
+    // Kept alive for the bind group's sake
+    _texture: wgpu::Texture,
+    bind_group: wgpu::BindGroup,
+}
+
+/// Draws the framebuffer onto the window, placed by letterbox.
+/// This does the same job as pixels' own scaling renderer (and uses its shaders),
+/// but since we position the image ourselves, we know exactly where it ends up.
+pub struct Scaler {
+    buffer_size: (u32, u32),
+    transform: wgpu::Buffer,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    pipeline: wgpu::RenderPipeline,
+    style: LetterboxStyle,
+    // Bars don't get scaled, so they always sample 1:1
+    bar_sampler: wgpu::Sampler,
+    // A quad covering the whole window, for drawing the bars
+    bar_transform: wgpu::Buffer,
+    bars: Option<Bars>,
+}
+
+impl Scaler {
+    pub fn new(
+        context: &PixelsContext,
+        window_size: (u32, u32),
+        style: LetterboxStyle,
+        filter: ScalingFilter,
+    ) -> Self {
+        let device = &context.device;
+        let buffer_size = (context.texture_extent.width, context.texture_extent.height);
+        let vs_module = device.create_shader_module(wgpu::include_spirv!("../shaders/vert.spv"));
+        let fs_module = device.create_shader_module(wgpu::include_spirv!("../shaders/frag.spv"));
+        let sampler = device.create_sampler(&filter.sampler_descriptor());
+        let bar_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("scaler_bar_sampler"),
+            ..Default::default()
+        });
+        let transform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("scaler_transform"),
+            contents: &transform_bytes(&quad_transform(window_size, buffer_size)),
+            usage: wgpu::BufferUsage::UNIFORM | wgpu::BufferUsage::COPY_DST,
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("scaler_bind_group_layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::SampledTexture {
+                        component_type: wgpu::TextureComponentType::Float,
+                        multisampled: false,
+                        dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStage::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler { comparison: false },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStage::VERTEX,
+                    ty: wgpu::BindingType::UniformBuffer {
+                        dynamic: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        let texture_view = context
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = bind_quad(
+            device,
+            &bind_group_layout,
+            &texture_view,
+            &sampler,
+            &transform,
+        );
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("scaler_pipeline_layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("scaler_pipeline"),
+            layout: Some(&pipeline_layout),
+            vertex_stage: wgpu::ProgrammableStageDescriptor {
+                module: &vs_module,
+                entry_point: "main",
+            },
+            fragment_stage: Some(wgpu::ProgrammableStageDescriptor {
+                module: &fs_module,
+                entry_point: "main",
+            }),
+            rasterization_state: Some(wgpu::RasterizationStateDescriptor {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: wgpu::CullMode::None,
+                clamp_depth: false,
+                depth_bias: 0,
+                depth_bias_slope_scale: 0.0,
+                depth_bias_clamp: 0.0,
+            }),
+            primitive_topology: wgpu::PrimitiveTopology::TriangleList,
+            color_states: &[wgpu::ColorStateDescriptor {
+                // What pixels makes its swap chains with
+                format: wgpu::TextureFormat::Bgra8UnormSrgb,
+                color_blend: wgpu::BlendDescriptor::REPLACE,
+                alpha_blend: wgpu::BlendDescriptor::REPLACE,
+                write_mask: wgpu::ColorWrite::ALL,
+            }],
+            depth_stencil_state: None,
+            vertex_state: wgpu::VertexStateDescriptor {
+                index_format: wgpu::IndexFormat::Uint16,
+                vertex_buffers: &[],
+            },
+            sample_count: 1,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+        });
+        // A window-sized image at 1x with no offset is exactly the whole window
+        let bar_transform = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("scaler_bar_transform"),
+            contents: &transform_bytes(&quad_transform((1, 1), (1, 1))),
+            usage: wgpu::BufferUsage::UNIFORM,
+        });
+        let mut scaler = Self {
+            buffer_size,
+            transform,
+            bind_group_layout,
+            bind_group,
+            pipeline,
+            style,
+            bar_sampler,
+            bar_transform,
+            bars: None,
+        };
+        scaler.build_bars(context, window_size);
+        scaler
+    }
+    /// Call when the window changes size (in physical pixels) so the image gets re-placed
+    pub fn resize(&mut self, context: &PixelsContext, window_size: (u32, u32)) {
+        let transform = quad_transform(window_size, self.buffer_size);
+        context
+            .queue
+            .write_buffer(&self.transform, 0, &transform_bytes(&transform));
+        self.build_bars(context, window_size);
+    }
+    /// Switch how the image is scaled up, starting with the next frame
+    pub fn set_filter(&mut self, context: &PixelsContext, filter: ScalingFilter) {
+        let sampler = context.device.create_sampler(&filter.sampler_descriptor());
+        let texture_view = context
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        self.bind_group = bind_quad(
+            &context.device,
+            &self.bind_group_layout,
+            &texture_view,
+            &sampler,
+            &self.transform,
+        );
+    }
+    // Paint the bars for this window size and upload them
+    fn build_bars(&mut self, context: &PixelsContext, window_size: (u32, u32)) {
+        // Minimized windows are 0x0, and there's nothing to see anyway
+        if window_size.0 == 0 || window_size.1 == 0 {
+            return;
+        }
+        let device = &context.device;
+        let size = wgpu::Extent3d {
+            width: window_size.0,
+            height: window_size.1,
+            depth: 1,
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("scaler_bar_texture"),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsage::SAMPLED | wgpu::TextureUsage::COPY_DST,
+        });
+        context.queue.write_texture(
+            wgpu::TextureCopyView {
+                texture: &texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+            },
+            &bar_image(&self.style, window_size, self.buffer_size),
+            wgpu::TextureDataLayout {
+                offset: 0,
+                bytes_per_row: window_size.0 * 4,
+                rows_per_image: window_size.1,
+            },
+            size,
+        );
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let bind_group = bind_quad(
+            device,
+            &self.bind_group_layout,
+            &view,
+            &self.bar_sampler,
+            &self.bar_transform,
+        );
+        self.bars = Some(Bars {
+            _texture: texture,
+            bind_group,
+        });
+    }
+    /// Paint the bars over the whole window and draw the framebuffer into its letterbox
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder, target: &wgpu::TextureView) {
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            color_attachments: &[wgpu::RenderPassColorAttachmentDescriptor {
+                attachment: target,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            }],
+            depth_stencil_attachment: None,
+        });
+        pass.set_pipeline(&self.pipeline);
+        if let Some(bars) = &self.bars {
+            pass.set_bind_group(0, &bars.bind_group, &[]);
+            pass.draw(0..6, 0..1);
+        }
+        pass.set_bind_group(0, &self.bind_group, &[]);
+        // Two triangles make the quad
+        pass.draw(0..6, 0..1);
+    }
+}
+
+// Everything the shaders need to draw one textured quad
+fn bind_quad(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+    transform: &wgpu::Buffer,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("scaler_bind_group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: wgpu::BindingResource::Buffer(transform.slice(..)),
+            },
+        ],
+    })
+}
+
+/// Pixels drawing through a Scaler, so the window shows the frame where letterbox says
+pub struct ScaledPixels<W: HasRawWindowHandle> {
+    pixels: Pixels<W>,
+    scaler: Scaler,
+}
+
+impl<W: HasRawWindowHandle> ScaledPixels<W> {
+    pub fn new(
+        pixels: Pixels<W>,
+        window_size: (u32, u32),
+        style: LetterboxStyle,
+        filter: ScalingFilter,
+    ) -> Self {
+        let scaler = Scaler::new(pixels.context(), window_size, style, filter);
+        Self { pixels, scaler }
+    }
+    /// The window is now this many physical pixels
+    pub fn resize(&mut self, width: u32, height: u32) {
+        // pixels still needs to know, to rebuild its swap chain
+        self.pixels.resize(width, height);
+        self.scaler.resize(self.pixels.context(), (width, height));
+    }
+    /// Change the scaling filter in place; the window and everything else stays as it is
+    pub fn set_filter(&mut self, filter: ScalingFilter) {
+        self.scaler.set_filter(self.pixels.context(), filter);
+    }
+}
+
+impl<W: HasRawWindowHandle> Presenter for ScaledPixels<W> {
+    type Error = pixels::Error;
+    fn frame_buffer(&mut self) -> &mut [u8] {
+        self.pixels.get_frame()
+    }
+    fn present(&mut self) -> Result<(), Self::Error> {
+        let scaler = &self.scaler;
+        self.pixels
+            .render_with(|encoder, target, _context| scaler.render(encoder, target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Where the transform puts a corner of the quad, in clip space
+    fn apply(t: &[f32; 16], (x, y): (f32, f32)) -> (f32, f32) {
+        (t[0] * x + t[4] * y + t[12], t[1] * x + t[5] * y + t[13])
+    }
+
+    #[test]
+    fn letterbox_exact_fit() {
+        assert_eq!(letterbox((700, 550), (700, 550)), (1, (0, 0)));
+        assert_eq!(letterbox((1400, 1100), (700, 550)), (2, (0, 0)));
+    }
+    #[test]
+    fn letterbox_wide_window_gets_side_bars() {
+        // Height allows 2x, so the extra width is split between left and right
+        assert_eq!(letterbox((1600, 1100), (700, 550)), (2, (100, 0)));
+    }
+    #[test]
+    fn letterbox_tall_window_gets_top_and_bottom_bars() {
+        assert_eq!(letterbox((1400, 1300), (700, 550)), (2, (0, 100)));
+        // Not quite 2x either way rounds down to 1x, centered
+        assert_eq!(letterbox((1000, 1000), (700, 550)), (1, (150, 225)));
+    }
+    #[test]
+    fn letterbox_never_shrinks_below_1x() {
+        // Hangs off both sides instead
+        assert_eq!(letterbox((600, 500), (700, 550)), (1, (-50, -25)));
+    }
+    #[test]
+    fn quad_lands_on_the_letterbox() {
+        // 1x in a 1000x1000 window starts at (150, 225): -0.7 to 0.7 across, 0.55 to -0.55 down
+        let t = quad_transform((1000, 1000), (700, 550));
+        let (x0, y0) = apply(&t, (-1.0, -1.0));
+        let (x1, y1) = apply(&t, (1.0, 1.0));
+        assert!((x0 + 0.7).abs() < 1e-6 && (x1 - 0.7).abs() < 1e-6);
+        // Flipped, so the quad's bottom (where the texture's last row is) is up top
+        assert!((y0 - 0.55).abs() < 1e-6 && (y1 + 0.55).abs() < 1e-6);
+    }
+    #[test]
+    fn window_spots_map_to_buffer_pixels() {
+        let (win, buf) = ((1600, 1100), (700, 550));
+        assert_eq!(window_to_buffer((50.0, 500.0), win, buf), None);
+        assert_eq!(window_to_buffer((100.0, 0.0), win, buf), Some((0, 0)));
+        assert_eq!(
+            window_to_buffer((1499.0, 1099.0), win, buf),
+            Some((699, 549))
+        );
+        assert_eq!(window_to_buffer((1500.0, 10.0), win, buf), None);
+    }
+    #[test]
+    fn bars_get_the_configured_color() {
+        let teal = Rgba(0, 128, 128, 255);
+        // A 4x2 buffer at 1x in a 6x4 window: one column of bar each side, one row top and bottom
+        let image = bar_image(&LetterboxStyle::Solid(teal), (6, 4), (4, 2));
+        let at = |x: usize, y: usize| {
+            let i = (y * 6 + x) * 4;
+            Rgba(image[i], image[i + 1], image[i + 2], image[i + 3])
+        };
+        for y in 0..4 {
+            for x in 0..6 {
+                let in_image = (1..5).contains(&x) && (1..3).contains(&y);
+                let expected = if in_image { Rgba(0, 0, 0, 0) } else { teal };
+                assert_eq!(at(x, y), expected, "at ({}, {})", x, y);
+            }
+        }
+        // Black unless told otherwise
+        let image = bar_image(&LetterboxStyle::default(), (6, 4), (4, 2));
+        assert_eq!(&image[..4], &[0, 0, 0, 255]);
+    }
+    #[test]
+    fn tiled_bars_repeat_from_the_corner() {
+        let mut img = image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 0, 0, 255]));
+        img.put_pixel(1, 0, image::Rgba([0, 0, 255, 255]));
+        let style = LetterboxStyle::Tiled(Rc::new(Texture::new(img)));
+        // The buffer fills the window's middle two rows, leaving a bar above and below
+        let image = bar_image(&style, (5, 4), (5, 2));
+        let reds: Vec<u8> = image.chunks_exact(4).take(5).map(|p| p[0]).collect();
+        assert_eq!(reds, vec![255, 0, 255, 0, 255]);
+        assert_eq!(&image[5 * 4..5 * 4 + 4], &[0, 0, 0, 0]);
+        assert_eq!(&image[3 * 5 * 4 + 4..3 * 5 * 4 + 8], &[0, 0, 255, 255]);
+    }
+    #[test]
+    fn filters_pick_the_sampler() {
+        use wgpu::FilterMode::*;
+        let filters = |d: wgpu::SamplerDescriptor| (d.mag_filter, d.min_filter, d.mipmap_filter);
+        assert_eq!(ScalingFilter::default(), ScalingFilter::Nearest);
+        assert_eq!(
+            filters(ScalingFilter::Nearest.sampler_descriptor()),
+            (Nearest, Nearest, Nearest)
+        );
+        assert_eq!(
+            filters(ScalingFilter::Linear.sampler_descriptor()),
+            (Linear, Linear, Linear)
+        );
+        // Either way, the image's edge pixels don't bleed in from the other side
+        let linear = ScalingFilter::Linear.sampler_descriptor();
+        assert_eq!(linear.address_mode_u, wgpu::AddressMode::ClampToEdge);
+        assert_eq!(linear.address_mode_v, wgpu::AddressMode::ClampToEdge);
+    }
+}
@@ -1,6 +1,11 @@
 // We can pull in definitions from elsewhere in the crate!
+use crate::text::{layout_text, Align, Font};
 use crate::texture::Texture;
 use crate::types::{Rect, Rgba, Vec2i};
+// How many flat colors a dithered gradient is made of
+const GRADIENT_BANDS: usize = 8;
+// Ordered dithering thresholds (out of 16)
+const BAYER_4X4: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
 pub struct Screen<'fb> {
     framebuffer: &'fb mut [u8],
     pub width: usize,
@@ -17,6 +22,15 @@ impl<'fb> Screen<'fb> {
         depth: usize,
         position: Vec2i,
     ) -> Self {
+        // Catch a mismatched WIDTH/HEIGHT here instead of as a slice panic at the bottom-right edge
+        assert_eq!(
+            framebuffer.len(),
+            width * height * depth,
+            "Framebuffer isn't {}x{}x{}",
+            width,
+            height,
+            depth
+        );
         Self {
             framebuffer,
             width,
@@ -36,6 +50,20 @@ impl<'fb> Screen<'fb> {
             h: self.height as u16,
         }
     }
+    /// A copy of everything drawn so far as a texture the size of the screen,
+    /// for freeze-frames and transitions. Scrolling doesn't matter: the texture's
+    /// top-left is the screen's top-left.
+    #[allow(dead_code)]
+    pub fn capture(&self) -> Texture {
+        Texture::from_premultiplied(self.framebuffer.to_vec(), self.width, self.height, self.depth)
+    }
+
+    // Scroll to scroll, but keep the view inside world_bounds so we never show what's past the edge.
+    // If the world is narrower (or shorter) than the screen, it gets centered on that axis instead.
+    #[allow(dead_code)]
+    pub fn set_scroll_clamped(&mut self, scroll: Vec2i, world_bounds: Rect) {
+        self.position = clamp_scroll(scroll, (self.width, self.height), world_bounds);
+    }
 
     // Our old, slow friend draw_at, now with super scrolling powers!
     #[inline(always)]
@@ -54,7 +82,27 @@ impl<'fb> Screen<'fb> {
         let idx = idx as usize;
         self.framebuffer[idx..(idx + self.depth)].copy_from_slice(&c);
     }
+    // Same result as calling draw_at for each point, but the scroll offset, bounds,
+    // and depth check happen once for the whole batch instead of once per pixel.
+    // Handy for particles and other effects with lots of single pixels.
+    #[allow(dead_code)]
+    pub fn draw_points(&mut self, points: &[(Vec2i, Rgba)]) {
+        assert_eq!(self.depth, 4);
+        let Vec2i(ox, oy) = self.position;
+        let (w, h) = (self.width as i32, self.height as i32);
+        let pitch = self.width * 4;
+        for &(Vec2i(x, y), col) in points {
+            let (x, y) = (x - ox, y - oy);
+            if x < 0 || w <= x || y < 0 || h <= y {
+                continue;
+            }
+            let idx = y as usize * pitch + x as usize * 4;
+            self.framebuffer[idx..(idx + 4)].copy_from_slice(&[col.0, col.1, col.2, col.3]);
+        }
+    }
     // Clear's the same...
+    // Every pixel gets the bytes [r, g, b, a], which is the rgba8888 layout pixels expects.
+    #[allow(dead_code)]
     pub fn clear(&mut self, col: Rgba) {
         let c = [col.0, col.1, col.2, col.3];
         for px in self.framebuffer.chunks_exact_mut(4) {
@@ -83,6 +131,96 @@ impl<'fb> Screen<'fb> {
             }
         }
     }
+    // Fill a bunch of rects, each in its own color, in order. Same pixels as calling rect
+    // for each, but the scroll offset and screen size are only looked up once.
+    pub fn fill_rects(&mut self, rects: &[(Rect, Rgba)]) {
+        let Vec2i(ox, oy) = self.position;
+        let (w, h) = (self.width as i32, self.height as i32);
+        let depth = self.depth;
+        let pitch = self.width * depth;
+        for &(r, col) in rects {
+            let c = [col.0, col.1, col.2, col.3];
+            let (x, y) = (r.x - ox, r.y - oy);
+            let x0 = x.max(0).min(w) as usize;
+            let x1 = (x + r.w as i32).max(0).min(w) as usize;
+            let y0 = y.max(0).min(h) as usize;
+            let y1 = (y + r.h as i32).max(0).min(h) as usize;
+            for row in self.framebuffer[(y0 * pitch)..(y1 * pitch)].chunks_exact_mut(pitch) {
+                for p in row[(x0 * depth)..(x1 * depth)].chunks_exact_mut(depth) {
+                    p.copy_from_slice(&c);
+                }
+            }
+        }
+    }
+    // A health/progress bar: all of r in bg, then the left fraction (0 to 1) of it in fill.
+    // The filled width rounds down, so the bar only looks full when it really is.
+    #[allow(dead_code)]
+    pub fn draw_bar(&mut self, r: Rect, fraction: f32, fill: Rgba, bg: Rgba) {
+        self.rect(r, bg);
+        let w = (r.w as f32 * fraction.clamp(0.0, 1.0)) as u16;
+        self.rect(Rect { w, ..r }, fill);
+    }
+    // Same, but filling from the bottom up
+    #[allow(dead_code)]
+    pub fn draw_bar_vertical(&mut self, r: Rect, fraction: f32, fill: Rgba, bg: Rgba) {
+        self.rect(r, bg);
+        let h = (r.h as f32 * fraction.clamp(0.0, 1.0)) as u16;
+        let y = r.y + (r.h - h) as i32;
+        self.rect(Rect { y, h, ..r }, fill);
+    }
+    // A vertical gradient down r from top to bottom, for skies and backgrounds.
+    // The first row is exactly top and the last exactly bottom. Without dither each row is
+    // just the blend for its height; with dither the gradient is cut into GRADIENT_BANDS
+    // flat bands and the rows between two bands mix them in a 4x4 Bayer pattern,
+    // which hides banding and looks properly retro. The pattern follows world space,
+    // so it doesn't crawl when the screen scrolls.
+    #[allow(dead_code)]
+    pub fn fill_gradient(&mut self, r: Rect, top: Rgba, bottom: Rgba, dither: bool) {
+        let lerp = |t: f32| {
+            let ch = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+            [
+                ch(top.0, bottom.0),
+                ch(top.1, bottom.1),
+                ch(top.2, bottom.2),
+                ch(top.3, bottom.3),
+            ]
+        };
+        let Vec2i(ox, oy) = self.position;
+        let x0 = (r.x - ox).max(0).min(self.width as i32);
+        let x1 = (r.x + r.w as i32 - ox).max(0).min(self.width as i32);
+        let y0 = (r.y - oy).max(0).min(self.height as i32);
+        let y1 = (r.y + r.h as i32 - oy).max(0).min(self.height as i32);
+        let depth = self.depth;
+        let pitch = self.width * depth;
+        for y in y0..y1 {
+            // How far down the gradient this row is, from 0 to 1
+            let row = y + oy - r.y;
+            let t = if r.h > 1 {
+                row as f32 / (r.h - 1) as f32
+            } else {
+                0.0
+            };
+            let line = &mut self.framebuffer[(y as usize * pitch)..((y as usize + 1) * pitch)];
+            let span = &mut line[(x0 as usize * depth)..(x1 as usize * depth)];
+            if !dither {
+                let c = lerp(t);
+                for p in span.chunks_exact_mut(depth) {
+                    p.copy_from_slice(&c);
+                }
+                continue;
+            }
+            let bands = GRADIENT_BANDS as f32;
+            let band = (t * bands).floor();
+            let frac = t * bands - band;
+            let lower = lerp(band / bands);
+            let upper = lerp(((band + 1.0) / bands).min(1.0));
+            for (x, p) in (x0..x1).zip(span.chunks_exact_mut(depth)) {
+                let (bx, by) = ((x + ox).rem_euclid(4), (y + oy).rem_euclid(4));
+                let threshold = (BAYER_4X4[by as usize][bx as usize] as f32 + 0.5) / 16.0;
+                p.copy_from_slice(if frac > threshold { &upper } else { &lower });
+            }
+        }
+    }
     // Ditto line
     pub fn line(&mut self, Vec2i(x0, y0): Vec2i, Vec2i(x1, y1): Vec2i, col: Rgba) {
         let col = [col.0, col.1, col.2, col.3];
@@ -102,7 +240,7 @@ impl<'fb> Screen<'fb> {
         let mut err = dx + dy;
         let width = self.width as i32;
         let height = self.height as i32;
-        while x != x1 || y != y1 {
+        loop {
             if 0 <= x && x < width && 0 <= y && y < height {
                 // TODO this bounds check could in theory be avoided with
                 // the unsafe get_unchecked, but maybe better not...
@@ -112,6 +250,10 @@ impl<'fb> Screen<'fb> {
                 // We couldn't just clamp x0/y0 and x1/y1 into bounds, because then
                 // we might change the slope of the line.
             }
+            // Check after drawing so the last pixel gets drawn too
+            if x == x1 && y == y1 {
+                break;
+            }
             let e2 = 2 * err;
             if dy <= e2 {
                 err += dy;
@@ -123,13 +265,143 @@ impl<'fb> Screen<'fb> {
             }
         }
     }
+    // Solid triangle. Walks the triangle's bounding box (clipped to the screen) and fills
+    // every pixel that's on the inside of all three edges.
+    #[allow(dead_code)]
+    pub fn filled_triangle(&mut self, a: Vec2i, b: Vec2i, c: Vec2i, col: Rgba) {
+        let col = [col.0, col.1, col.2, col.3];
+        // translate translate translate
+        let t = |Vec2i(x, y): Vec2i| (x - self.position.0, y - self.position.1);
+        let (a, mut b, mut c) = (t(a), t(b), t(c));
+        // Positive when p is to the left of the edge from p0 to p1
+        let edge = |p0: (i32, i32), p1: (i32, i32), p: (i32, i32)| {
+            (p1.0 - p0.0) as i64 * (p.1 - p0.1) as i64 - (p1.1 - p0.1) as i64 * (p.0 - p0.0) as i64
+        };
+        let area = edge(a, b, c);
+        // Collinear points, nothing to fill
+        if area == 0 {
+            return;
+        }
+        // Wind them one consistent way so "inside" always means all edges non-negative
+        if area < 0 {
+            std::mem::swap(&mut b, &mut c);
+        }
+        let x0 = a.0.min(b.0).min(c.0).max(0);
+        let x1 = a.0.max(b.0).max(c.0).min(self.width as i32 - 1);
+        let y0 = a.1.min(b.1).min(c.1).max(0);
+        let y1 = a.1.max(b.1).max(c.1).min(self.height as i32 - 1);
+        let depth = self.depth;
+        let pitch = self.width * depth;
+        for y in y0..=y1 {
+            for x in x0..=x1 {
+                let p = (x, y);
+                if edge(a, b, p) >= 0 && edge(b, c, p) >= 0 && edge(c, a, p) >= 0 {
+                    let idx = y as usize * pitch + x as usize * depth;
+                    self.framebuffer[idx..(idx + depth)].copy_from_slice(&col);
+                }
+            }
+        }
+    }
+    // Anti-aliased line (Xiaolin Wu's algorithm).
+    // Each step along the line splits the color between the two pixels the ideal line
+    // runs between, weighted by how close it is to each; straight lines stay crisp.
+    #[allow(dead_code)]
+    pub fn line_aa(&mut self, Vec2i(x0, y0): Vec2i, Vec2i(x1, y1): Vec2i, col: Rgba) {
+        // translate translate
+        let (mut x0, mut y0) = (x0 - self.position.0, y0 - self.position.1);
+        let (mut x1, mut y1) = (x1 - self.position.0, y1 - self.position.1);
+        // Always step along the longer axis, left to right
+        let steep = (y1 - y0).abs() > (x1 - x0).abs();
+        if steep {
+            std::mem::swap(&mut x0, &mut y0);
+            std::mem::swap(&mut x1, &mut y1);
+        }
+        if x0 > x1 {
+            std::mem::swap(&mut x0, &mut x1);
+            std::mem::swap(&mut y0, &mut y1);
+        }
+        let dx = x1 - x0;
+        let gradient = if dx == 0 {
+            0.0
+        } else {
+            (y1 - y0) as f32 / dx as f32
+        };
+        for x in x0..=x1 {
+            let y = y0 as f32 + gradient * (x - x0) as f32;
+            let yi = y.floor() as i32;
+            let frac = y - yi as f32;
+            if steep {
+                self.blend_at(yi, x, col, 1.0 - frac);
+                self.blend_at(yi + 1, x, col, frac);
+            } else {
+                self.blend_at(x, yi, col, 1.0 - frac);
+                self.blend_at(x, yi + 1, col, frac);
+            }
+        }
+    }
+    // Blend col over the pixel at (x, y) in framebuffer coordinates, scaled by coverage (0 to 1).
+    // Anything off screen is ignored, so callers can clip by just calling this.
+    fn blend_at(&mut self, x: i32, y: i32, col: Rgba, coverage: f32) {
+        if coverage <= 0.0 || x < 0 || (self.width as i32) <= x || y < 0 || (self.height as i32) <= y
+        {
+            return;
+        }
+        // Premultiply so we can use the same over operator as bitblt
+        let a = col.3 as f32 / 255.0 * coverage.min(1.0);
+        let src = [
+            (col.0 as f32 * a).round() as u8,
+            (col.1 as f32 * a).round() as u8,
+            (col.2 as f32 * a).round() as u8,
+            (a * 255.0).round() as u8,
+        ];
+        let idx = (y as usize * self.width + x as usize) * self.depth;
+        composite(&mut self.framebuffer[idx..(idx + self.depth)], &src);
+    }
+    // Flood fill: repaint the 4-connected region around start that matches start's color.
+    // Uses an explicit stack instead of recursion so big regions can't blow the call stack.
+    #[allow(dead_code)]
+    pub fn flood_fill(&mut self, Vec2i(x, y): Vec2i, fill: Rgba) {
+        // Same translation as everything else
+        let x = x - self.position.0;
+        let y = y - self.position.1;
+        let width = self.width as i32;
+        let height = self.height as i32;
+        if x < 0 || width <= x || y < 0 || height <= y {
+            return;
+        }
+        let depth = self.depth;
+        let fill = [fill.0, fill.1, fill.2, fill.3];
+        let pitch = self.width * depth;
+        let idx = |x: i32, y: i32| y as usize * pitch + x as usize * depth;
+        let start = idx(x, y);
+        let mut target = [0; 4];
+        target.copy_from_slice(&self.framebuffer[start..(start + depth)]);
+        // Already the right color, and filling would never terminate anyway
+        if target == fill {
+            return;
+        }
+        let mut stack = vec![(x, y)];
+        while let Some((x, y)) = stack.pop() {
+            if x < 0 || width <= x || y < 0 || height <= y {
+                continue;
+            }
+            let i = idx(x, y);
+            let px = &mut self.framebuffer[i..(i + depth)];
+            if px != target {
+                continue;
+            }
+            px.copy_from_slice(&fill);
+            stack.push((x + 1, y));
+            stack.push((x - 1, y));
+            stack.push((x, y + 1));
+            stack.push((x, y - 1));
+        }
+    }
     // Bitblt too begins with a translation
     pub fn bitblt(&mut self, src: &Texture, from: Rect, Vec2i(to_x, to_y): Vec2i) {
-        let (tw, th) = src.size();
-        assert!(0 <= from.x);
-        assert!(from.x < tw as i32);
-        assert!(0 <= from.y);
-        assert!(from.y < th as i32);
+        // The whole source rect has to be inside the texture, not just its corner,
+        // or the row slices below run off the end
+        assert!(src.valid_frame(from), "Blit source rect outside texture");
         let to_x = to_x - self.position.0;
         let to_y = to_y - self.position.1;
         if (to_x + from.w as i32) < 0
@@ -166,15 +438,740 @@ impl<'fb> Screen<'fb> {
             let from_cols = row_a
                 [(depth * (from.x + x_skip) as usize)..(depth * (from.x + x_count) as usize)]
                 .chunks_exact(depth);
-            // Composite over, assume premultiplied rgba8888
             for (to, from) in to_cols.zip(from_cols) {
-                let ta = to[3] as f32 / 255.0;
-                let fa = from[3] as f32 / 255.0;
-                for i in 0..3 {
-                    to[i] = from[i].saturating_add((to[i] as f32 * (1.0 - fa)).round() as u8);
+                composite(to, from);
+            }
+        }
+    }
+    // Stretch (or squash) the from rect of src to exactly fill the to rect, nearest-neighbor style.
+    // The two rects can have different shapes, so this scales each axis separately.
+    #[allow(dead_code)]
+    pub fn bitblt_rect(&mut self, src: &Texture, from: Rect, to: Rect) {
+        assert!(src.valid_frame(from));
+        assert_eq!(self.depth, src.depth());
+        self.stretch_from(src.buffer(), src.size(), from, to);
+    }
+    // Same as bitblt_rect, but the pixels come from another screen (say, a HUD drawn offscreen
+    // at 1x). from is in src's own pixels, ignoring its scroll, and whatever part of it
+    // hangs off src is just left out.
+    #[allow(dead_code)]
+    pub fn bitblt_screen(&mut self, src: &Screen, from: Rect, to: Rect) {
+        assert_eq!(self.depth, src.depth);
+        self.stretch_from(src.framebuffer, (src.width, src.height), from, to);
+    }
+    // The guts of bitblt_rect: src is a whole image of size src_size with our depth
+    fn stretch_from(&mut self, src: &[u8], (src_w, src_h): (usize, usize), from: Rect, to: Rect) {
+        if from.w == 0 || from.h == 0 || to.w == 0 || to.h == 0 {
+            return;
+        }
+        let depth = self.depth;
+        let to_x = to.x - self.position.0;
+        let to_y = to.y - self.position.1;
+        // Only visit destination pixels that are actually on screen
+        let x0 = to_x.max(0);
+        let x1 = (to_x + to.w as i32).min(self.width as i32);
+        let y0 = to_y.max(0);
+        let y1 = (to_y + to.h as i32).min(self.height as i32);
+        let src_pitch = src_w * depth;
+        for y in y0..y1 {
+            let sy = from.y + (y - to_y) * from.h as i32 / to.h as i32;
+            if sy < 0 || src_h as i32 <= sy {
+                continue;
+            }
+            for x in x0..x1 {
+                let sx = from.x + (x - to_x) * from.w as i32 / to.w as i32;
+                if sx < 0 || src_w as i32 <= sx {
+                    continue;
+                }
+                let si = sy as usize * src_pitch + sx as usize * depth;
+                let di = (y as usize * self.width + x as usize) * depth;
+                composite(&mut self.framebuffer[di..(di + depth)], &src[si..(si + depth)]);
+            }
+        }
+    }
+    // Like bitblt, but multiplies every source pixel by tint first.
+    // Opaque white is a plain blit; lowering tint's alpha fades the whole image out,
+    // and darker colors darken it.
+    pub fn bitblt_tinted(&mut self, src: &Texture, from: Rect, Vec2i(to_x, to_y): Vec2i, tint: Rgba) {
+        assert!(src.valid_frame(from));
+        let depth = self.depth;
+        assert_eq!(depth, src.depth());
+        let to_x = to_x - self.position.0;
+        let to_y = to_y - self.position.1;
+        let scale = tint_scale(tint);
+        let src_pitch = src.pitch();
+        let src_buf = src.buffer();
+        for sy in 0..from.h as i32 {
+            let y = to_y + sy;
+            if y < 0 || (self.height as i32) <= y {
+                continue;
+            }
+            for sx in 0..from.w as i32 {
+                let x = to_x + sx;
+                if x < 0 || (self.width as i32) <= x {
+                    continue;
+                }
+                let si = (from.y + sy) as usize * src_pitch + (from.x + sx) as usize * depth;
+                let px = apply_tint(&src_buf[si..(si + depth)], scale);
+                let di = (y as usize * self.width + x as usize) * depth;
+                composite(&mut self.framebuffer[di..(di + depth)], &px);
+            }
+        }
+    }
+    // Like bitblt, but mirrored and/or transposed on the way.
+    // The flags work like Tiled's: transpose (swap x and y) first, then flip horizontally, then vertically.
+    // This goes pixel by pixel, so keep plain bitblt for the common unflipped case.
+    pub fn bitblt_flipped(
+        &mut self,
+        src: &Texture,
+        from: Rect,
+        Vec2i(to_x, to_y): Vec2i,
+        flip_x: bool,
+        flip_y: bool,
+        transpose: bool,
+    ) {
+        let white = Rgba(255, 255, 255, 255);
+        let flips = (flip_x, flip_y, transpose);
+        self.bitblt_flipped_tinted(src, from, Vec2i(to_x, to_y), flips, white);
+    }
+    // bitblt_flipped and bitblt_tinted at once; flips is (flip_x, flip_y, transpose)
+    pub fn bitblt_flipped_tinted(
+        &mut self,
+        src: &Texture,
+        from: Rect,
+        Vec2i(to_x, to_y): Vec2i,
+        (flip_x, flip_y, transpose): (bool, bool, bool),
+        tint: Rgba,
+    ) {
+        assert!(src.valid_frame(from));
+        let depth = self.depth;
+        assert_eq!(depth, src.depth());
+        let to_x = to_x - self.position.0;
+        let to_y = to_y - self.position.1;
+        // Transposing swaps the shape of what lands on screen
+        let (w, h) = if transpose {
+            (from.h as i32, from.w as i32)
+        } else {
+            (from.w as i32, from.h as i32)
+        };
+        let scale = tint_scale(tint);
+        let src_pitch = src.pitch();
+        let src_buf = src.buffer();
+        for dy in 0..h {
+            let y = to_y + dy;
+            if y < 0 || (self.height as i32) <= y {
+                continue;
+            }
+            for dx in 0..w {
+                let x = to_x + dx;
+                if x < 0 || (self.width as i32) <= x {
+                    continue;
+                }
+                // Undo the flips in reverse order to find the source pixel
+                let fy = if flip_y { h - 1 - dy } else { dy };
+                let fx = if flip_x { w - 1 - dx } else { dx };
+                let (sx, sy) = if transpose { (fy, fx) } else { (fx, fy) };
+                let si = (from.y + sy) as usize * src_pitch + (from.x + sx) as usize * depth;
+                let px = apply_tint(&src_buf[si..(si + depth)], scale);
+                let di = (y as usize * self.width + x as usize) * depth;
+                composite(&mut self.framebuffer[di..(di + depth)], &px);
+            }
+        }
+    }
+    // Word-wrap text into r, line up each line according to align, and draw it in color.
+    // Lines that don't fit in r's height are left off rather than spilling out the bottom.
+    #[allow(dead_code)]
+    pub fn draw_text_wrapped(
+        &mut self,
+        font: &Font,
+        text: &str,
+        r: Rect,
+        align: Align,
+        color: Rgba,
+    ) {
+        let lines = layout_text(text, r, font.line_height, align, |c| font.advance(c));
+        for (Vec2i(mut x, y), line) in lines {
+            for c in line.chars() {
+                if let Some(glyph) = font.glyph(c) {
+                    self.bitblt_tinted(&font.texture, glyph, Vec2i(x, y), color);
+                    x += glyph.w as i32;
                 }
-                to[3] = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
             }
         }
     }
 }
+
+// Per-channel multipliers for tinting a premultiplied pixel.
+// Since texture pixels are premultiplied, the tint's alpha scales color and alpha alike.
+fn tint_scale(tint: Rgba) -> [f32; 4] {
+    let ta = tint.3 as f32 / 255.0;
+    [
+        tint.0 as f32 / 255.0 * ta,
+        tint.1 as f32 / 255.0 * ta,
+        tint.2 as f32 / 255.0 * ta,
+        ta,
+    ]
+}
+
+#[inline(always)]
+fn apply_tint(px: &[u8], scale: [f32; 4]) -> [u8; 4] {
+    let mut out = [0; 4];
+    for i in 0..4 {
+        out[i] = (px[i] as f32 * scale[i]).round() as u8;
+    }
+    out
+}
+
+// Anything that knows how to put itself on a Screen, so a scene can be a list of
+// mixed things (Vec<Box<dyn Drawable>>) drawn back to front.
+pub trait Drawable {
+    fn draw(&self, screen: &mut Screen);
+}
+
+/// An ordered list of draw steps, back to front, so sprites can go between tile layers
+/// (floor, then the player, then treetops over them) and UI goes on top of it all.
+/// Steps are borrowed, so build a fresh one each frame.
+#[derive(Default)]
+pub struct Scene<'a> {
+    steps: Vec<&'a dyn Drawable>,
+}
+#[allow(dead_code)]
+impl<'a> Scene<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Add a step in front of everything added so far
+    pub fn push(&mut self, step: &'a dyn Drawable) -> &mut Self {
+        self.steps.push(step);
+        self
+    }
+    pub fn draw(&self, screen: &mut Screen) {
+        for step in self.steps.iter() {
+            step.draw(screen);
+        }
+    }
+}
+
+// Where a view of size view would end up scrolled to if it wanted to be at scroll but had to
+// stay inside world_bounds (see Screen::set_scroll_clamped).
+pub fn clamp_scroll(Vec2i(x, y): Vec2i, (w, h): (usize, usize), world_bounds: Rect) -> Vec2i {
+    let clamp = |v: i32, start: i32, world: u16, screen: usize| {
+        let room = world as i32 - screen as i32;
+        if room <= 0 {
+            start + room / 2
+        } else {
+            v.max(start).min(start + room)
+        }
+    };
+    Vec2i(
+        clamp(x, world_bounds.x, world_bounds.w, w),
+        clamp(y, world_bounds.y, world_bounds.h, h),
+    )
+}
+
+// Would something of the given size at pos show up at all on a screen of screen_size scrolled to camera?
+// Checks the whole footprint, so things hanging partly off the edge still count.
+pub fn is_on_screen(
+    Vec2i(x, y): Vec2i,
+    (w, h): (usize, usize),
+    Vec2i(cx, cy): Vec2i,
+    (sw, sh): (usize, usize),
+) -> bool {
+    x + (w as i32) > cx && x < cx + sw as i32 && y + (h as i32) > cy && y < cy + sh as i32
+}
+
+// A post-process for LoopConfig::post_process: blacks out every other row of a w pixel wide
+// rgba8888 frame for a CRT look. Alpha is left alone.
+#[allow(dead_code)]
+pub fn scanlines(buffer: &mut [u8], w: usize, _h: usize) {
+    for row in buffer.chunks_exact_mut(w * 4).skip(1).step_by(2) {
+        for px in row.chunks_exact_mut(4) {
+            px[..3].copy_from_slice(&[0, 0, 0]);
+        }
+    }
+}
+
+// Composite from over to, assume premultiplied rgba8888
+#[inline(always)]
+fn composite(to: &mut [u8], from: &[u8]) {
+    let ta = to[3] as f32 / 255.0;
+    let fa = from[3] as f32 / 255.0;
+    for i in 0..3 {
+        to[i] = from[i].saturating_add((to[i] as f32 * (1.0 - fa)).round() as u8);
+    }
+    to[3] = ((fa + ta * (1.0 - fa)) * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Rgba = Rgba(0, 0, 0, 255);
+    const WHITE: Rgba = Rgba(255, 255, 255, 255);
+    const RED: Rgba = Rgba(255, 0, 0, 255);
+
+    // The color at (x, y) of a w pixel wide rgba8888 framebuffer
+    fn px(fb: &[u8], w: usize, x: usize, y: usize) -> Rgba {
+        let i = (y * w + x) * 4;
+        Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+    }
+
+    #[test]
+    fn clear_writes_rgba_bytes() {
+        let mut fb = vec![0_u8; 3 * 2 * 4];
+        let mut screen = Screen::wrap(&mut fb, 3, 2, 4, Vec2i(0, 0));
+        screen.clear(Rgba::from_u32(0x4411_2233));
+        assert!(fb.chunks_exact(4).all(|px| px == [0x11, 0x22, 0x33, 0x44]));
+    }
+    #[test]
+    fn flood_fill_stays_inside_a_box() {
+        let mut fb = vec![0_u8; 10 * 10 * 4];
+        let mut screen = Screen::wrap(&mut fb, 10, 10, 4, Vec2i(0, 0));
+        screen.clear(BLACK);
+        // A white outline from (2, 2) to (7, 7)
+        for &(x, y, w, h) in &[(2, 2, 6, 1), (2, 7, 6, 1), (2, 2, 1, 6), (7, 2, 1, 6)] {
+            screen.rect(Rect { x, y, w, h }, WHITE);
+        }
+        screen.flood_fill(Vec2i(4, 5), RED);
+        for y in 0..10 {
+            for x in 0..10 {
+                let inside = (3..7).contains(&x) && (3..7).contains(&y);
+                let border = (2..8).contains(&x) && (2..8).contains(&y) && !inside;
+                let expected = if inside {
+                    RED
+                } else if border {
+                    WHITE
+                } else {
+                    BLACK
+                };
+                assert_eq!(px(&fb, 10, x, y), expected, "at ({}, {})", x, y);
+            }
+        }
+    }
+    #[test]
+    fn flood_fill_stops_at_the_edges() {
+        let mut fb = vec![0_u8; 5 * 3 * 4];
+        let mut screen = Screen::wrap(&mut fb, 5, 3, 4, Vec2i(0, 0));
+        screen.clear(BLACK);
+        screen.flood_fill(Vec2i(0, 0), RED);
+        // Off-screen starts are ignored
+        screen.flood_fill(Vec2i(-1, 0), WHITE);
+        assert!(fb.chunks_exact(4).all(|c| c == [255, 0, 0, 255]));
+    }
+    #[test]
+    fn shallow_aa_line_has_soft_edges() {
+        let mut fb = vec![0_u8; 10 * 5 * 4];
+        let mut screen = Screen::wrap(&mut fb, 10, 5, 4, Vec2i(0, 0));
+        screen.line_aa(Vec2i(0, 0), Vec2i(9, 3), WHITE);
+        let alphas: Vec<u8> = fb.chunks_exact(4).map(|p| p[3]).collect();
+        // The ends sit exactly on pixels
+        assert_eq!((alphas[0], alphas[3 * 10 + 9]), (255, 255));
+        // In between, coverage is split across two rows
+        let partial = alphas.iter().filter(|&&a| 0 < a && a < 255).count();
+        assert!(partial >= 8, "Only {} partly covered pixels", partial);
+        // Every column got the same total coverage, give or take rounding
+        for x in 0..10 {
+            let total: u32 = (0..5).map(|y| alphas[y * 10 + x] as u32).sum();
+            assert!((254..=256).contains(&total), "Column {} has {}", x, total);
+        }
+    }
+    #[test]
+    fn straight_aa_lines_stay_crisp_and_clip() {
+        let mut fb = vec![0_u8; 6 * 6 * 4];
+        let mut screen = Screen::wrap(&mut fb, 6, 6, 4, Vec2i(0, 0));
+        screen.line_aa(Vec2i(-3, 1), Vec2i(10, 1), WHITE);
+        screen.line_aa(Vec2i(4, -2), Vec2i(4, 9), WHITE);
+        for y in 0..6 {
+            for x in 0..6 {
+                let expected = if y == 1 || x == 4 {
+                    WHITE
+                } else {
+                    Rgba(0, 0, 0, 0)
+                };
+                assert_eq!(px(&fb, 6, x, y), expected, "at ({}, {})", x, y);
+            }
+        }
+    }
+    #[test]
+    fn wrapped_text_is_centered_in_its_box() {
+        // 'a' is a solid 2x2 block, ' ' a blank one
+        let mut img = image::RgbaImage::new(4, 2);
+        for y in 0..2 {
+            for x in 0..2 {
+                img.put_pixel(x, y, image::Rgba([255, 255, 255, 255]));
+            }
+        }
+        let glyphs = [('a', 0), (' ', 2)]
+            .iter()
+            .map(|&(c, x)| {
+                (
+                    c,
+                    Rect {
+                        x,
+                        y: 0,
+                        w: 2,
+                        h: 2,
+                    },
+                )
+            })
+            .collect();
+        let font = Font::new(std::rc::Rc::new(Texture::new(img)), glyphs, 2);
+        let mut fb = vec![0_u8; 8 * 6 * 4];
+        let mut screen = Screen::wrap(&mut fb, 8, 6, 4, Vec2i(0, 0));
+        // "a a" fills a line, the lone "a" after it is centered, and the rest is clipped
+        let r = Rect {
+            x: 1,
+            y: 0,
+            w: 6,
+            h: 4,
+        };
+        screen.draw_text_wrapped(&font, "a a a aaaa", r, Align::Center, RED);
+        let lit = |x: usize, y: usize| px(&fb, 8, x, y) == RED;
+        for y in 0..6 {
+            for x in 0..8 {
+                let expected = match y {
+                    0 | 1 => x == 1 || x == 2 || x == 5 || x == 6,
+                    2 | 3 => x == 3 || x == 4,
+                    _ => false,
+                };
+                assert_eq!(lit(x, y), expected, "at ({}, {})", x, y);
+            }
+        }
+    }
+    #[test]
+    fn sprites_just_off_the_right_edge_are_culled() {
+        let camera = Vec2i(100, 0);
+        let screen_size = (320, 240);
+        // The camera shows x from 100 up to (not including) 420
+        assert!(!is_on_screen(Vec2i(420, 10), (16, 16), camera, screen_size));
+        // Straddling the edge: only the leftmost column is visible
+        assert!(is_on_screen(Vec2i(419, 10), (16, 16), camera, screen_size));
+        // Same on the left, where the sprite's width decides it
+        assert!(!is_on_screen(Vec2i(84, 10), (16, 16), camera, screen_size));
+        assert!(is_on_screen(Vec2i(85, 10), (16, 16), camera, screen_size));
+    }
+    #[test]
+    fn right_triangle_fills_its_half_of_the_square() {
+        let mut fb = vec![0_u8; 8 * 8 * 4];
+        let mut screen = Screen::wrap(&mut fb, 8, 8, 4, Vec2i(0, 0));
+        // Legs of 3: the pixels with x + y <= 3, edges included
+        screen.filled_triangle(Vec2i(1, 1), Vec2i(4, 1), Vec2i(1, 4), RED);
+        let filled = fb
+            .chunks_exact(4)
+            .filter(|p| p == &[255, 0, 0, 255])
+            .count();
+        assert_eq!(filled, 4 + 3 + 2 + 1);
+        assert_eq!(px(&fb, 8, 4, 1), RED);
+        assert_eq!(px(&fb, 8, 2, 3), RED);
+        assert_eq!(px(&fb, 8, 3, 3), Rgba(0, 0, 0, 0));
+    }
+    #[test]
+    fn triangles_scroll_clip_and_skip_degenerates() {
+        let mut fb = vec![0_u8; 4 * 4 * 4];
+        let mut screen = Screen::wrap(&mut fb, 4, 4, 4, Vec2i(10, 10));
+        screen.filled_triangle(Vec2i(10, 10), Vec2i(13, 13), Vec2i(16, 16), RED);
+        // Hangs off the top left; with the scroll only its tip reaches the screen
+        screen.filled_triangle(Vec2i(5, 5), Vec2i(16, 5), Vec2i(5, 16), RED);
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected = if x + y <= 1 { RED } else { Rgba(0, 0, 0, 0) };
+                assert_eq!(px(&fb, 4, x, y), expected, "at ({}, {})", x, y);
+            }
+        }
+    }
+    #[test]
+    fn scroll_clamps_at_every_edge() {
+        let mut fb = vec![0_u8; 10 * 8 * 4];
+        let mut screen = Screen::wrap(&mut fb, 10, 8, 4, Vec2i(0, 0));
+        let world = Rect {
+            x: 5,
+            y: 5,
+            w: 30,
+            h: 20,
+        };
+        screen.set_scroll_clamped(Vec2i(-10, 9), world);
+        assert_eq!(screen.position, Vec2i(5, 9));
+        screen.set_scroll_clamped(Vec2i(100, 9), world);
+        assert_eq!(screen.position, Vec2i(25, 9));
+        screen.set_scroll_clamped(Vec2i(9, 0), world);
+        assert_eq!(screen.position, Vec2i(9, 5));
+        screen.set_scroll_clamped(Vec2i(9, 100), world);
+        assert_eq!(screen.position, Vec2i(9, 17));
+        // Inside already, so left alone
+        screen.set_scroll_clamped(Vec2i(12, 7), world);
+        assert_eq!(screen.position, Vec2i(12, 7));
+    }
+    #[test]
+    fn small_worlds_are_centered() {
+        // 6 wide and 4 tall in a 10x8 view: centered on both axes wherever we ask for
+        let world = Rect {
+            x: 20,
+            y: 0,
+            w: 6,
+            h: 4,
+        };
+        let centered = Vec2i(20 - 2, -2);
+        assert_eq!(clamp_scroll(Vec2i(0, 0), (10, 8), world), centered);
+        assert_eq!(clamp_scroll(Vec2i(99, -99), (10, 8), world), centered);
+    }
+    #[test]
+    fn one_pixel_stretches_over_the_whole_dest() {
+        let img = image::RgbaImage::from_pixel(1, 1, image::Rgba([255, 0, 0, 255]));
+        let tex = Texture::new(img);
+        let mut fb = vec![0_u8; 6 * 4 * 4];
+        let mut screen = Screen::wrap(&mut fb, 6, 4, 4, Vec2i(0, 0));
+        let to = Rect {
+            x: 1,
+            y: 1,
+            w: 4,
+            h: 2,
+        };
+        screen.bitblt_rect(&tex, tex.bounds(), to);
+        for y in 0..4 {
+            for x in 0..6 {
+                let inside = (1..5).contains(&x) && (1..3).contains(&y);
+                let expected = if inside { RED } else { Rgba(0, 0, 0, 0) };
+                assert_eq!(px(&fb, 6, x, y), expected, "at ({}, {})", x, y);
+            }
+        }
+    }
+    #[test]
+    fn stretched_blits_keep_their_proportions_when_clipped() {
+        // Left half red, right half white, stretched 2x and hanging off the left edge
+        let mut img = image::RgbaImage::from_pixel(2, 1, image::Rgba([255, 255, 255, 255]));
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        let tex = Texture::new(img);
+        let mut fb = vec![0_u8; 4 * 4];
+        let mut screen = Screen::wrap(&mut fb, 4, 1, 4, Vec2i(0, 0));
+        let to = Rect {
+            x: -1,
+            y: 0,
+            w: 4,
+            h: 1,
+        };
+        screen.bitblt_rect(&tex, tex.bounds(), to);
+        let row: Vec<Rgba> = (0..4).map(|x| px(&fb, 4, x, 0)).collect();
+        assert_eq!(row, vec![RED, WHITE, WHITE, Rgba(0, 0, 0, 0)]);
+    }
+    #[test]
+    fn every_pixel_of_an_odd_screen_is_addressable() {
+        let (w, h) = (7, 3);
+        let mut fb = vec![0_u8; w * h * 4];
+        let mut screen = Screen::wrap(&mut fb, w, h, 4, Vec2i(0, 0));
+        screen.clear(BLACK);
+        // A different color in every cell, written one pixel at a time
+        let color = |x: usize, y: usize| Rgba(x as u8 * 30, y as u8 * 80, 1, 255);
+        for y in 0..h {
+            for x in 0..w {
+                let r = Rect {
+                    x: x as i32,
+                    y: y as i32,
+                    w: 1,
+                    h: 1,
+                };
+                screen.rect(r, color(x, y));
+            }
+        }
+        for y in 0..h {
+            for x in 0..w {
+                assert_eq!(px(&fb, w, x, y), color(x, y), "at ({}, {})", x, y);
+            }
+        }
+        // Blits land in all four corners, including the last row and column
+        let mut screen = Screen::wrap(&mut fb, w, h, 4, Vec2i(0, 0));
+        screen.clear(BLACK);
+        let tex = Texture::new(image::RgbaImage::from_pixel(
+            1,
+            1,
+            image::Rgba([255, 0, 0, 255]),
+        ));
+        let from = Rect {
+            x: 0,
+            y: 0,
+            w: 1,
+            h: 1,
+        };
+        let corners = [(0, 0), (w - 1, 0), (0, h - 1), (w - 1, h - 1)];
+        for &(x, y) in &corners {
+            screen.bitblt(&tex, from, Vec2i(x as i32, y as i32));
+        }
+        let reds = fb
+            .chunks_exact(4)
+            .filter(|p| *p == [255, 0, 0, 255])
+            .count();
+        assert_eq!(reds, 4);
+        for &(x, y) in &corners {
+            assert_eq!(px(&fb, w, x, y), RED);
+        }
+        // Blits hanging off the bottom right keep just their on-screen part
+        let mut screen = Screen::wrap(&mut fb, w, h, 4, Vec2i(0, 0));
+        let big = Texture::new(image::RgbaImage::from_pixel(3, 3, image::Rgba([255; 4])));
+        let from = Rect {
+            x: 0,
+            y: 0,
+            w: 3,
+            h: 3,
+        };
+        screen.bitblt(&big, from, Vec2i(w as i32 - 1, h as i32 - 1));
+        assert_eq!(px(&fb, w, w - 1, h - 1), WHITE);
+        assert_eq!(px(&fb, w, w - 2, h - 1), BLACK);
+    }
+    #[test]
+    fn draw_points_matches_drawing_one_at_a_time() {
+        // Scrolled, with points on every edge, off every edge, and drawn over twice
+        let points: Vec<(Vec2i, Rgba)> = (-3..12)
+            .flat_map(|x| (-2..9).map(move |y| (x, y)))
+            .filter(|(x, y)| (x * 7 + y * 3) % 4 == 0)
+            .map(|(x, y)| {
+                (
+                    Vec2i(x, y),
+                    Rgba((x + 3) as u8 * 15, (y + 2) as u8 * 20, 7, 255),
+                )
+            })
+            .chain(vec![(Vec2i(2, 1), RED), (Vec2i(2, 1), WHITE)])
+            .collect();
+        let scroll = Vec2i(1, 1);
+        let mut batched = vec![0_u8; 9 * 5 * 4];
+        Screen::wrap(&mut batched, 9, 5, 4, scroll).draw_points(&points);
+        let mut one_by_one = vec![0_u8; 9 * 5 * 4];
+        let mut screen = Screen::wrap(&mut one_by_one, 9, 5, 4, scroll);
+        for &(pos, col) in &points {
+            screen.draw_at(col, pos);
+        }
+        assert_eq!(batched, one_by_one);
+        assert!(batched.chunks_exact(4).any(|p| p[3] != 0));
+        // The last point drawn wins
+        assert_eq!(px(&batched, 9, 1, 0), WHITE);
+    }
+    #[test]
+    fn half_a_bar_fills_half_its_width() {
+        let mut fb = vec![0_u8; 10 * 4 * 4];
+        let mut screen = Screen::wrap(&mut fb, 10, 4, 4, Vec2i(0, 0));
+        let bar = Rect {
+            x: 0,
+            y: 0,
+            w: 10,
+            h: 4,
+        };
+        screen.draw_bar(bar, 0.5, RED, BLACK);
+        for x in 0..10 {
+            assert_eq!(
+                px(&fb, 10, x, 3),
+                if x < 5 { RED } else { BLACK },
+                "x {}",
+                x
+            );
+        }
+        // Vertical bars fill from the bottom, and too much is just full
+        let mut screen = Screen::wrap(&mut fb, 10, 4, 4, Vec2i(0, 0));
+        screen.draw_bar_vertical(bar, 0.5, WHITE, BLACK);
+        for y in 0..4 {
+            assert_eq!(
+                px(&fb, 10, 9, y),
+                if y < 2 { BLACK } else { WHITE },
+                "y {}",
+                y
+            );
+        }
+        let mut screen = Screen::wrap(&mut fb, 10, 4, 4, Vec2i(0, 0));
+        screen.draw_bar(bar, 3.0, RED, BLACK);
+        assert_eq!(px(&fb, 10, 9, 0), RED);
+    }
+    #[test]
+    fn fill_rects_matches_filling_one_at_a_time() {
+        let at = |x, y, w, h| Rect { x, y, w, h };
+        // Overlapping, hanging off every edge, and one entirely off screen
+        let rects = [
+            (at(0, 0, 4, 3), RED),
+            (at(2, 1, 4, 4), WHITE),
+            (at(-3, 2, 5, 2), BLACK),
+            (at(6, -2, 9, 3), RED),
+            (at(20, 20, 2, 2), WHITE),
+        ];
+        let scroll = Vec2i(-1, 1);
+        let mut batched = vec![0_u8; 8 * 5 * 4];
+        Screen::wrap(&mut batched, 8, 5, 4, scroll).fill_rects(&rects);
+        let mut one_by_one = vec![0_u8; 8 * 5 * 4];
+        let mut screen = Screen::wrap(&mut one_by_one, 8, 5, 4, scroll);
+        for &(r, c) in rects.iter() {
+            screen.rect(r, c);
+        }
+        assert_eq!(batched, one_by_one);
+        assert_eq!(px(&batched, 8, 3, 1), WHITE);
+    }
+    #[test]
+    fn capturing_copies_the_framebuffer_whatever_the_scroll() {
+        let mut fb = vec![0_u8; 5 * 3 * 4];
+        let mut screen = Screen::wrap(&mut fb, 5, 3, 4, Vec2i(10, 20));
+        screen.clear(WHITE);
+        screen.rect(
+            Rect {
+                x: 11,
+                y: 21,
+                w: 2,
+                h: 1,
+            },
+            RED,
+        );
+        let shot = screen.capture();
+        assert_eq!(shot.size(), (5, 3));
+        assert_eq!(shot.buffer(), &fb[..]);
+        assert_eq!(shot.pixel(1, 1), Some(RED));
+        assert_eq!(shot.pixel(0, 0), Some(WHITE));
+    }
+    #[test]
+    fn gradients_hit_both_ends_and_dither_in_between() {
+        // 17 rows, so each row is a sixteenth of the way down and every other one is a band
+        let r = Rect {
+            x: 0,
+            y: 0,
+            w: 4,
+            h: 17,
+        };
+        for &dither in &[false, true] {
+            let mut fb = vec![0_u8; 4 * 17 * 4];
+            Screen::wrap(&mut fb, 4, 17, 4, Vec2i(0, 0)).fill_gradient(r, BLACK, WHITE, dither);
+            for x in 0..4 {
+                assert_eq!(px(&fb, 4, x, 0), BLACK);
+                assert_eq!(px(&fb, 4, x, 16), WHITE);
+            }
+            let second_row: Vec<u8> = (0..4).map(|x| px(&fb, 4, x, 1).0).collect();
+            if dither {
+                // Halfway between the first two bands, so half the pixels each
+                assert_eq!(second_row, vec![0, 32, 0, 32]);
+            } else {
+                assert_eq!(second_row, vec![16; 4]);
+            }
+        }
+    }
+    #[test]
+    fn part_of_an_offscreen_hud_stretches_onto_the_screen() {
+        let at = |x, y, w, h| Rect { x, y, w, h };
+        // A 4x2 HUD, red on the left half and white on the right; its scroll doesn't matter
+        let mut hud_fb = vec![0_u8; 4 * 2 * 4];
+        let mut hud = Screen::wrap(&mut hud_fb, 4, 2, 4, Vec2i(50, 50));
+        hud.rect(at(50, 50, 2, 2), RED);
+        hud.rect(at(52, 50, 2, 2), WHITE);
+
+        let mut fb = vec![0_u8; 6 * 6 * 4];
+        let mut screen = Screen::wrap(&mut fb, 6, 6, 4, Vec2i(0, 0));
+        // The white half at twice the size, hanging off the bottom right of the screen
+        screen.bitblt_screen(&hud, at(2, 0, 2, 2), at(3, 3, 4, 4));
+        for y in 0..6 {
+            for x in 0..6 {
+                let expected = if x >= 3 && y >= 3 {
+                    WHITE
+                } else {
+                    Rgba(0, 0, 0, 0)
+                };
+                assert_eq!(px(&fb, 6, x, y), expected, "at {:?}", (x, y));
+            }
+        }
+        // Asking for more than the HUD has leaves the rest alone
+        let mut fb = vec![0_u8; 6 * 6 * 4];
+        let mut screen = Screen::wrap(&mut fb, 6, 6, 4, Vec2i(0, 0));
+        screen.bitblt_screen(&hud, at(1, 0, 4, 1), at(0, 0, 4, 1));
+        let row: Vec<Rgba> = (0..4).map(|x| px(&fb, 6, x, 0)).collect();
+        assert_eq!(row, vec![RED, WHITE, WHITE, Rgba(0, 0, 0, 0)]);
+    }
+}
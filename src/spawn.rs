@@ -0,0 +1,64 @@
+/// Things scheduled to show up on particular frames, like enemy waves.
+/// T is whatever describes what to spawn; the queue just hands them back on time.
+pub struct SpawnQueue<T> {
+    // Kept sorted by frame (ties stay in the order they were pushed),
+    // so the ready ones are always at the front
+    entries: Vec<(usize, T)>,
+}
+
+#[allow(dead_code)]
+impl<T> SpawnQueue<T> {
+    pub fn new() -> Self {
+        Self { entries: vec![] }
+    }
+    /// Schedule spec to come out of drain_ready once the frame counter reaches frame
+    pub fn push(&mut self, frame: usize, spec: T) {
+        let at = self.entries.partition_point(|(f, _)| *f <= frame);
+        self.entries.insert(at, (frame, spec));
+    }
+    /// Take out everything due on or before frame, earliest first.
+    /// Anything we're already late for comes out too, and nothing comes out twice.
+    pub fn drain_ready(&mut self, frame: usize) -> Vec<T> {
+        let ready = self.entries.partition_point(|(f, _)| *f <= frame);
+        self.entries.drain(..ready).map(|(_, spec)| spec).collect()
+    }
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_fire_once_on_or_after_their_frame() {
+        let mut queue = SpawnQueue::new();
+        queue.push(10, "second wave");
+        queue.push(5, "first wave");
+        queue.push(10, "second wave, late arrival");
+        queue.push(30, "boss");
+        assert!(queue.drain_ready(4).is_empty());
+        assert_eq!(queue.drain_ready(5), vec!["first wave"]);
+        // Checking again on the same frame doesn't repeat anything
+        assert!(queue.drain_ready(5).is_empty());
+        // Skipped straight past frame 10: both of its entries still come out, in order
+        assert_eq!(
+            queue.drain_ready(12),
+            vec!["second wave", "second wave, late arrival"]
+        );
+        assert_eq!(queue.len(), 1);
+        assert_eq!(queue.drain_ready(100), vec!["boss"]);
+        assert!(queue.is_empty());
+    }
+    #[test]
+    fn past_due_entries_fire_on_the_first_check() {
+        let mut queue = SpawnQueue::new();
+        queue.push(0, 'a');
+        queue.push(3, 'b');
+        assert_eq!(queue.drain_ready(60), vec!['a', 'b']);
+    }
+}
@@ -1,6 +1,7 @@
-use crate::animation::Animation;
+use crate::animation::{Animation, TIME_SLACK};
+use crate::collision;
 use crate::texture::Texture;
-use crate::types::{Rect, Vec2i};
+use crate::types::{Rect, Rgba, Vec2i};
 use std::rc::Rc;
 use std::sync::Arc;
 
@@ -9,8 +10,24 @@ pub struct Sprite {
     pub animation: Rc<Animation>, // Maybe better to use a type that can't have a negative origin
     // Or use =animation:Animation= instead of a frame field
     pub current_frame: Rect,
-    pub elapsed_time: usize,
+    // Seconds into the current animation's loop
+    pub elapsed_time: f32,
     pub position: Vec2i,
+    /// Draw order for draw_sprites: higher layers go on top (say 0 for the world, 10 for HUD)
+    pub layer: i32,
+    /// Draw mirrored left to right, e.g. to face left with art drawn facing right
+    pub flip_x: bool,
+    // Set while crossfading out of a previous animation
+    blend: Option<Blend>,
+}
+
+// The outgoing side of a crossfade
+struct Blend {
+    // Frame of the old animation we're fading out of (it holds still while fading)
+    from_frame: Rect,
+    // How long the whole crossfade lasts and how much of it is left, in seconds
+    duration: f32,
+    remaining: f32,
 }
 
 impl Sprite {
@@ -18,7 +35,7 @@ impl Sprite {
         image: &Rc<Texture>,
         animation: &Rc<Animation>,
         current_frame: Rect,
-        elapsed_time: usize,
+        elapsed_time: f32,
         position: Vec2i,
     ) -> Self {
         Self {
@@ -27,29 +44,363 @@ impl Sprite {
             current_frame,
             elapsed_time,
             position,
+            layer: 0,
+            flip_x: false,
+            blend: None,
         }
     }
 
-    pub fn update_anim(&mut self) {
-        if self.elapsed_time < 1 {
-            self.current_frame = self.animation.frames[1];
-            self.elapsed_time += 1;
+    /// Switch to target, fading the current frame out and target's first frame in over
+    /// the next `frames` ticks' worth of time. Once the fade is done it's just playing target.
+    #[allow(dead_code)]
+    pub fn blend_to(&mut self, target: &Rc<Animation>, frames: u32) {
+        let secs = frames as f32 * crate::DT as f32;
+        self.blend = if frames == 0 {
+            None
         } else {
-            self.current_frame = self.animation.frames[0];
-            self.elapsed_time -= 1;
+            Some(Blend {
+                from_frame: self.current_frame,
+                duration: secs,
+                remaining: secs,
+            })
+        };
+        self.animation = Rc::clone(target);
+        self.current_frame = self.animation.frames[0];
+        self.elapsed_time = 0.0;
+    }
+
+    /// Advance the animation by one tick, respecting each frame's timing
+    #[allow(dead_code)]
+    pub fn update(&mut self) {
+        self.advance(crate::DT);
+    }
+    /// Advance the animation by dt_secs of game time. Animation timings are in seconds,
+    /// so this plays at the same speed however often it's called.
+    pub fn advance(&mut self, dt_secs: f64) {
+        let dt = dt_secs.max(0.0) as f32;
+        if let Some(blend) = &mut self.blend {
+            blend.remaining -= dt;
+            if blend.remaining < TIME_SLACK {
+                self.blend = None;
+            }
+        }
+        self.elapsed_time = (self.elapsed_time + dt) % self.animation.duration();
+        self.current_frame = self.animation.frame_at(self.elapsed_time);
+    }
+
+    /// Where the sprite is and how big its current frame is, for collision checks
+    pub fn bounds(&self) -> collision::Rect {
+        collision::Rect {
+            x: self.position.0,
+            y: self.position.1,
+            w: self.current_frame.w,
+            h: self.current_frame.h,
         }
     }
+    /// Does the sprite's current frame touch other? Same test as rect_touching.
+    #[allow(dead_code)]
+    pub fn overlaps(&self, other: collision::Rect) -> bool {
+        collision::rect_touching(self.bounds(), other)
+    }
+
+    /// Blit the current frame at the sprite's position
+    #[allow(dead_code)]
+    pub fn draw(&self, screen: &mut Screen) {
+        screen.draw_sprite(self);
+    }
+}
+
+/// Which way something is looking, for picking directional frames
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Facing {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Face along whichever axis the velocity is bigger on.
+/// Standing still keeps the old facing, and so does an exact diagonal
+/// if the old facing is one of the two it's between (otherwise horizontal wins),
+/// so diagonal movement doesn't flicker back and forth.
+#[allow(dead_code)]
+pub fn facing_from_velocity(Vec2i(vx, vy): Vec2i, previous: Facing) -> Facing {
+    let horizontal = if vx < 0 { Facing::Left } else { Facing::Right };
+    let vertical = if vy < 0 { Facing::Up } else { Facing::Down };
+    if vx == 0 && vy == 0 {
+        previous
+    } else if vx.abs() > vy.abs() {
+        horizontal
+    } else if vy.abs() > vx.abs() || previous == vertical {
+        vertical
+    } else {
+        horizontal
+    }
 }
 
 pub trait DrawSpriteExt {
     fn draw_sprite(&mut self, s: &Sprite);
 }
 
-use crate::screen::Screen;
+use crate::screen::{Drawable, Screen};
 impl<'fb> DrawSpriteExt for Screen<'fb> {
     fn draw_sprite(&mut self, s: &Sprite) {
         // This works because we're only using a public method of Screen here,
         // and the private fields of sprite are visible inside this module
-        self.bitblt(&s.image, s.current_frame, s.position);
+        let flips = (s.flip_x, false, false);
+        match &s.blend {
+            Some(blend) => {
+                // Old frame fading out underneath, new frame fading in over it.
+                // The old one fades on its own so it's gone by the end even where the new
+                // frame is see-through.
+                let t = 1.0 - blend.remaining / blend.duration;
+                let fade = |a: f32| Rgba(255, 255, 255, (a * 255.0).round() as u8);
+                let (from, to) = (blend.from_frame, s.current_frame);
+                self.bitblt_flipped_tinted(&s.image, from, s.position, flips, fade(1.0 - t));
+                self.bitblt_flipped_tinted(&s.image, to, s.position, flips, fade(t));
+            }
+            None if s.flip_x => {
+                self.bitblt_flipped(&s.image, s.current_frame, s.position, true, false, false)
+            }
+            None => self.bitblt(&s.image, s.current_frame, s.position),
+        }
+    }
+}
+
+/// Draw all of sprites that the screen can see, lowest layer first so higher layers end up on top.
+/// Sprites on the same layer keep their order in the slice. The slice itself isn't reordered,
+/// since other things (like rewind snapshots) go by sprite index.
+pub fn draw_sprites(sprites: &[Sprite], screen: &mut Screen) {
+    let mut order: Vec<&Sprite> = sprites.iter().collect();
+    // sort_by_key is stable, which is what keeps ties in order
+    order.sort_by_key(|s| s.layer);
+    for s in order {
+        // Don't bother blitting sprites the camera can't see
+        let size = (s.current_frame.w as usize, s.current_frame.h as usize);
+        if crate::screen::is_on_screen(s.position, size, screen.position, screen.size()) {
+            screen.draw_sprite(s);
+        }
+    }
+}
+
+impl Drawable for Sprite {
+    fn draw(&self, screen: &mut Screen) {
+        screen.draw_sprite(self);
+    }
+}
+
+// A whole group of sprites is one scene step, drawn in layer order like draw_sprites
+impl Drawable for Vec<Sprite> {
+    fn draw(&self, screen: &mut Screen) {
+        draw_sprites(self, screen);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 2x1 texture: an opaque red pixel and an opaque blue one
+    fn red_blue() -> Rc<Texture> {
+        let mut img = image::RgbaImage::new(2, 1);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        img.put_pixel(1, 0, image::Rgba([0, 0, 255, 255]));
+        Rc::new(Texture::new(img))
+    }
+    fn pixel(r: i32) -> Rect {
+        Rect {
+            x: r,
+            y: 0,
+            w: 1,
+            h: 1,
+        }
+    }
+    fn draw_one(s: &Sprite) -> Rgba {
+        let mut fb = vec![0_u8; 4];
+        let mut screen = Screen::wrap(&mut fb, 1, 1, 4, Vec2i(0, 0));
+        s.draw(&mut screen);
+        Rgba(fb[0], fb[1], fb[2], fb[3])
+    }
+
+    #[test]
+    fn crossfade_mixes_then_settles_on_the_target() {
+        let red = Rc::new(Animation::new(vec![pixel(0)]));
+        let blue = Rc::new(Animation::new(vec![pixel(1)]));
+        let mut s = Sprite::new(&red_blue(), &red, pixel(0), 0.0, Vec2i(0, 0));
+        s.blend_to(&blue, 4);
+        assert_eq!(draw_one(&s), Rgba(255, 0, 0, 255));
+        s.update();
+        s.update();
+        // Halfway: the red is fading out and the blue fading in, and both show
+        let Rgba(r, _, b, _) = draw_one(&s);
+        assert!(0 < r && r < 255 && 0 < b && b < 255, "Got {:?}", (r, b));
+        s.update();
+        s.update();
+        assert_eq!(draw_one(&s), Rgba(0, 0, 255, 255));
+    }
+    #[test]
+    fn sprites_draw_at_their_position_and_update_their_frame() {
+        let anim = Rc::new(Animation::with_timings(
+            vec![pixel(0), pixel(1)],
+            vec![0.2, 0.1],
+        ));
+        let mut s = Sprite::new(&red_blue(), &anim, pixel(0), 0.0, Vec2i(2, 1));
+        let drawn = |s: &Sprite| {
+            let mut fb = vec![0_u8; 4 * 3 * 4];
+            let mut screen = Screen::wrap(&mut fb, 4, 3, 4, Vec2i(0, 0));
+            s.draw(&mut screen);
+            fb.chunks_exact(4)
+                .map(|p| Rgba(p[0], p[1], p[2], p[3]))
+                .collect::<Vec<_>>()
+        };
+        let lit = drawn(&s);
+        for (i, &px) in lit.iter().enumerate() {
+            let expected = if i == 4 + 2 {
+                Rgba(255, 0, 0, 255)
+            } else {
+                Rgba(0, 0, 0, 0)
+            };
+            assert_eq!(px, expected, "at pixel {}", i);
+        }
+        // The first frame lasts two tenths of a second
+        s.advance(0.1);
+        assert_eq!(s.current_frame, pixel(0));
+        s.advance(0.1);
+        assert_eq!(s.current_frame, pixel(1));
+        assert_eq!(drawn(&s)[4 + 2], Rgba(0, 0, 255, 255));
+    }
+    #[test]
+    fn facing_follows_the_bigger_axis() {
+        use Facing::*;
+        let cases = [
+            ((3, 0), Up, Right),
+            ((-3, 1), Up, Left),
+            ((1, -5), Left, Up),
+            ((0, 2), Right, Down),
+            // Standing still keeps whatever it was
+            ((0, 0), Left, Left),
+            // Exact diagonals stick with the old facing if it's one of the two...
+            ((2, 2), Down, Down),
+            ((2, 2), Right, Right),
+            ((-2, 2), Left, Left),
+            // ...and otherwise go horizontal
+            ((2, -2), Down, Right),
+            ((-2, -2), Right, Left),
+        ];
+        for &((vx, vy), previous, expected) in cases.iter() {
+            let got = facing_from_velocity(Vec2i(vx, vy), previous);
+            assert_eq!(got, expected, "{:?} from {:?}", (vx, vy), previous);
+        }
+    }
+    #[test]
+    fn many_sprites_share_one_animation() {
+        let tex = red_blue();
+        let walk = Rc::new(Animation::new(vec![pixel(0), pixel(1)]));
+        let mut crowd: Vec<Sprite> = (0..100)
+            .map(|i| Sprite::new(&tex, &walk, pixel(0), 0.0, Vec2i(i, 0)))
+            .collect();
+        for (i, s) in crowd.iter_mut().enumerate() {
+            // Each one can be at its own point in the cycle
+            for _ in 0..i % 2 {
+                s.update();
+            }
+        }
+        // One set of frames, however many sprites are playing it
+        assert_eq!(Rc::strong_count(&walk), 101);
+        assert!(crowd.iter().all(|s| Rc::ptr_eq(&s.animation, &walk)));
+        assert!(crowd
+            .iter()
+            .all(|s| s.animation.frames.as_ptr() == walk.frames.as_ptr()));
+        assert_eq!(crowd[0].current_frame, pixel(0));
+        assert_eq!(crowd[1].current_frame, pixel(1));
+        drop(crowd);
+        assert_eq!(Rc::strong_count(&walk), 1);
+    }
+    #[test]
+    fn bounds_follow_position_and_the_current_frame() {
+        let tex = Rc::new(Texture::placeholder(8, 4));
+        let small = Rect {
+            x: 0,
+            y: 0,
+            w: 2,
+            h: 2,
+        };
+        let wide = Rect {
+            x: 0,
+            y: 2,
+            w: 8,
+            h: 2,
+        };
+        let anim = Rc::new(Animation::new(vec![small, wide]));
+        let mut sprite = Sprite::new(&tex, &anim, small, 0.0, Vec2i(10, 20));
+        let at = |x, y, w, h| collision::Rect { x, y, w, h };
+        assert_eq!(sprite.bounds(), at(10, 20, 2, 2));
+        sprite.update();
+        sprite.position = Vec2i(-3, 5);
+        assert_eq!(sprite.current_frame, wide);
+        assert_eq!(sprite.bounds(), at(-3, 5, 8, 2));
+        // Overlapping agrees with rect_touching, edges included
+        for &other in &[
+            at(5, 7, 4, 4),
+            at(4, 5, 1, 1),
+            at(6, 5, 1, 1),
+            at(0, 8, 2, 2),
+        ] {
+            assert_eq!(
+                sprite.overlaps(other),
+                collision::rect_touching(sprite.bounds(), other),
+                "{:?}",
+                other
+            );
+        }
+        assert!(sprite.overlaps(at(5, 7, 4, 4)));
+        assert!(!sprite.overlaps(at(6, 5, 1, 1)));
+    }
+    #[test]
+    fn a_tenth_of_a_second_per_frame_plays_in_real_time() {
+        let anim = Rc::new(Animation::with_timings(
+            vec![pixel(0), pixel(1)],
+            vec![0.1, 0.1],
+        ));
+        let mut s = Sprite::new(&red_blue(), &anim, pixel(0), 0.0, Vec2i(0, 0));
+        // Five 60Hz steps is just short of a tenth of a second, and the sixth gets there
+        for _ in 0..5 {
+            s.update();
+        }
+        assert_eq!(s.current_frame, pixel(0));
+        s.update();
+        assert_eq!(s.current_frame, pixel(1));
+        // The same time in bigger steps lands in the same place
+        let mut s = Sprite::new(&red_blue(), &anim, pixel(0), 0.0, Vec2i(0, 0));
+        s.advance(0.05);
+        assert_eq!(s.current_frame, pixel(0));
+        s.advance(0.05);
+        assert_eq!(s.current_frame, pixel(1));
+        // And it loops back around after 0.2 seconds
+        s.advance(0.1);
+        assert_eq!(s.current_frame, pixel(0));
+    }
+    #[test]
+    fn higher_layers_draw_on_top_whatever_the_order() {
+        let red = Rc::new(Animation::new(vec![pixel(0)]));
+        let blue = Rc::new(Animation::new(vec![pixel(1)]));
+        let tex = red_blue();
+        let draw_all = |sprites: &[Sprite]| {
+            let mut fb = vec![0_u8; 4];
+            draw_sprites(sprites, &mut Screen::wrap(&mut fb, 1, 1, 4, Vec2i(0, 0)));
+            Rgba(fb[0], fb[1], fb[2], fb[3])
+        };
+        let mut hud = Sprite::new(&tex, &blue, pixel(1), 0.0, Vec2i(0, 0));
+        hud.layer = 10;
+        let world = Sprite::new(&tex, &red, pixel(0), 0.0, Vec2i(0, 0));
+        let mut sprites = vec![hud, world];
+        assert_eq!(draw_all(&sprites), Rgba(0, 0, 255, 255));
+        sprites.reverse();
+        assert_eq!(draw_all(&sprites), Rgba(0, 0, 255, 255));
+        // Same layer falls back to slice order
+        sprites[1].layer = 0;
+        assert_eq!(draw_all(&sprites), Rgba(0, 0, 255, 255));
+        sprites.reverse();
+        assert_eq!(draw_all(&sprites), Rgba(255, 0, 0, 255));
     }
 }
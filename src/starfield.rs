@@ -0,0 +1,106 @@
+use crate::rng::Rng;
+use crate::screen::Screen;
+use crate::types::{Rgba, Vec2i};
+
+// Far to near: how fast each layer scrolls compared to the camera (as a fraction num/den),
+// and how bright its stars are. Farther stars are dimmer and move less.
+const LAYERS: [((i32, i32), Rgba); 3] = [
+    ((1, 4), Rgba(90, 90, 110, 255)),
+    ((1, 2), Rgba(160, 160, 180, 255)),
+    ((1, 1), Rgba(255, 255, 255, 255)),
+];
+
+/// A scrolling space background: a few layers of single-pixel stars that wrap around
+/// a (w, h) tile, so it repeats forever in every direction.
+/// The same seed always gives the same sky.
+pub struct Starfield {
+    size: (i32, i32),
+    // Position in the tile and which layer each star is on
+    stars: Vec<(Vec2i, usize)>,
+}
+
+#[allow(dead_code)]
+impl Starfield {
+    pub fn new(seed: u64, count: usize, (w, h): (usize, usize)) -> Self {
+        assert!(w > 0 && h > 0, "Starfield can't be empty");
+        let mut rng = Rng::new(seed);
+        let stars = (0..count)
+            .map(|_| {
+                let pos = Vec2i(rng.below(w) as i32, rng.below(h) as i32);
+                (pos, rng.below(LAYERS.len()))
+            })
+            .collect();
+        Self {
+            size: (w as i32, h as i32),
+            stars,
+        }
+    }
+    pub fn stars(&self) -> &[(Vec2i, usize)] {
+        &self.stars
+    }
+    /// Draw the stars over the whole screen as seen from offset (a camera position,
+    /// or something that grows over time for a constant drift).
+    /// Stars are placed relative to the screen, so this works no matter where it's scrolled.
+    pub fn draw(&self, screen: &mut Screen, Vec2i(ox, oy): Vec2i) {
+        let (w, h) = self.size;
+        let (sw, sh) = screen.size();
+        let origin = screen.position;
+        for &(Vec2i(x, y), layer) in self.stars.iter() {
+            let ((num, den), col) = LAYERS[layer];
+            let x = (x - ox * num / den).rem_euclid(w);
+            let y = (y - oy * num / den).rem_euclid(h);
+            // The tile repeats, so a screen bigger than it sees each star several times
+            for ty in (0..sh as i32).step_by(h as usize) {
+                for tx in (0..sw as i32).step_by(w as usize) {
+                    screen.draw_at(col, Vec2i(origin.0 + tx + x, origin.1 + ty + y));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // What a 12x8 screen shows of sky from offset
+    fn render(sky: &Starfield, offset: Vec2i) -> Vec<u8> {
+        let mut fb = vec![0_u8; 12 * 8 * 4];
+        sky.draw(&mut Screen::wrap(&mut fb, 12, 8, 4, Vec2i(0, 0)), offset);
+        fb
+    }
+
+    #[test]
+    fn same_seed_same_sky() {
+        let a = Starfield::new(7, 20, (6, 4));
+        let b = Starfield::new(7, 20, (6, 4));
+        assert_eq!(a.stars(), b.stars());
+        assert_eq!(a.stars().len(), 20);
+        assert!(a
+            .stars()
+            .iter()
+            .all(|&(Vec2i(x, y), layer)| x < 6 && y < 4 && layer < LAYERS.len()));
+        assert_ne!(a.stars(), Starfield::new(8, 20, (6, 4)).stars());
+    }
+    #[test]
+    fn sky_tiles_seamlessly() {
+        let sky = Starfield::new(3, 10, (6, 4));
+        let home = render(&sky, Vec2i(0, 0));
+        // The screen is two tiles across and two down, so it shows each quarter the same
+        let quarter = |fb: &[u8], qx: usize, qy: usize| -> Vec<u8> {
+            (0..4)
+                .flat_map(|y| {
+                    let start = ((qy * 4 + y) * 12 + qx * 6) * 4;
+                    fb[start..start + 6 * 4].to_vec()
+                })
+                .collect()
+        };
+        assert!(home.chunks_exact(4).any(|p| p[3] != 0));
+        for &(qx, qy) in &[(1, 0), (0, 1), (1, 1)] {
+            assert_eq!(quarter(&home, qx, qy), quarter(&home, 0, 0));
+        }
+        // Scrolling far enough to move every layer by whole tiles shows the same picture
+        assert_eq!(render(&sky, Vec2i(4 * 6, -4 * 4)), home);
+        assert_ne!(render(&sky, Vec2i(1, 0)), home);
+    }
+}
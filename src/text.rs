@@ -0,0 +1,188 @@
+use crate::texture::Texture;
+use crate::types::{Rect, Vec2i};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+/// Where each line of a text box sits horizontally
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[allow(dead_code)]
+pub enum Align {
+    Left,
+    Center,
+    Right,
+}
+
+/// A bitmap font: every glyph is a rectangle of one texture.
+/// Glyphs can have different widths; characters without a glyph are skipped.
+pub struct Font {
+    pub texture: Rc<Texture>,
+    glyphs: HashMap<char, Rect>,
+    pub line_height: u32,
+}
+
+#[allow(dead_code)]
+impl Font {
+    pub fn new(texture: Rc<Texture>, glyphs: HashMap<char, Rect>, line_height: u32) -> Self {
+        Self {
+            texture,
+            glyphs,
+            line_height,
+        }
+    }
+    pub fn glyph(&self, c: char) -> Option<Rect> {
+        self.glyphs.get(&c).copied()
+    }
+    /// How far the pen moves after drawing c
+    pub fn advance(&self, c: char) -> u32 {
+        self.glyph(c).map_or(0, |r| r.w as u32)
+    }
+    /// How wide text would be drawn on a single line
+    pub fn measure(&self, text: &str) -> u32 {
+        text.chars().map(|c| self.advance(c)).sum()
+    }
+}
+
+/// Break text into lines no wider than max_width.
+/// Lines break on spaces (and always at a newline); a word that is wider
+/// than the box on its own gets hard-broken wherever it runs out of room.
+pub fn wrap_text(text: &str, max_width: u32, measure: impl Fn(char) -> u32) -> Vec<String> {
+    let width = |s: &str| -> u32 { s.chars().map(&measure).sum() };
+    let mut lines = vec![];
+    for paragraph in text.split('\n') {
+        let mut line = String::new();
+        for word in paragraph.split(' ').filter(|w| !w.is_empty()) {
+            let joined = if line.is_empty() {
+                word.to_string()
+            } else {
+                format!("{} {}", line, word)
+            };
+            if width(&joined) <= max_width {
+                line = joined;
+                continue;
+            }
+            if !line.is_empty() {
+                lines.push(std::mem::take(&mut line));
+            }
+            // The word doesn't fit next to anything else; chop it up if it doesn't fit at all
+            for c in word.chars() {
+                if !line.is_empty() && width(&line) + measure(c) > max_width {
+                    lines.push(std::mem::take(&mut line));
+                }
+                line.push(c);
+            }
+        }
+        lines.push(line);
+    }
+    lines
+}
+
+/// How far from the left edge of a box_width wide box a line_width wide line starts
+pub fn align_offset(line_width: u32, box_width: u32, align: Align) -> i32 {
+    let slack = box_width as i32 - line_width as i32;
+    match align {
+        Align::Left => 0,
+        Align::Center => slack / 2,
+        Align::Right => slack,
+    }
+}
+
+/// Wrap text to r and work out where each line starts.
+/// Lines that would poke out of the bottom of r are dropped.
+pub fn layout_text(
+    text: &str,
+    r: Rect,
+    line_height: u32,
+    align: Align,
+    measure: impl Fn(char) -> u32,
+) -> Vec<(Vec2i, String)> {
+    let lines = wrap_text(text, r.w as u32, &measure);
+    let fit = (r.h as u32 / line_height.max(1)) as usize;
+    lines
+        .into_iter()
+        .take(fit)
+        .enumerate()
+        .map(|(i, line)| {
+            let w = line.chars().map(&measure).sum();
+            let x = r.x + align_offset(w, r.w as u32, align);
+            let y = r.y + (i as u32 * line_height) as i32;
+            (Vec2i(x, y), line)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every character is 4 pixels wide
+    fn mono(_: char) -> u32 {
+        4
+    }
+
+    #[test]
+    fn long_text_wraps_on_spaces() {
+        // 10 characters fit on a line
+        let lines = wrap_text("the quick brown fox jumps over the lazy dog", 40, mono);
+        assert_eq!(
+            lines,
+            vec!["the quick", "brown fox", "jumps over", "the lazy", "dog"]
+        );
+        assert!(lines.iter().all(|l| l.len() * 4 <= 40));
+    }
+
+    #[test]
+    fn long_words_hard_break() {
+        let lines = wrap_text("a abcdefghijkl b", 20, mono);
+        assert_eq!(lines, vec!["a", "abcde", "fghij", "kl b"]);
+    }
+
+    #[test]
+    fn newlines_always_break() {
+        let lines = wrap_text("hi\nthere", 100, mono);
+        assert_eq!(lines, vec!["hi", "there"]);
+    }
+
+    #[test]
+    fn center_alignment_splits_the_slack() {
+        let r = Rect {
+            x: 10,
+            y: 20,
+            w: 40,
+            h: 100,
+        };
+        let laid_out = layout_text("abcd efghijkl", r, 8, Align::Center, mono);
+        // "abcd" is 16 wide in a 40 wide box, "efghijkl" is 32 wide
+        assert_eq!(laid_out[0], (Vec2i(10 + 12, 20), "abcd".to_string()));
+        assert_eq!(laid_out[1], (Vec2i(10 + 4, 28), "efghijkl".to_string()));
+        assert_eq!(align_offset(16, 40, Align::Left), 0);
+        assert_eq!(align_offset(16, 40, Align::Right), 24);
+    }
+
+    #[test]
+    fn lines_past_the_bottom_are_clipped() {
+        let r = Rect {
+            x: 0,
+            y: 0,
+            w: 8,
+            h: 20,
+        };
+        // Five lines of text but only room for two 8 pixel lines
+        let laid_out = layout_text("aa bb cc dd ee", r, 8, Align::Left, mono);
+        let lines: Vec<_> = laid_out.into_iter().map(|(_, l)| l).collect();
+        assert_eq!(lines, vec!["aa", "bb"]);
+    }
+
+    #[test]
+    fn font_measures_by_glyph_width() {
+        let glyph = |w| Rect {
+            x: 0,
+            y: 0,
+            w,
+            h: 2,
+        };
+        let glyphs = vec![('i', glyph(1)), ('m', glyph(3))].into_iter().collect();
+        let font = Font::new(Rc::new(Texture::placeholder(4, 2)), glyphs, 2);
+        // Characters the font doesn't have take up no room
+        assert_eq!(font.measure("mim?"), 7);
+    }
+}
@@ -1,6 +1,11 @@
-use crate::types::Rect;
-use image::{self, RgbaImage};
-use std::path::Path;
+use crate::animation::{ms_to_secs, Animation};
+use crate::types::{Rect, Rgba};
+use image::error::{DecodingError, ImageError, ImageResult};
+use image::{self, gif::GifDecoder, AnimationDecoder, ImageFormat, RgbaImage};
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::fmt;
+use std::path::{Path, PathBuf};
 
 pub struct Texture {
     image: Vec<u8>,
@@ -9,13 +14,86 @@ pub struct Texture {
     depth: usize,
 }
 
+/// An image that couldn't be loaded, and which file it was
+#[derive(Debug)]
+pub struct LoadError {
+    pub path: PathBuf,
+    pub cause: image::ImageError,
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Couldn't load image {}: {}", self.path.display(), self.cause)
+    }
+}
+
+impl std::error::Error for LoadError {}
+
 enum AlphaChannel {
     First,
     Last,
 }
 impl Texture {
     pub fn with_file(path: &Path) -> Self {
-        Self::new(image::open(path).expect("Couldn't load image").into_rgba8())
+        Self::try_with_file(path).unwrap_or_else(|e| panic!("{}", e))
+    }
+    /// Like with_file, but a missing or broken file is an error instead of a panic
+    pub fn try_with_file(path: &Path) -> Result<Self, LoadError> {
+        let img = image::open(path).map_err(|cause| LoadError {
+            path: path.to_path_buf(),
+            cause,
+        })?;
+        Ok(Self::new(img.into_rgba8()))
+    }
+    /// Magenta and black 8x8 checks, to stand in for an image that wouldn't load.
+    /// Hard to miss on screen, but the game keeps running.
+    pub fn placeholder(w: u32, h: u32) -> Self {
+        Self::new(RgbaImage::from_fn(w, h, |x, y| {
+            if (x / 8 + y / 8) % 2 == 0 {
+                image::Rgba([255, 0, 255, 255])
+            } else {
+                image::Rgba([0, 0, 0, 255])
+            }
+        }))
+    }
+    /// Load an animated GIF as one tall texture with the frames stacked top to bottom,
+    /// plus an Animation that steps through them with the GIF's own delays.
+    /// Frames come out of the decoder already composited, so each is a full picture.
+    #[allow(dead_code)]
+    pub fn with_gif(path: &Path) -> Result<(Self, Animation), LoadError> {
+        File::open(path)
+            .map_err(ImageError::IoError)
+            .and_then(|file| Self::decode_gif(BufReader::new(file)))
+            .map_err(|cause| LoadError {
+                path: path.to_path_buf(),
+                cause,
+            })
+    }
+    fn decode_gif(reader: impl Read) -> ImageResult<(Self, Animation)> {
+        let frames = GifDecoder::new(reader)?.into_frames().collect_frames()?;
+        if frames.is_empty() {
+            return Err(ImageError::Decoding(DecodingError::new(
+                ImageFormat::Gif.into(),
+                "gif has no frames",
+            )));
+        }
+        let (w, h) = frames[0].buffer().dimensions();
+        let mut sheet = RgbaImage::new(w, h * frames.len() as u32);
+        let mut rects = Vec::with_capacity(frames.len());
+        let mut timings = Vec::with_capacity(frames.len());
+        for (i, frame) in frames.iter().enumerate() {
+            let y = h * i as u32;
+            image::imageops::replace(&mut sheet, frame.buffer(), 0, y);
+            rects.push(Rect {
+                x: 0,
+                y: y as i32,
+                w: w as u16,
+                h: h as u16,
+            });
+            let (num, denom) = frame.delay().numer_denom_ms();
+            timings.push(ms_to_secs(num as f64 / denom as f64));
+        }
+        Ok((Self::new(sheet), Animation::with_timings(rects, timings)))
     }
     pub fn new(image: RgbaImage) -> Self {
         let (width, height) = image.dimensions();
@@ -28,18 +106,126 @@ impl Texture {
             image,
         }
     }
+    /// Wrap pixels that are already premultiplied (like a copy of the framebuffer),
+    /// row by row with no padding.
+    pub fn from_premultiplied(image: Vec<u8>, width: usize, height: usize, depth: usize) -> Self {
+        assert_eq!(
+            image.len(),
+            width * height * depth,
+            "Image isn't {}x{}x{}",
+            width,
+            height,
+            depth
+        );
+        Self {
+            image,
+            width,
+            height,
+            depth,
+        }
+    }
+    /// A copy turned clockwise by some number of quarter turns.
+    /// Odd turns swap width and height. Pixels are just moved around, never blended.
+    #[allow(dead_code)]
+    pub fn rotated(&self, quarter_turns: u8) -> Texture {
+        let turns = quarter_turns % 4;
+        let (w, h) = (self.width, self.height);
+        let (nw, nh) = if turns % 2 == 1 { (h, w) } else { (w, h) };
+        let depth = self.depth;
+        let mut image = vec![0; nw * nh * depth];
+        for y in 0..nh {
+            for x in 0..nw {
+                // Where did the pixel that lands at (x, y) come from?
+                let (sx, sy) = match turns {
+                    0 => (x, y),
+                    1 => (y, h - 1 - x),
+                    2 => (w - 1 - x, h - 1 - y),
+                    _ => (w - 1 - y, x),
+                };
+                let from = (sy * w + sx) * depth;
+                let to = (y * nw + x) * depth;
+                image[to..(to + depth)].copy_from_slice(&self.image[from..(from + depth)]);
+            }
+        }
+        // Already premultiplied, so no need to go through new
+        Texture {
+            image,
+            width: nw,
+            height: nh,
+            depth,
+        }
+    }
     pub fn depth(&self) -> usize {
         self.depth
     }
     pub fn size(&self) -> (usize, usize) {
         (self.width, self.height)
     }
+    pub fn width(&self) -> usize {
+        self.width
+    }
+    pub fn height(&self) -> usize {
+        self.height
+    }
+    /// A rect covering the whole image, for blitting all of it
+    pub fn bounds(&self) -> Rect {
+        Rect {
+            x: 0,
+            y: 0,
+            w: self.width as u16,
+            h: self.height as u16,
+        }
+    }
     pub fn pitch(&self) -> usize {
         self.width * self.depth
     }
     pub fn buffer(&self) -> &[u8] {
         &self.image
     }
+    /// The pixel at (x, y), or None if that's outside the image.
+    /// Colors come back premultiplied, the way they're stored.
+    pub fn pixel(&self, x: i32, y: i32) -> Option<Rgba> {
+        if x < 0 || (self.width as i32) <= x || y < 0 || (self.height as i32) <= y {
+            return None;
+        }
+        let idx = y as usize * self.pitch() + x as usize * self.depth;
+        let px = &self.image[idx..(idx + self.depth)];
+        Some(Rgba(px[0], px[1], px[2], px[3]))
+    }
+    /// Like pixel, but coordinates off the edge get the nearest edge pixel
+    pub fn pixel_clamped(&self, x: i32, y: i32) -> Rgba {
+        assert!(self.width > 0 && self.height > 0, "Can't sample an empty texture");
+        let x = x.max(0).min(self.width as i32 - 1);
+        let y = y.max(0).min(self.height as i32 - 1);
+        self.pixel(x, y).unwrap()
+    }
+    /// A copy with colors swapped out, NES palette-swap style: each pixel whose RGB is
+    /// exactly one of the from colors becomes the matching to color, keeping its own alpha.
+    /// The alpha of the colors in mapping is ignored. Fully transparent pixels are left alone.
+    #[allow(dead_code)]
+    pub fn remap_palette(&self, mapping: &[(Rgba, Rgba)]) -> Texture {
+        let mut image = self.image.clone();
+        for px in image.chunks_exact_mut(self.depth) {
+            let a = px[3];
+            if a == 0 {
+                continue;
+            }
+            // Pixels are stored premultiplied, so premultiply the palette the same way to compare
+            let pm = |c: u8| (c as f32 * (a as f32 / 255.0)) as u8;
+            if let Some((_, to)) = mapping
+                .iter()
+                .find(|(from, _)| px[0..3] == [pm(from.0), pm(from.1), pm(from.2)])
+            {
+                px[0..3].copy_from_slice(&[pm(to.0), pm(to.1), pm(to.2)]);
+            }
+        }
+        Texture {
+            image,
+            width: self.width,
+            height: self.height,
+            depth: self.depth,
+        }
+    }
     pub fn valid_frame(&self, frame: Rect) -> bool {
         0 <= frame.x
             && (frame.x + frame.w as i32) <= (self.width as i32)
@@ -75,3 +261,141 @@ fn premultiply(img: &mut [u8], depth: usize, alpha: AlphaChannel) {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::screen::Screen;
+    use crate::types::Vec2i;
+    use image::{gif::GifEncoder, Delay, Frame};
+
+    #[test]
+    fn gif_frames_become_an_animation() {
+        let red = RgbaImage::from_pixel(3, 2, image::Rgba([255, 0, 0, 255]));
+        let blue = RgbaImage::from_pixel(3, 2, image::Rgba([0, 0, 255, 255]));
+        let mut bytes = vec![];
+        GifEncoder::new(&mut bytes)
+            .encode_frames(vec![
+                Frame::from_parts(red, 0, 0, Delay::from_numer_denom_ms(100, 1)),
+                Frame::from_parts(blue, 0, 0, Delay::from_numer_denom_ms(50, 1)),
+            ])
+            .expect("Couldn't encode test gif");
+        let (tex, anim) = Texture::decode_gif(&bytes[..]).expect("Couldn't decode test gif");
+        assert_eq!(anim.frames.len(), 2);
+        // Stacked top to bottom
+        assert_eq!(tex.size(), (3, 4));
+        assert_eq!(tex.pixel(0, 0), Some(Rgba(255, 0, 0, 255)));
+        assert_eq!(tex.pixel(2, 3), Some(Rgba(0, 0, 255, 255)));
+        assert_eq!(anim.frames[1].y, 2);
+    }
+    #[test]
+    fn missing_gif_is_an_error() {
+        let path = Path::new("no/such/animation.gif");
+        let err = Texture::with_gif(path)
+            .err()
+            .expect("Loaded a gif that isn't there");
+        assert_eq!(err.path, path);
+    }
+    #[test]
+    fn quarter_turns_swap_width_and_height() {
+        let mut img = RgbaImage::new(2, 1);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        img.put_pixel(1, 0, image::Rgba([0, 0, 255, 255]));
+        let tex = Texture::new(img);
+        let (red, blue) = (Rgba(255, 0, 0, 255), Rgba(0, 0, 255, 255));
+        // Clockwise: what was on the left ends up on top
+        let turned = tex.rotated(1);
+        assert_eq!(turned.size(), (1, 2));
+        assert_eq!(
+            (turned.pixel(0, 0), turned.pixel(0, 1)),
+            (Some(red), Some(blue))
+        );
+        let flipped = tex.rotated(2);
+        assert_eq!(flipped.size(), (2, 1));
+        assert_eq!(
+            (flipped.pixel(0, 0), flipped.pixel(1, 0)),
+            (Some(blue), Some(red))
+        );
+        let back = tex.rotated(3);
+        assert_eq!(
+            (back.pixel(0, 0), back.pixel(0, 1)),
+            (Some(blue), Some(red))
+        );
+        // Four turns (or any multiple) is no turn at all
+        assert_eq!(tex.rotated(4).buffer(), tex.buffer());
+    }
+    // Red, green on top; blue, white underneath
+    fn four_colors() -> Texture {
+        let mut img = RgbaImage::new(2, 2);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        img.put_pixel(1, 0, image::Rgba([0, 255, 0, 255]));
+        img.put_pixel(0, 1, image::Rgba([0, 0, 255, 255]));
+        img.put_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        Texture::new(img)
+    }
+    #[test]
+    fn pixel_is_none_off_the_texture() {
+        let tex = four_colors();
+        assert_eq!(tex.pixel(1, 0), Some(Rgba(0, 255, 0, 255)));
+        assert_eq!(tex.pixel(0, 1), Some(Rgba(0, 0, 255, 255)));
+        for &(x, y) in [(-1, 0), (2, 0), (0, -1), (0, 2), (i32::MIN, i32::MAX)].iter() {
+            assert_eq!(tex.pixel(x, y), None, "at ({}, {})", x, y);
+        }
+    }
+    #[test]
+    fn pixel_clamped_repeats_the_edges() {
+        let tex = four_colors();
+        assert_eq!(tex.pixel_clamped(1, 1), Rgba(255, 255, 255, 255));
+        assert_eq!(tex.pixel_clamped(-5, -5), Rgba(255, 0, 0, 255));
+        assert_eq!(tex.pixel_clamped(9, -1), Rgba(0, 255, 0, 255));
+        assert_eq!(tex.pixel_clamped(-1, 9), Rgba(0, 0, 255, 255));
+        assert_eq!(tex.pixel_clamped(1, 3), Rgba(255, 255, 255, 255));
+    }
+    #[test]
+    fn bounds_cover_the_whole_loaded_image() {
+        // Odd sizes, so a swapped or rounded dimension shows up
+        let img = RgbaImage::from_pixel(5, 3, image::Rgba([10, 20, 30, 255]));
+        let path = std::env::temp_dir().join(format!("game2-bounds-{}.png", std::process::id()));
+        img.save(&path).unwrap();
+        let tex = Texture::with_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((tex.width(), tex.height()), (5, 3));
+        assert_eq!(
+            tex.bounds(),
+            Rect {
+                x: 0,
+                y: 0,
+                w: 5,
+                h: 3
+            }
+        );
+        // Blitting bounds() draws every pixel of it
+        let mut fb = vec![0_u8; 5 * 3 * 4];
+        let mut screen = Screen::wrap(&mut fb, 5, 3, 4, Vec2i(0, 0));
+        screen.bitblt(&tex, tex.bounds(), Vec2i(0, 0));
+        assert!(fb.chunks_exact(4).all(|p| p == [10, 20, 30, 255]));
+    }
+    #[test]
+    fn remapping_red_to_green_leaves_blue_alone() {
+        let (red, green, blue) = (
+            Rgba(255, 0, 0, 255),
+            Rgba(0, 255, 0, 255),
+            Rgba(0, 0, 255, 255),
+        );
+        let mut img = RgbaImage::new(4, 1);
+        img.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        img.put_pixel(1, 0, image::Rgba([0, 0, 255, 255]));
+        // Half see-through red, and a red that's a hair off
+        img.put_pixel(2, 0, image::Rgba([255, 0, 0, 128]));
+        img.put_pixel(3, 0, image::Rgba([254, 0, 0, 255]));
+        let tex = Texture::new(img);
+        let swapped = tex.remap_palette(&[(red, green)]);
+        assert_eq!(swapped.pixel(0, 0), Some(green));
+        assert_eq!(swapped.pixel(1, 0), Some(blue));
+        // Keeps its own alpha (and stays premultiplied)
+        assert_eq!(swapped.pixel(2, 0), Some(Rgba(0, 128, 0, 128)));
+        assert_eq!(swapped.pixel(3, 0), Some(Rgba(254, 0, 0, 255)));
+        // The original is untouched
+        assert_eq!(tex.pixel(0, 0), Some(red));
+    }
+}
@@ -1,23 +1,95 @@
-use crate::texture::Texture;
+use crate::collision::{self, Mobile};
+use crate::texture::{LoadError, Texture};
 use crate::types::{Rect, Rgba, Vec2i};
+use image::error::{ImageError, ParameterError, ParameterErrorKind};
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
 use std::rc::Rc;
 
-use crate::screen::Screen;
+use crate::screen::{Drawable, Screen};
 
-pub const TILE_SZ: usize = 16;
 /// A graphical tile, we'll implement Copy since it's tiny
 #[derive(Clone, Copy)]
 pub struct Tile {
     pub solid: bool, // ... any extra data like collision flags or other properties
+    /// The part of a solid tile that actually blocks things, relative to the cell's top-left,
+    /// e.g. just the bottom half for a ledge. None means the whole cell.
+    pub hitbox: Option<Rect>,
+}
+impl Tile {
+    // Where this tile's hitbox is when it's in the cell at cell (in world space)
+    fn hitbox_in(&self, cell: Rect) -> Rect {
+        match self.hitbox {
+            Some(hb) => Rect {
+                x: cell.x + hb.x,
+                y: cell.y + hb.y,
+                ..hb
+            },
+            None => cell,
+        }
+    }
+}
+/// How a single map cell's tile is mirrored, following Tiled's flags.
+/// The transpose (Tiled calls it a diagonal flip) happens first, so together with
+/// the other two it covers all four rotations of a tile and their mirror images.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct TileFlip {
+    pub horizontal: bool,
+    pub vertical: bool,
+    pub diagonal: bool,
+}
+impl TileFlip {
+    const HORIZONTAL_BIT: u32 = 0x8000_0000;
+    const VERTICAL_BIT: u32 = 0x4000_0000;
+    const DIAGONAL_BIT: u32 = 0x2000_0000;
+    /// Tiled stores the flip flags in the top three bits of each GID.
+    /// Splits a raw GID into the plain tile GID and its flags.
+    pub fn split_gid(gid: u32) -> (u32, TileFlip) {
+        let flags = Self::HORIZONTAL_BIT | Self::VERTICAL_BIT | Self::DIAGONAL_BIT;
+        (
+            gid & !flags,
+            TileFlip {
+                horizontal: gid & Self::HORIZONTAL_BIT != 0,
+                vertical: gid & Self::VERTICAL_BIT != 0,
+                diagonal: gid & Self::DIAGONAL_BIT != 0,
+            },
+        )
+    }
+    /// The flag bits to OR onto a GID, the reverse of split_gid
+    pub fn gid_bits(&self) -> u32 {
+        let mut bits = 0;
+        if self.horizontal {
+            bits |= Self::HORIZONTAL_BIT;
+        }
+        if self.vertical {
+            bits |= Self::VERTICAL_BIT;
+        }
+        if self.diagonal {
+            bits |= Self::DIAGONAL_BIT;
+        }
+        bits
+    }
 }
 /// A set of tiles used in multiple Tilemaps
 pub struct Tileset {
-    // Tile size is a constant, so we can find the tile in the texture using math
+    // Tiles are all one size, so we can find the tile in the texture using math
     // (assuming the texture is a grid of tiles).
     pub tiles: Vec<Tile>,
-    texture: Rc<Texture>,
-    // In this design, each tileset is a distinct image.
-    // Maybe not always the best choice if there aren't many tiles in a tileset!
+    // The images tiles come from. Each sheet's tiles get the next run of IDs,
+    // so sheet 0 has IDs 0..first_ids[1], sheet 1 the ones after that, and so on.
+    textures: Vec<Rc<Texture>>,
+    first_ids: Vec<usize>,
+}
+/// Which tile to use for each way a cell can connect to its neighbors, for Tilemap::autotile.
+/// variants is indexed by a bitmask of which neighbors are the same kind of tile:
+/// 1 above, 2 right, 4 below, 8 left. So variants[0] stands alone, variants[15] is
+/// surrounded, variants[2 | 4] is a top-left corner, and so on (the usual 16-tile set).
+pub struct AutoTileRules {
+    pub variants: [usize; 16],
+    /// Whether the edge of the map counts as more of the same tile,
+    /// so walls running off the map don't get a border there
+    pub edges_connect: bool,
 }
 /// An actual tilemap
 pub struct Tilemap {
@@ -25,10 +97,14 @@ pub struct Tilemap {
     pub position: Vec2i,
     /// How big it is
     dims: (usize, usize),
+    /// How big each tile is in pixels (the tileset's texture is a grid of these)
+    tile_size: (u16, u16),
     /// Which tileset is used for this tilemap
     tileset: Rc<Tileset>,
     /// A row-major grid of tile IDs in tileset
     map: Vec<TileID>,
+    /// How each cell's tile is flipped, parallel to map
+    flips: Vec<TileFlip>,
 }
 /// Grab a tile with a given ID
 impl std::ops::Index<TileID> for Tileset {
@@ -37,40 +113,106 @@ impl std::ops::Index<TileID> for Tileset {
         &self.tiles[id.0]
     }
 }
+#[allow(dead_code)]
 impl Tileset {
     /// Create a new tileset
     pub fn new(tiles: Vec<Tile>, texture: &Rc<Texture>) -> Self {
         Self {
             tiles,
-            texture: Rc::clone(texture),
+            textures: vec![Rc::clone(texture)],
+            first_ids: vec![0],
         }
     }
-    /// Get the frame rect for a tile ID
-    fn get_rect(&self, id: TileID) -> Rect {
-        let idx = id.0;
-        let (w, _h) = self.texture.size();
-        let tw = w / TILE_SZ;
+    /// A tileset drawing from several images, like a terrain sheet plus an object sheet.
+    /// Each entry is a sheet's tiles (in grid order) and its texture. IDs run through the
+    /// sheets in order, so the second sheet's first tile comes right after the first's last.
+    pub fn with_sheets(sheets: Vec<(Vec<Tile>, Rc<Texture>)>) -> Self {
+        assert!(!sheets.is_empty(), "Tileset needs at least one sheet");
+        let mut tiles = vec![];
+        let mut textures = vec![];
+        let mut first_ids = vec![];
+        for (sheet_tiles, texture) in sheets {
+            first_ids.push(tiles.len());
+            tiles.extend(sheet_tiles);
+            textures.push(texture);
+        }
+        Self {
+            tiles,
+            textures,
+            first_ids,
+        }
+    }
+    /// Make a tileset for texture whose solid flags come from a mask image the same size as it.
+    /// Both are grids of tile_size tiles numbered row by row; a tile is solid if
+    /// anything in its cell of the mask isn't black.
+    /// A mask that won't load, or is a different size from the texture, is an error.
+    pub fn solids_from_mask(
+        texture: &Rc<Texture>,
+        mask_path: impl AsRef<Path>,
+        (tw, th): (u16, u16),
+    ) -> Result<Self, LoadError> {
+        assert!(tw > 0 && th > 0, "Tiles can't be empty");
+        let mask_path = mask_path.as_ref();
+        let load_error = |cause| LoadError {
+            path: mask_path.to_path_buf(),
+            cause,
+        };
+        let mask = image::open(mask_path).map_err(load_error)?.into_rgba8();
+        let (w, h) = texture.size();
+        if (mask.width() as usize, mask.height() as usize) != (w, h) {
+            return Err(load_error(ImageError::Parameter(
+                ParameterError::from_kind(ParameterErrorKind::DimensionMismatch),
+            )));
+        }
+        let (cols, rows) = (w / tw as usize, h / th as usize);
+        let mut tiles = Vec::with_capacity(cols * rows);
+        for row in 0..rows {
+            for col in 0..cols {
+                let (x0, y0) = ((col * tw as usize) as u32, (row * th as usize) as u32);
+                let solid = (y0..y0 + th as u32).any(|y| {
+                    (x0..x0 + tw as u32).any(|x| {
+                        let p = mask.get_pixel(x, y);
+                        p[0] != 0 || p[1] != 0 || p[2] != 0
+                    })
+                });
+                tiles.push(Tile { solid, hitbox: None });
+            }
+        }
+        Ok(Self::new(tiles, texture))
+    }
+    /// Get the sheet and frame rect for a tile ID, when the sheets are grids of (w, h) tiles
+    fn get_frame(&self, id: TileID, (w, h): (u16, u16)) -> (&Texture, Rect) {
+        // The last sheet starting at or before id is the one it's on
+        let sheet = self.first_ids.iter().rposition(|&first| first <= id.0).unwrap();
+        let texture = &self.textures[sheet];
+        let idx = id.0 - self.first_ids[sheet];
+        let (tex_w, _tex_h) = texture.size();
+        let tw = tex_w / w as usize;
         let row = idx / tw;
         let col = idx - (row * tw);
-        Rect {
-            x: col as i32 * TILE_SZ as i32,
-            y: row as i32 * TILE_SZ as i32,
-            w: TILE_SZ as u16,
-            h: TILE_SZ as u16,
-        }
+        let rect = Rect {
+            x: col as i32 * w as i32,
+            y: row as i32 * h as i32,
+            w,
+            h,
+        };
+        (texture, rect)
     }
     /// Does this tileset have a tile for `id`?
     fn contains(&self, id: TileID) -> bool {
         id.0 < self.tiles.len()
     }
 }
+#[allow(dead_code)]
 impl Tilemap {
     pub fn new(
         position: Vec2i,
         dims: (usize, usize),
+        tile_size: (u16, u16),
         tileset: &Rc<Tileset>,
         map: Vec<usize>,
     ) -> Self {
+        assert!(tile_size.0 > 0 && tile_size.1 > 0, "Tiles can't be empty");
         assert_eq!(dims.0 * dims.1, map.len(), "Tilemap is the wrong size!");
         assert!(
             map.iter().all(|tid| tileset.contains(TileID(*tid))),
@@ -79,15 +221,133 @@ impl Tilemap {
         Self {
             position,
             dims,
+            tile_size,
             tileset: Rc::clone(tileset),
             map: map.into_iter().map(TileID).collect(),
+            flips: vec![TileFlip::default(); dims.0 * dims.1],
+        }
+    }
+    /// Set how the tile at map cell (x, y) is flipped
+    pub fn set_flip(&mut self, (x, y): (usize, usize), flip: TileFlip) {
+        assert!(x < self.dims.0 && y < self.dims.1, "Tile ({}, {}) out of bounds", x, y);
+        self.flips[y * self.dims.0 + x] = flip;
+    }
+    pub fn flip_at(&self, (x, y): (usize, usize)) -> TileFlip {
+        self.flips[y * self.dims.0 + x]
+    }
+    /// Change the tile at map cell (x, y)
+    pub fn set_tile(&mut self, (x, y): (usize, usize), id: usize) {
+        assert!(x < self.dims.0 && y < self.dims.1, "Tile ({}, {}) out of bounds", x, y);
+        assert!(self.tileset.contains(TileID(id)), "Tilemap refers to nonexistent tiles");
+        self.map[y * self.dims.0 + x] = TileID(id);
+    }
+    /// Pull entity start points out of the map, so levels can place things by painting
+    /// marker tiles. markers pairs a marker tile ID with what it stands for (an entity kind);
+    /// every cell holding one is replaced with the empty tile and reported as (kind, x, y)
+    /// in map cells, row by row.
+    pub fn take_markers<T: Copy>(
+        &mut self,
+        markers: &[(usize, T)],
+        empty: usize,
+    ) -> Vec<(T, usize, usize)> {
+        assert!(self.tileset.contains(TileID(empty)), "Empty tile isn't in the tileset");
+        let mut found = vec![];
+        for (i, id) in self.map.iter_mut().enumerate() {
+            if let Some(&(_, kind)) = markers.iter().find(|(m, _)| *m == id.0) {
+                found.push((kind, i % self.dims.0, i / self.dims.0));
+                *id = TileID(empty);
+                self.flips[i] = TileFlip::default();
+            }
+        }
+        found
+    }
+    /// Replace every cell holding the tile base with the variant from rules that matches
+    /// which of its four neighbors are also base, so you can paint plain "wall" and get
+    /// proper edges and corners. Neighbors are checked against the map as it was before,
+    /// so the order cells get rewritten in doesn't matter.
+    pub fn autotile(&mut self, base: usize, rules: &AutoTileRules) {
+        assert!(
+            rules.variants.iter().all(|&v| self.tileset.contains(TileID(v))),
+            "Autotile rules refer to nonexistent tiles"
+        );
+        let (w, h) = (self.dims.0 as i32, self.dims.1 as i32);
+        let before = self.map.clone();
+        let same = |x: i32, y: i32| {
+            if x < 0 || w <= x || y < 0 || h <= y {
+                rules.edges_connect
+            } else {
+                before[(y * w + x) as usize].0 == base
+            }
+        };
+        for y in 0..h {
+            for x in 0..w {
+                if before[(y * w + x) as usize].0 != base {
+                    continue;
+                }
+                let mask = same(x, y - 1) as usize
+                    | (same(x + 1, y) as usize) << 1
+                    | (same(x, y + 1) as usize) << 2
+                    | (same(x - 1, y) as usize) << 3;
+                self.map[(y * w + x) as usize] = TileID(rules.variants[mask]);
+            }
         }
     }
+    /// Write the map out as text: a "width height" line, then one line of tile IDs per row.
+    /// Flipped tiles get the Tiled flip bits on their ID so those survive the trip too.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let mut out = format!("{} {}\n", self.dims.0, self.dims.1);
+        for (row, flips) in self
+            .map
+            .chunks_exact(self.dims.0)
+            .zip(self.flips.chunks_exact(self.dims.0))
+        {
+            let ids: Vec<String> = row
+                .iter()
+                .zip(flips.iter())
+                .map(|(id, flip)| (id.0 as u32 | flip.gid_bits()).to_string())
+                .collect();
+            out.push_str(&ids.join(" "));
+            out.push('\n');
+        }
+        std::fs::write(path, out)
+    }
+    /// Read back a map written by save
+    pub fn load(
+        path: impl AsRef<Path>,
+        position: Vec2i,
+        tile_size: (u16, u16),
+        tileset: &Rc<Tileset>,
+    ) -> io::Result<Self> {
+        let bad = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+        let text = std::fs::read_to_string(path)?;
+        let mut nums = text
+            .split_whitespace()
+            .map(|n| n.parse::<u32>().map_err(|_| bad("Tilemap has a non-numeric entry")));
+        let w = nums.next().ok_or_else(|| bad("Tilemap is missing its width"))?? as usize;
+        let h = nums.next().ok_or_else(|| bad("Tilemap is missing its height"))?? as usize;
+        let gids = nums.collect::<io::Result<Vec<u32>>>()?;
+        if gids.len() != w * h {
+            return Err(bad("Tilemap is the wrong size!"));
+        }
+        let (ids, flips): (Vec<usize>, Vec<TileFlip>) = gids
+            .into_iter()
+            .map(|gid| {
+                let (id, flip) = TileFlip::split_gid(gid);
+                (id as usize, flip)
+            })
+            .unzip();
+        if !ids.iter().all(|tid| tileset.contains(TileID(*tid))) {
+            return Err(bad("Tilemap refers to nonexistent tiles"));
+        }
+        let mut map = Self::new(position, (w, h), tile_size, tileset, ids);
+        map.flips = flips;
+        Ok(map)
+    }
 
     pub fn tile_id_at(&self, Vec2i(x, y): Vec2i) -> TileID {
         // Translate into map coordinates
-        let x = (x - self.position.0) / TILE_SZ as i32;
-        let y = (y - self.position.1) / TILE_SZ as i32;
+        let x = (x - self.position.0) / self.tile_size.0 as i32;
+        let y = (y - self.position.1) / self.tile_size.1 as i32;
         assert!(
             x >= 0 && x < self.dims.0 as i32,
             "Tile X coordinate {} out of bounds {}",
@@ -105,36 +365,225 @@ impl Tilemap {
     pub fn size(&self) -> (usize, usize) {
         self.dims
     }
+    pub fn tile_size(&self) -> (u16, u16) {
+        self.tile_size
+    }
+    /// Is this world-space pixel over some tile of the map?
+    pub fn contains(&self, Vec2i(x, y): Vec2i) -> bool {
+        let (x, y) = (x - self.position.0, y - self.position.1);
+        let (tw, th) = (self.tile_size.0 as usize, self.tile_size.1 as usize);
+        0 <= x && x < (self.dims.0 * tw) as i32 && 0 <= y && y < (self.dims.1 * th) as i32
+    }
     pub fn tile_at(&self, posn: Vec2i) -> Tile {
         self.tileset[self.tile_id_at(posn)]
     }
+    // Is the cell at (col, row) solid? Everything off the map counts as solid.
+    fn solid_cell(&self, col: i32, row: i32) -> bool {
+        if col < 0 || self.dims.0 as i32 <= col || row < 0 || self.dims.1 as i32 <= row {
+            return true;
+        }
+        self.tileset[self.map[row as usize * self.dims.0 + col as usize]].solid
+    }
+    /// Is there a solid tile at this world-space pixel? Off the map counts as solid,
+    /// so things checking before they move won't wander out of the level.
+    /// Tiles with a hitbox are only solid inside it.
+    pub fn is_solid_at(&self, Vec2i(x, y): Vec2i) -> bool {
+        let (tw, th) = (self.tile_size.0 as i32, self.tile_size.1 as i32);
+        let col = (x - self.position.0).div_euclid(tw);
+        let row = (y - self.position.1).div_euclid(th);
+        if !self.solid_cell(col, row) {
+            return false;
+        }
+        if !self.contains(Vec2i(x, y)) {
+            return true;
+        }
+        let tile = self.tileset[self.map[row as usize * self.dims.0 + col as usize]];
+        let hb = tile.hitbox_in(Rect {
+            x: self.position.0 + col * tw,
+            y: self.position.1 + row * th,
+            w: self.tile_size.0,
+            h: self.tile_size.1,
+        });
+        hb.x <= x && x < hb.x + hb.w as i32 && hb.y <= y && y < hb.y + hb.h as i32
+    }
+    /// Which of the cells above, below, left, and right of (x, y) (in map cells) are solid,
+    /// in that order. Like is_solid_at, the edge of the map counts as a wall.
+    pub fn neighbors_solid(&self, (x, y): (usize, usize)) -> [bool; 4] {
+        let (x, y) = (x as i32, y as i32);
+        [
+            self.solid_cell(x, y - 1),
+            self.solid_cell(x, y + 1),
+            self.solid_cell(x - 1, y),
+            self.solid_cell(x + 1, y),
+        ]
+    }
+    /// All the open (non-solid) cells you can walk to from start without going diagonally,
+    /// like a room and everything connected to it. start and the results are map cells
+    /// (column, row), nearest first. Starting on a solid cell or off the map finds nothing.
+    pub fn flood_region(&self, start: Vec2i) -> Vec<Vec2i> {
+        let mut region = vec![];
+        if self.solid_cell(start.0, start.1) {
+            return region;
+        }
+        let mut seen = vec![false; self.dims.0 * self.dims.1];
+        let mut queue = std::collections::VecDeque::new();
+        seen[start.1 as usize * self.dims.0 + start.0 as usize] = true;
+        queue.push_back(start);
+        while let Some(Vec2i(x, y)) = queue.pop_front() {
+            region.push(Vec2i(x, y));
+            for &(nx, ny) in [(x, y - 1), (x, y + 1), (x - 1, y), (x + 1, y)].iter() {
+                // solid_cell also keeps us on the map
+                if self.solid_cell(nx, ny) {
+                    continue;
+                }
+                let i = ny as usize * self.dims.0 + nx as usize;
+                if !seen[i] {
+                    seen[i] = true;
+                    queue.push_back(Vec2i(nx, ny));
+                }
+            }
+        }
+        region
+    }
+    /// Can you see from map cell from to map cell to without looking through a solid tile?
+    /// Walks the cells along a Bresenham line between them; the two ends themselves
+    /// don't block, so a wall can be seen (but not seen past).
+    pub fn line_of_sight(&self, from: Vec2i, to: Vec2i) -> bool {
+        let Vec2i(mut x, mut y) = from;
+        let (dx, dy) = ((to.0 - x).abs(), -(to.1 - y).abs());
+        let (sx, sy) = ((to.0 - x).signum(), (to.1 - y).signum());
+        let mut err = dx + dy;
+        while (x, y) != (to.0, to.1) {
+            if (x, y) != (from.0, from.1) && self.solid_cell(x, y) {
+                return false;
+            }
+            let e2 = 2 * err;
+            if e2 >= dy {
+                err += dy;
+                x += sx;
+            }
+            if e2 <= dx {
+                err += dx;
+                y += sy;
+            }
+        }
+        true
+    }
+    /// Every map cell that r (in world space) overlaps, as (index into the map, cell bounds),
+    /// row by row. Rects that only share an edge with a cell don't count, and the parts
+    /// of r hanging off the map just don't contribute.
+    pub fn tiles_under(&self, r: Rect) -> Vec<(usize, Rect)> {
+        let mut cells = vec![];
+        if r.w == 0 || r.h == 0 {
+            return cells;
+        }
+        let (tw, th) = (self.tile_size.0 as i32, self.tile_size.1 as i32);
+        let (x, y) = (r.x - self.position.0, r.y - self.position.1);
+        let col0 = x.div_euclid(tw).max(0);
+        let row0 = y.div_euclid(th).max(0);
+        let col1 = (x + r.w as i32 - 1).div_euclid(tw).min(self.dims.0 as i32 - 1);
+        let row1 = (y + r.h as i32 - 1).div_euclid(th).min(self.dims.1 as i32 - 1);
+        for row in row0..=row1 {
+            for col in col0..=col1 {
+                cells.push((
+                    row as usize * self.dims.0 + col as usize,
+                    Rect {
+                        x: self.position.0 + col * tw,
+                        y: self.position.1 + row * th,
+                        w: self.tile_size.0,
+                        h: self.tile_size.1,
+                    },
+                ));
+            }
+        }
+        cells
+    }
+    // World-space hitboxes of the solid tiles that r overlaps
+    fn solid_hitboxes_under(&self, r: collision::Rect) -> Vec<Rect> {
+        let r = Rect {
+            x: r.x,
+            y: r.y,
+            w: r.w,
+            h: r.h,
+        };
+        let overlaps = |a: Rect, b: Rect| {
+            a.x < b.x + b.w as i32
+                && b.x < a.x + a.w as i32
+                && a.y < b.y + b.h as i32
+                && b.y < a.y + a.h as i32
+        };
+        self.tiles_under(r)
+            .into_iter()
+            .map(|(idx, cell)| (self.tileset[self.map[idx]], cell))
+            .filter(|(tile, _)| tile.solid)
+            .map(|(tile, cell)| tile.hitbox_in(cell))
+            .filter(|&hb| overlaps(hb, r))
+            .collect()
+    }
+    /// Move m by its velocity, stopping it flush against solid tiles
+    /// (or their hitboxes, for tiles that have one).
+    /// x moves and gets resolved first, then y, so pushing diagonally into a wall
+    /// slides along it instead of sticking. Velocity on a blocked axis goes to zero.
+    pub fn move_and_collide(&self, m: &mut Mobile) {
+        m.rect.x += m.vx;
+        let hits = self.solid_hitboxes_under(m.rect);
+        if !hits.is_empty() {
+            if m.vx > 0 {
+                m.rect.x = hits.iter().map(|hb| hb.x).min().unwrap() - m.rect.w as i32;
+            } else if m.vx < 0 {
+                m.rect.x = hits.iter().map(|hb| hb.x + hb.w as i32).max().unwrap();
+            }
+            m.vx = 0;
+        }
+        m.rect.y += m.vy;
+        let hits = self.solid_hitboxes_under(m.rect);
+        if !hits.is_empty() {
+            if m.vy > 0 {
+                m.rect.y = hits.iter().map(|hb| hb.y).min().unwrap() - m.rect.h as i32;
+            } else if m.vy < 0 {
+                m.rect.y = hits.iter().map(|hb| hb.y + hb.h as i32).max().unwrap();
+            }
+            m.vy = 0;
+        }
+    }
     // ...
     /// Draws the portion of self appearing within screen.
     /// This could just as well be an extension trait on Screen defined in =tiles.rs= or something, like we did for =sprite.rs= and =draw_sprite=.
     pub fn draw(&self, screen: &mut Screen) {
+        self.draw_with(screen, |_| None);
+    }
+    /// Like draw, but every tile gets multiplied by tint (see Screen::bitblt_tinted),
+    /// for darkening a level at night or fading it out between levels.
+    /// Opaque white looks the same as draw.
+    pub fn draw_tinted(&self, screen: &mut Screen, tint: Rgba) {
+        self.draw_with(screen, |_| Some(tint));
+    }
+    // tint gets asked about each cell (by index into the map) as it's drawn
+    fn draw_with(&self, screen: &mut Screen, tint: impl Fn(usize) -> Option<Rgba>) {
         let Rect {
             x: sx,
             y: sy,
             w: sw,
             h: sh,
         } = screen.bounds();
+        let (tw, th) = (self.tile_size.0 as i32, self.tile_size.1 as i32);
 
         // We'll draw from the topmost/leftmost visible tile to the bottommost/rightmost visible tile.
         // The camera combined with out position and size tell us what's visible.
         // leftmost tile: get camera.x into our frame of reference, then divide down to tile units
         // Note that it's also forced inside of 0..self.size.0
-        let left = ((sx - self.position.0) / TILE_SZ as i32)
+        let left = ((sx - self.position.0) / tw)
             .max(0)
             .min(self.dims.0 as i32) as usize;
         // rightmost tile: same deal, but with screen.x + screen.w plus a little padding to be sure we draw the rightmost tile even if it's a bit off screen.
-        let right = ((sx + ((sw + TILE_SZ as u16) as i32) - self.position.0) / TILE_SZ as i32)
+        let right = ((sx + sw as i32 + tw - self.position.0) / tw)
             .max(0)
             .min(self.dims.0 as i32) as usize;
         // ditto top and bot
-        let top = ((sy - self.position.1) / TILE_SZ as i32)
+        let top = ((sy - self.position.1) / th)
             .max(0)
             .min(self.dims.1 as i32) as usize;
-        let bot = ((sy + ((sh + TILE_SZ as u16) as i32) - self.position.1) / TILE_SZ as i32)
+        let bot = ((sy + sh as i32 + th - self.position.1) / th)
             .max(0)
             .min(self.dims.1 as i32) as usize;
         // Now draw the tiles we need to draw where we need to draw them.
@@ -143,16 +592,1330 @@ impl Tilemap {
             .zip(self.map[(top * self.dims.0)..(bot * self.dims.0)].chunks_exact(self.dims.0))
         {
             // We are in tile coordinates at this point so we'll need to translate back to pixel units and world coordinates to draw.
-            let ypx = (y * TILE_SZ) as i32 + self.position.1;
+            let ypx = y as i32 * th + self.position.1;
             // Here we can iterate through the column index and the relevant slice of the row in parallel
             for (x, id) in (left..right).zip(row[left..right].iter()) {
-                let xpx = (x * TILE_SZ) as i32 + self.position.0;
-                let frame = self.tileset.get_rect(*id);
-                screen.bitblt(&self.tileset.texture, frame, Vec2i(xpx, ypx));
+                let xpx = x as i32 * tw + self.position.0;
+                let (tex, frame) = self.tileset.get_frame(*id, self.tile_size);
+                let flip = self.flips[y * self.dims.0 + x];
+                let to = Vec2i(xpx, ypx);
+                match (flip == TileFlip::default(), tint(y * self.dims.0 + x)) {
+                    (true, None) => screen.bitblt(tex, frame, to),
+                    (true, Some(tint)) => screen.bitblt_tinted(tex, frame, to, tint),
+                    (false, tint) => screen.bitblt_flipped_tinted(
+                        tex,
+                        frame,
+                        to,
+                        (flip.horizontal, flip.vertical, flip.diagonal),
+                        tint.unwrap_or(Rgba(255, 255, 255, 255)),
+                    ),
+                }
+            }
+        }
+    }
+}
+/// How much the player knows about a map cell
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum FogState {
+    /// Never seen; drawn solid black
+    Hidden,
+    /// Seen before but not in view right now; drawn dimmed
+    Seen,
+    /// In view right now
+    Visible,
+}
+// Multiplied into Seen tiles
+const FOG_SEEN_TINT: Rgba = Rgba(96, 96, 128, 255);
+const FOG_HIDDEN: Rgba = Rgba(0, 0, 0, 255);
+/// Fog of war over a tilemap, one FogState per cell, for exploration games:
+/// cells light up as the player gets close and stay dimmed once they've been seen.
+pub struct FogOfWar {
+    dims: (usize, usize),
+    cells: Vec<FogState>,
+}
+#[allow(dead_code)]
+impl FogOfWar {
+    /// Everything starts Hidden
+    pub fn new(map: &Tilemap) -> Self {
+        let dims = map.size();
+        Self {
+            dims,
+            cells: vec![FogState::Hidden; dims.0 * dims.1],
+        }
+    }
+    /// The state of map cell (column, row); off the map is always Hidden
+    pub fn state_at(&self, Vec2i(x, y): Vec2i) -> FogState {
+        if x < 0 || self.dims.0 as i32 <= x || y < 0 || self.dims.1 as i32 <= y {
+            return FogState::Hidden;
+        }
+        self.cells[y as usize * self.dims.0 + x as usize]
+    }
+    /// Call whenever the player moves (or every frame, it's cheap enough for small maps).
+    /// Cells within radius cells of player_tile that it has line of sight to become Visible,
+    /// and whatever was Visible before but isn't anymore goes back to Seen.
+    pub fn update_visibility(&mut self, map: &Tilemap, player_tile: Vec2i, radius: u32) {
+        assert_eq!(map.size(), self.dims, "Fog of war is for a different size map");
+        for c in self.cells.iter_mut() {
+            if *c == FogState::Visible {
+                *c = FogState::Seen;
+            }
+        }
+        let r = radius as i32;
+        let Vec2i(px, py) = player_tile;
+        for y in (py - r).max(0)..=(py + r).min(self.dims.1 as i32 - 1) {
+            for x in (px - r).max(0)..=(px + r).min(self.dims.0 as i32 - 1) {
+                let (dx, dy) = (x - px, y - py);
+                if dx * dx + dy * dy <= r * r && map.line_of_sight(player_tile, Vec2i(x, y)) {
+                    self.cells[y as usize * self.dims.0 + x as usize] = FogState::Visible;
+                }
+            }
+        }
+    }
+    /// Draw map with the fog applied, instead of map.draw: Visible cells as usual,
+    /// Seen ones tinted dark, and Hidden ones blacked out.
+    pub fn draw(&self, screen: &mut Screen, map: &Tilemap) {
+        assert_eq!(map.size(), self.dims, "Fog of war is for a different size map");
+        map.draw_with(screen, |i| match self.cells[i] {
+            FogState::Seen => Some(FOG_SEEN_TINT),
+            _ => None,
+        });
+        let hidden: Vec<(Rect, Rgba)> = map
+            .tiles_under(screen.bounds())
+            .into_iter()
+            .filter(|&(i, _)| self.cells[i] == FogState::Hidden)
+            .map(|(_, cell)| (cell, FOG_HIDDEN))
+            .collect();
+        screen.fill_rects(&hidden);
+    }
+}
+const MINIMAP_BG: Rgba = Rgba(16, 16, 32, 255);
+const MINIMAP_SOLID: Rgba = Rgba(200, 200, 200, 255);
+/// Draw a little overview of map into dest, with each entity as a colored dot.
+/// dest is in screen coordinates (it ignores scrolling, like a HUD should).
+/// The map is scaled to fit dest either way, so one tile may be a block of pixels or a fraction of one.
+#[allow(dead_code)]
+pub fn draw_minimap(screen: &mut Screen, map: &Tilemap, entities: &[(Vec2i, Rgba)], dest: Rect) {
+    let (mw, mh) = map.size();
+    if mw == 0 || mh == 0 || dest.w == 0 || dest.h == 0 {
+        return;
+    }
+    // Minimap pixels per tile
+    let scale = (dest.w as f32 / mw as f32).min(dest.h as f32 / mh as f32);
+    let origin = Vec2i(dest.x + screen.position.0, dest.y + screen.position.1);
+    screen.rect(Rect { x: origin.0, y: origin.1, ..dest }, MINIMAP_BG);
+    let w = ((mw as f32 * scale) as i32).min(dest.w as i32);
+    let h = ((mh as f32 * scale) as i32).min(dest.h as i32);
+    // One sample per minimap pixel, so big maps shrink and small maps grow
+    for py in 0..h {
+        let ty = ((py as f32 / scale) as usize).min(mh - 1);
+        for px in 0..w {
+            let tx = ((px as f32 / scale) as usize).min(mw - 1);
+            if map.tileset[map.map[ty * mw + tx]].solid {
+                screen.draw_at(MINIMAP_SOLID, Vec2i(origin.0 + px, origin.1 + py));
+            }
+        }
+    }
+    for (Vec2i(x, y), col) in entities.iter() {
+        let (tw, th) = map.tile_size;
+        let px = ((x - map.position.0) as f32 / tw as f32 * scale).floor() as i32;
+        let py = ((y - map.position.1) as f32 / th as f32 * scale).floor() as i32;
+        if 0 <= px && px < dest.w as i32 && 0 <= py && py < dest.h as i32 {
+            screen.draw_at(*col, Vec2i(origin.0 + px, origin.1 + py));
+        }
+    }
+}
+/// Roguelike-style movement: one whole tile per step, sliding between cells over a few frames.
+pub struct GridMover {
+    pub position: Vec2i,
+    /// How many frames each step takes
+    frames: usize,
+    /// Where the current step started and where it's going, plus how far along it is
+    step: Option<(Vec2i, Vec2i, usize)>,
+}
+#[allow(dead_code)]
+impl GridMover {
+    pub fn new(position: Vec2i, frames: usize) -> Self {
+        Self {
+            position,
+            frames: frames.max(1),
+            step: None,
+        }
+    }
+    pub fn is_moving(&self) -> bool {
+        self.step.is_some()
+    }
+    /// Start stepping one tile in direction dir (like Vec2i(1, 0) for right).
+    /// Does nothing and returns false if we're already mid-step, or the tile there is solid or off the map.
+    pub fn try_move(&mut self, map: &Tilemap, Vec2i(dx, dy): Vec2i) -> bool {
+        if self.is_moving() {
+            return false;
+        }
+        let to = Vec2i(
+            self.position.0 + dx.signum() * map.tile_size.0 as i32,
+            self.position.1 + dy.signum() * map.tile_size.1 as i32,
+        );
+        if to == self.position || !map.contains(to) || map.tile_at(to).solid {
+            return false;
+        }
+        self.step = Some((self.position, to, 0));
+        true
+    }
+    /// Advance the current step by a frame, landing exactly on the new cell at the end
+    pub fn update(&mut self) {
+        if let Some((from, to, elapsed)) = self.step {
+            let elapsed = elapsed + 1;
+            let lerp = |a: i32, b: i32| a + (b - a) * elapsed as i32 / self.frames as i32;
+            self.position = Vec2i(lerp(from.0, to.0), lerp(from.1, to.1));
+            self.step = if elapsed >= self.frames {
+                None
+            } else {
+                Some((from, to, elapsed))
+            };
+        }
+    }
+}
+/// How many tiles wide and tall each chunk of a ChunkedTilemap is
+pub const CHUNK_SZ: usize = 16;
+/// One CHUNK_SZ x CHUNK_SZ block of a ChunkedTilemap
+pub struct Chunk {
+    /// A row-major grid of tile IDs in tileset
+    map: Vec<TileID>,
+}
+/// A tilemap with no fixed size, for huge or procedurally generated worlds.
+/// Tiles live in chunks that get generated the first time something looks at them,
+/// so only the parts of the world near the camera take up memory.
+pub struct ChunkedTilemap {
+    /// Where tile (0, 0) is in space
+    pub position: Vec2i,
+    /// How big each tile is in pixels, same as Tilemap's
+    tile_size: (u16, u16),
+    /// Which tileset is used for every chunk
+    tileset: Rc<Tileset>,
+    /// Loaded chunks, keyed by chunk coordinates (which can be negative)
+    chunks: HashMap<(i32, i32), Chunk>,
+    /// Given chunk coordinates, produces that chunk's CHUNK_SZ * CHUNK_SZ tile IDs in row-major order.
+    /// Should give the same answer every time so unloaded chunks come back the same.
+    generate: Box<dyn Fn(i32, i32) -> Vec<usize>>,
+}
+#[allow(dead_code)]
+impl ChunkedTilemap {
+    pub fn new(
+        position: Vec2i,
+        tile_size: (u16, u16),
+        tileset: &Rc<Tileset>,
+        generate: impl Fn(i32, i32) -> Vec<usize> + 'static,
+    ) -> Self {
+        assert!(tile_size.0 > 0 && tile_size.1 > 0, "Tiles can't be empty");
+        Self {
+            position,
+            tile_size,
+            tileset: Rc::clone(tileset),
+            chunks: HashMap::new(),
+            generate: Box::new(generate),
+        }
+    }
+    /// Look up a tile by tile coordinates, generating its chunk if needed
+    fn tile_id(&mut self, tx: i32, ty: i32) -> TileID {
+        let c = CHUNK_SZ as i32;
+        let key = (tx.div_euclid(c), ty.div_euclid(c));
+        let generate = &self.generate;
+        let tileset = &self.tileset;
+        let chunk = self.chunks.entry(key).or_insert_with(|| {
+            let map = generate(key.0, key.1);
+            assert_eq!(CHUNK_SZ * CHUNK_SZ, map.len(), "Chunk is the wrong size!");
+            assert!(
+                map.iter().all(|tid| tileset.contains(TileID(*tid))),
+                "Chunk refers to nonexistent tiles"
+            );
+            Chunk {
+                map: map.into_iter().map(TileID).collect(),
+            }
+        });
+        chunk.map[(ty.rem_euclid(c) * c + tx.rem_euclid(c)) as usize]
+    }
+    // Which tile a world-space pixel falls in
+    fn tile_coords(&self, Vec2i(x, y): Vec2i) -> (i32, i32) {
+        (
+            (x - self.position.0).div_euclid(self.tile_size.0 as i32),
+            (y - self.position.1).div_euclid(self.tile_size.1 as i32),
+        )
+    }
+    pub fn tile_id_at(&mut self, posn: Vec2i) -> TileID {
+        let (tx, ty) = self.tile_coords(posn);
+        self.tile_id(tx, ty)
+    }
+    pub fn tile_at(&mut self, posn: Vec2i) -> Tile {
+        let id = self.tile_id_at(posn);
+        self.tileset[id]
+    }
+    pub fn tile_size(&self) -> (u16, u16) {
+        self.tile_size
+    }
+    /// How many chunks are in memory right now
+    pub fn loaded_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+    /// Throw away chunks more than margin chunks away from area (usually the screen bounds).
+    /// They'll be regenerated if we come back.
+    pub fn unload_outside(&mut self, area: Rect, margin: i32) {
+        // How big a chunk is in pixels
+        let cw = CHUNK_SZ as i32 * self.tile_size.0 as i32;
+        let ch = CHUNK_SZ as i32 * self.tile_size.1 as i32;
+        let left = (area.x - self.position.0).div_euclid(cw) - margin;
+        let right = (area.x + area.w as i32 - self.position.0).div_euclid(cw) + margin;
+        let top = (area.y - self.position.1).div_euclid(ch) - margin;
+        let bot = (area.y + area.h as i32 - self.position.1).div_euclid(ch) + margin;
+        self.chunks
+            .retain(|&(cx, cy), _| left <= cx && cx <= right && top <= cy && cy <= bot);
+    }
+    /// Draws the portion of self appearing within screen, generating chunks as they come into view.
+    pub fn draw(&mut self, screen: &mut Screen) {
+        let Rect {
+            x: sx,
+            y: sy,
+            w: sw,
+            h: sh,
+        } = screen.bounds();
+        // No edges to clamp against here, so just cover every tile touching the screen
+        let (left, top) = self.tile_coords(Vec2i(sx, sy));
+        let (right, bot) = self.tile_coords(Vec2i(sx + sw as i32, sy + sh as i32));
+        let (tw, th) = (self.tile_size.0 as i32, self.tile_size.1 as i32);
+        for ty in top..=bot {
+            let ypx = ty * th + self.position.1;
+            for tx in left..=right {
+                let xpx = tx * tw + self.position.0;
+                let id = self.tile_id(tx, ty);
+                let (tex, frame) = self.tileset.get_frame(id, self.tile_size);
+                screen.bitblt(tex, frame, Vec2i(xpx, ypx));
+            }
+        }
+    }
+}
+/// A tilemap drawn isometrically: each of the tileset's tile_size images is a diamond
+/// touching all four edges (usually twice as wide as it is tall, like 32x16),
+/// and the grid is laid out on a slant.
+pub struct IsoTilemap {
+    /// Where the cell of tile (0, 0) is in space
+    pub position: Vec2i,
+    /// How big it is
+    dims: (usize, usize),
+    /// How big each tile's image is in pixels
+    tile_size: (u16, u16),
+    /// Which tileset is used for this tilemap
+    tileset: Rc<Tileset>,
+    /// A row-major grid of tile IDs in tileset
+    map: Vec<TileID>,
+}
+#[allow(dead_code)]
+impl IsoTilemap {
+    pub fn new(
+        position: Vec2i,
+        dims: (usize, usize),
+        tile_size: (u16, u16),
+        tileset: &Rc<Tileset>,
+        map: Vec<usize>,
+    ) -> Self {
+        assert!(tile_size.0 > 0 && tile_size.1 > 0, "Tiles can't be empty");
+        assert_eq!(dims.0 * dims.1, map.len(), "Tilemap is the wrong size!");
+        assert!(
+            map.iter().all(|tid| tileset.contains(TileID(*tid))),
+            "Tilemap refers to nonexistent tiles"
+        );
+        Self {
+            position,
+            dims,
+            tile_size,
+            tileset: Rc::clone(tileset),
+            map: map.into_iter().map(TileID).collect(),
+        }
+    }
+    pub fn size(&self) -> (usize, usize) {
+        self.dims
+    }
+    /// Where the cell for tile (col, row) gets drawn.
+    /// Moving one column steps right and down by half a diamond, one row steps left and down.
+    pub fn grid_to_screen(&self, col: i32, row: i32) -> Vec2i {
+        let hw = self.tile_size.0 as i32 / 2;
+        let hh = self.tile_size.1 as i32 / 2;
+        Vec2i(
+            self.position.0 + (col - row) * hw,
+            self.position.1 + (col + row) * hh,
+        )
+    }
+    /// Draws every tile, back to front.
+    /// Anything that overlaps a tile from in front has a column and row at least as big,
+    /// so plain row-major order already paints the far tiles first.
+    pub fn draw(&self, screen: &mut Screen) {
+        for (row, tiles) in self.map.chunks_exact(self.dims.0).enumerate() {
+            for (col, id) in tiles.iter().enumerate() {
+                let (tex, frame) = self.tileset.get_frame(*id, self.tile_size);
+                // bitblt skips anything that ends up off screen
+                let to = self.grid_to_screen(col as i32, row as i32);
+                screen.bitblt(tex, frame, to);
+            }
+        }
+    }
+}
+/// Axial coordinates (q, r) of a hex: q counts along a row, r counts rows,
+/// and the q axis leans so that every neighbor is a fixed offset away.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct HexCoord(pub i32, pub i32);
+#[allow(dead_code)]
+impl HexCoord {
+    /// The six hexes touching this one, going around from the east
+    pub fn neighbors(self) -> [HexCoord; 6] {
+        let HexCoord(q, r) = self;
+        [
+            HexCoord(q + 1, r),
+            HexCoord(q + 1, r - 1),
+            HexCoord(q, r - 1),
+            HexCoord(q - 1, r),
+            HexCoord(q - 1, r + 1),
+            HexCoord(q, r + 1),
+        ]
+    }
+    // Storage is row by row with odd rows shoved right half a tile ("odd-r" offset coordinates)
+    fn to_offset(self) -> (i32, i32) {
+        let HexCoord(q, r) = self;
+        (q + (r - (r & 1)) / 2, r)
+    }
+    fn from_offset(col: i32, row: i32) -> Self {
+        HexCoord(col - (row - (row & 1)) / 2, row)
+    }
+}
+/// A tilemap of pointy-topped hexes. Each tile image fills a tile_size cell;
+/// odd rows sit half a tile to the right and rows overlap so each is 3/4 of a tile below the last.
+pub struct HexTilemap {
+    /// Where the cell of hex (0, 0) is in space
+    pub position: Vec2i,
+    /// How big it is, in columns and rows
+    dims: (usize, usize),
+    /// How big each hex's cell is in pixels
+    tile_size: (u16, u16),
+    /// Which tileset is used for this tilemap
+    tileset: Rc<Tileset>,
+    /// A row-major grid of tile IDs in tileset
+    map: Vec<TileID>,
+}
+#[allow(dead_code)]
+impl HexTilemap {
+    pub fn new(
+        position: Vec2i,
+        dims: (usize, usize),
+        tile_size: (u16, u16),
+        tileset: &Rc<Tileset>,
+        map: Vec<usize>,
+    ) -> Self {
+        assert!(tile_size.0 > 0 && tile_size.1 > 0, "Tiles can't be empty");
+        assert_eq!(dims.0 * dims.1, map.len(), "Tilemap is the wrong size!");
+        assert!(
+            map.iter().all(|tid| tileset.contains(TileID(*tid))),
+            "Tilemap refers to nonexistent tiles"
+        );
+        Self {
+            position,
+            dims,
+            tile_size,
+            tileset: Rc::clone(tileset),
+            map: map.into_iter().map(TileID).collect(),
+        }
+    }
+    pub fn size(&self) -> (usize, usize) {
+        self.dims
+    }
+    /// Top-left corner of the cell a hex gets drawn in
+    pub fn hex_to_pixel(&self, hex: HexCoord) -> Vec2i {
+        let (col, row) = hex.to_offset();
+        let (w, h) = (self.tile_size.0 as i32, self.tile_size.1 as i32);
+        Vec2i(
+            self.position.0 + col * w + (row & 1) * w / 2,
+            self.position.1 + row * h * 3 / 4,
+        )
+    }
+    /// Which hex a world-space pixel falls in (whether or not it's on the map)
+    pub fn pixel_to_hex(&self, Vec2i(x, y): Vec2i) -> HexCoord {
+        let (w, h) = (self.tile_size.0 as f64, self.tile_size.1 as f64);
+        // Measure from the center of hex (0, 0), in units of one column across and one row down.
+        // In those units hex (q, r) is centered at (q + r/2, r).
+        let fy = (y - self.position.1) as f64 / (h * 0.75) - 0.5 / 0.75;
+        let fx = (x - self.position.0) as f64 / w - 0.5;
+        let (fq, fr) = (fx - fy / 2.0, fy);
+        // Round in cube coordinates (q + r + s = 0), fixing up whichever moved the most
+        let fs = -fq - fr;
+        let (mut q, mut r, s) = (fq.round(), fr.round(), fs.round());
+        let (dq, dr, ds) = ((q - fq).abs(), (r - fr).abs(), (s - fs).abs());
+        if dq > dr && dq > ds {
+            q = -r - s;
+        } else if dr > ds {
+            r = -q - s;
+        }
+        HexCoord(q as i32, r as i32)
+    }
+    /// The tile under a world-space pixel, or None off the map
+    pub fn tile_at(&self, posn: Vec2i) -> Option<Tile> {
+        let (col, row) = self.pixel_to_hex(posn).to_offset();
+        if col < 0 || col >= self.dims.0 as i32 || row < 0 || row >= self.dims.1 as i32 {
+            return None;
+        }
+        Some(self.tileset[self.map[row as usize * self.dims.0 + col as usize]])
+    }
+    /// Draws every hex, top row first so lower rows overlap the ones above
+    pub fn draw(&self, screen: &mut Screen) {
+        for (row, tiles) in self.map.chunks_exact(self.dims.0).enumerate() {
+            for (col, id) in tiles.iter().enumerate() {
+                let (tex, frame) = self.tileset.get_frame(*id, self.tile_size);
+                let to = self.hex_to_pixel(HexCoord::from_offset(col as i32, row as i32));
+                screen.bitblt(tex, frame, to);
             }
         }
     }
 }
 /// Indices into a Tileset
-#[derive(Clone, Copy, PartialEq, Eq)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
 pub struct TileID(usize);
+
+impl Drawable for Tilemap {
+    fn draw(&self, screen: &mut Screen) {
+        Tilemap::draw(self, screen);
+    }
+}
+impl Drawable for IsoTilemap {
+    fn draw(&self, screen: &mut Screen) {
+        IsoTilemap::draw(self, screen);
+    }
+}
+impl Drawable for HexTilemap {
+    fn draw(&self, screen: &mut Screen) {
+        HexTilemap::draw(self, screen);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    // n plain tiles on a placeholder sheet big enough for them all
+    fn tileset(n: usize, tile_size: (u16, u16)) -> Rc<Tileset> {
+        let tex = Rc::new(Texture::placeholder(
+            tile_size.0 as u32 * n as u32,
+            tile_size.1 as u32,
+        ));
+        let tiles = vec![
+            Tile {
+                solid: false,
+                hitbox: None,
+            };
+            n
+        ];
+        Rc::new(Tileset::new(tiles, &tex))
+    }
+
+    #[test]
+    fn chunks_generate_on_first_look() {
+        let generated = Rc::new(Cell::new(0));
+        let counter = Rc::clone(&generated);
+        // Tile ID depends on which chunk it's in, so we can tell them apart
+        let mut map =
+            ChunkedTilemap::new(Vec2i(0, 0), (8, 4), &tileset(4, (8, 4)), move |cx, cy| {
+                counter.set(counter.get() + 1);
+                vec![(cx + 2 * cy).rem_euclid(4) as usize; CHUNK_SZ * CHUNK_SZ]
+            });
+        assert_eq!(map.loaded_chunks(), 0);
+        // A chunk is 16 tiles of 8x4 pixels, so (130, 70) is in chunk (1, 1)
+        assert_eq!(map.tile_id_at(Vec2i(130, 70)), TileID(3));
+        assert_eq!((map.loaded_chunks(), generated.get()), (1, 1));
+        // Same chunk again doesn't regenerate it
+        assert_eq!(map.tile_id_at(Vec2i(135, 127)), TileID(3));
+        assert_eq!(generated.get(), 1);
+        // Negative coordinates have chunks too
+        assert_eq!(map.tile_id_at(Vec2i(-1, 0)), TileID(3));
+        assert_eq!(map.loaded_chunks(), 2);
+    }
+    #[test]
+    fn unloaded_chunks_come_back_the_same() {
+        let mut map = ChunkedTilemap::new(Vec2i(0, 0), (8, 8), &tileset(2, (8, 8)), |cx, cy| {
+            (0..CHUNK_SZ * CHUNK_SZ)
+                .map(|i| (i + (cx * cy) as usize) % 2)
+                .collect()
+        });
+        let far = Vec2i(1000, 1000);
+        let before = map.tile_id_at(far);
+        // Keep only what's around the origin
+        map.unload_outside(
+            Rect {
+                x: 0,
+                y: 0,
+                w: 64,
+                h: 64,
+            },
+            0,
+        );
+        assert_eq!(map.loaded_chunks(), 0);
+        assert_eq!(map.tile_id_at(far), before);
+        assert_eq!(map.loaded_chunks(), 1);
+    }
+    #[test]
+    fn chunked_maps_draw_across_chunk_edges() {
+        // Tile 0 is open and red, tile 1 is solid and blue, both 2x2
+        let tex = Rc::new(Texture::new(image::RgbaImage::from_fn(4, 2, |x, _| {
+            if x < 2 {
+                image::Rgba([255, 0, 0, 255])
+            } else {
+                image::Rgba([0, 0, 255, 255])
+            }
+        })));
+        let tile = |solid| Tile {
+            solid,
+            hitbox: None,
+        };
+        let set = Rc::new(Tileset::new(vec![tile(false), tile(true)], &tex));
+        // Chunks alternate like a checkerboard
+        let mut map = ChunkedTilemap::new(Vec2i(0, 0), (2, 2), &set, |cx, cy| {
+            vec![(cx + cy).rem_euclid(2) as usize; CHUNK_SZ * CHUNK_SZ]
+        });
+        assert_eq!(map.tile_size(), (2, 2));
+        // A 4x2 view straddling the edge between chunks (-1, 0) and (0, 0)
+        let mut fb = vec![0_u8; 4 * 2 * 4];
+        map.draw(&mut Screen::wrap(&mut fb, 4, 2, 4, Vec2i(-2, 0)));
+        let row: Vec<u8> = fb[..4 * 4].chunks_exact(4).map(|p| p[2]).collect();
+        assert_eq!(row, vec![255, 255, 0, 0]);
+        assert_eq!(fb[..4 * 4], fb[4 * 4..]);
+        assert_eq!(map.loaded_chunks(), 2);
+        assert!(map.tile_at(Vec2i(-1, 1)).solid);
+        assert!(!map.tile_at(Vec2i(0, 1)).solid);
+    }
+    #[test]
+    fn iso_grid_steps_by_half_diamonds() {
+        let map = IsoTilemap::new(
+            Vec2i(100, 50),
+            (4, 4),
+            (32, 16),
+            &tileset(1, (32, 16)),
+            vec![0; 16],
+        );
+        assert_eq!(map.size(), (4, 4));
+        assert_eq!(map.grid_to_screen(0, 0), Vec2i(100, 50));
+        assert_eq!(map.grid_to_screen(1, 0), Vec2i(116, 58));
+        assert_eq!(map.grid_to_screen(0, 1), Vec2i(84, 58));
+        assert_eq!(map.grid_to_screen(1, 1), Vec2i(100, 66));
+        assert_eq!(map.grid_to_screen(2, 3), Vec2i(84, 90));
+    }
+    #[test]
+    fn tiled_flip_bits_split_off_the_gid() {
+        let (gid, flip) = TileFlip::split_gid(0x8000_0000 | 0x2000_0000 | 7);
+        assert_eq!(gid, 7);
+        assert_eq!(
+            flip,
+            TileFlip {
+                horizontal: true,
+                vertical: false,
+                diagonal: true,
+            }
+        );
+        assert_eq!(flip.gid_bits() | gid, 0xA000_0007);
+        assert_eq!(TileFlip::split_gid(3), (3, TileFlip::default()));
+    }
+    #[test]
+    fn flipped_cells_draw_mirrored() {
+        // One 4x1 tile, a different color in each column
+        let colors = [(255, 0, 0), (0, 255, 0), (0, 0, 255), (255, 255, 0)];
+        let tex = Rc::new(Texture::new(image::RgbaImage::from_fn(4, 1, |x, _| {
+            let (r, g, b) = colors[x as usize];
+            image::Rgba([r, g, b, 255])
+        })));
+        let tiles = vec![Tile {
+            solid: false,
+            hitbox: None,
+        }];
+        let tileset = Rc::new(Tileset::new(tiles, &tex));
+        let mut map = Tilemap::new(Vec2i(0, 0), (2, 1), (4, 1), &tileset, vec![0, 0]);
+        map.set_flip(
+            (1, 0),
+            TileFlip {
+                horizontal: true,
+                ..TileFlip::default()
+            },
+        );
+        let mut fb = vec![0_u8; 8 * 4];
+        map.draw(&mut Screen::wrap(&mut fb, 8, 1, 4, Vec2i(0, 0)));
+        let drawn: Vec<(u8, u8, u8)> = fb.chunks_exact(4).map(|p| (p[0], p[1], p[2])).collect();
+        let mut expected = colors.to_vec();
+        expected.extend(colors.iter().rev());
+        assert_eq!(drawn, expected);
+    }
+    #[test]
+    fn edited_maps_survive_a_save_and_load() {
+        let set = tileset(4, (8, 8));
+        let path = std::env::temp_dir().join(format!("game2-tilemap-{}.txt", std::process::id()));
+        let ids = vec![0, 1, 2, 3, 3, 2, 1, 0];
+        let original = Tilemap::new(Vec2i(0, 0), (4, 2), (8, 8), &set, ids);
+        original.save(&path).unwrap();
+
+        let mut map = Tilemap::load(&path, Vec2i(0, 0), (8, 8), &set).unwrap();
+        assert_eq!(map.size(), (4, 2));
+        map.set_tile((2, 1), 3);
+        let flip = TileFlip {
+            horizontal: true,
+            vertical: false,
+            diagonal: true,
+        };
+        map.set_flip((0, 0), flip);
+        map.save(&path).unwrap();
+
+        let reloaded = Tilemap::load(&path, Vec2i(0, 0), (8, 8), &set).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let expected = [0, 1, 2, 3, 3, 2, 3, 0];
+        for (i, &id) in expected.iter().enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            let at = reloaded.tile_id_at(Vec2i(x as i32 * 8, y as i32 * 8));
+            assert_eq!(at, TileID(id), "at ({}, {})", x, y);
+        }
+        assert_eq!(reloaded.flip_at((0, 0)), flip);
+        assert_eq!(reloaded.flip_at((1, 0)), TileFlip::default());
+    }
+    #[test]
+    fn hex_rows_stagger_and_stack_at_three_quarters() {
+        let set = tileset(1, (16, 20));
+        let hexes = HexTilemap::new(Vec2i(100, 50), (3, 3), (16, 20), &set, vec![0; 9]);
+        assert_eq!(hexes.hex_to_pixel(HexCoord(0, 0)), Vec2i(100, 50));
+        assert_eq!(hexes.hex_to_pixel(HexCoord(1, 0)), Vec2i(116, 50));
+        // Odd rows are shoved half a tile right, and each row is 3/4 of a tile lower
+        assert_eq!(hexes.hex_to_pixel(HexCoord(0, 1)), Vec2i(108, 65));
+        // Axial q leans, so q = 0 on row 2 is the second column
+        assert_eq!(hexes.hex_to_pixel(HexCoord(0, 2)), Vec2i(116, 80));
+        // Picking the middle of any hex's cell gets that hex back
+        for row in 0..3 {
+            for col in 0..3 {
+                let hex = HexCoord::from_offset(col, row);
+                let Vec2i(x, y) = hexes.hex_to_pixel(hex);
+                assert_eq!(hexes.pixel_to_hex(Vec2i(x + 8, y + 10)), hex);
+                assert!(hexes.tile_at(Vec2i(x + 8, y + 10)).is_some());
+            }
+        }
+        assert!(hexes.tile_at(Vec2i(90, 40)).is_none());
+    }
+    #[test]
+    fn hexes_draw_in_their_cells() {
+        let set = tileset(1, (4, 4));
+        let hexes = HexTilemap::new(Vec2i(0, 0), (1, 2), (4, 4), &set, vec![0, 0]);
+        assert_eq!(hexes.size(), (1, 2));
+        let mut fb = vec![0_u8; 8 * 8 * 4];
+        let mut screen = Screen::wrap(&mut fb, 8, 8, 4, Vec2i(0, 0));
+        hexes.draw(&mut screen);
+        let alpha = |x: usize, y: usize| fb[(y * 8 + x) * 4 + 3];
+        // Row 0 covers (0..4, 0..4) and row 1 covers (2..6, 3..7)
+        assert_eq!((alpha(0, 0), alpha(5, 6)), (255, 255));
+        assert_eq!((alpha(6, 1), alpha(1, 6), alpha(6, 7)), (0, 0, 0));
+    }
+    #[test]
+    fn central_hex_has_six_touching_neighbors() {
+        // Regular hexes are about 0.87 times as wide as they are tall
+        let set = tileset(1, (14, 16));
+        let hexes = HexTilemap::new(Vec2i(0, 0), (5, 5), (14, 16), &set, vec![0; 25]);
+        let center = HexCoord::from_offset(2, 2);
+        let mut found = center.neighbors().to_vec();
+        // Row by row, left to right
+        found.sort_by_key(|h| (h.to_offset().1, h.to_offset().0));
+        // Offset (col, row) of the neighbors of an even row's hex
+        let expected: Vec<HexCoord> = [(1, 1), (2, 1), (1, 2), (3, 2), (1, 3), (2, 3)]
+            .iter()
+            .map(|&(c, r)| HexCoord::from_offset(c, r))
+            .collect();
+        assert_eq!(found, expected);
+        // And they're all the same distance away on screen
+        let Vec2i(cx, cy) = hexes.hex_to_pixel(center);
+        for n in center.neighbors().iter() {
+            let Vec2i(x, y) = hexes.hex_to_pixel(*n);
+            let d = ((x - cx) * (x - cx) + (y - cy) * (y - cy)) as f64;
+            assert!(
+                (d.sqrt() - 14.0).abs() < 1.0,
+                "{:?} is {} away",
+                n,
+                d.sqrt()
+            );
+            assert!(n.neighbors().contains(&center));
+        }
+    }
+    #[test]
+    fn grid_moves_go_one_tile_or_nowhere() {
+        let tex = Rc::new(Texture::placeholder(16, 8));
+        let open = Tile {
+            solid: false,
+            hitbox: None,
+        };
+        let wall = Tile {
+            solid: true,
+            hitbox: None,
+        };
+        let set = Rc::new(Tileset::new(vec![open, wall], &tex));
+        #[rustfmt::skip]
+        let cells = vec![
+            0, 0, 0,
+            0, 0, 1,
+            0, 0, 0,
+        ];
+        let map = Tilemap::new(Vec2i(0, 0), (3, 3), (8, 8), &set, cells);
+        let mut mover = GridMover::new(Vec2i(8, 8), 4);
+        // A wall to the right
+        assert!(!mover.try_move(&map, Vec2i(1, 0)));
+        mover.update();
+        assert_eq!(mover.position, Vec2i(8, 8));
+        assert!(!mover.is_moving());
+
+        assert!(mover.try_move(&map, Vec2i(0, 1)));
+        mover.update();
+        mover.update();
+        assert_eq!(mover.position, Vec2i(8, 12));
+        // Mid-step, other moves are ignored
+        assert!(!mover.try_move(&map, Vec2i(-1, 0)));
+        mover.update();
+        mover.update();
+        assert_eq!(mover.position, Vec2i(8, 16));
+        assert!(!mover.is_moving());
+        // Off the bottom of the map counts as blocked too
+        assert!(!mover.try_move(&map, Vec2i(0, 1)));
+    }
+    // A tileset whose tile 0 is open and tile 1 is solid
+    fn open_and_solid() -> Rc<Tileset> {
+        let tex = Rc::new(Texture::placeholder(16, 8));
+        let tile = |solid| Tile {
+            solid,
+            hitbox: None,
+        };
+        Rc::new(Tileset::new(vec![tile(false), tile(true)], &tex))
+    }
+    #[test]
+    fn minimap_scales_small_maps_up() {
+        let mut cells = vec![0; 8];
+        cells[4 + 3] = 1;
+        let map = Tilemap::new(Vec2i(0, 0), (4, 2), (8, 8), &open_and_solid(), cells);
+        let mut fb = vec![0_u8; 12 * 12 * 4];
+        let mut screen = Screen::wrap(&mut fb, 12, 12, 4, Vec2i(0, 0));
+        let dest = Rect {
+            x: 2,
+            y: 1,
+            w: 8,
+            h: 8,
+        };
+        let red = Rgba(255, 0, 0, 255);
+        // One in the middle of tile (1, 0), one way off the map
+        let entities = [(Vec2i(12, 4), red), (Vec2i(1000, 4), red)];
+        draw_minimap(&mut screen, &map, &entities, dest);
+        let at = |x: usize, y: usize| {
+            let i = (y * 12 + x) * 4;
+            Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+        };
+        // Two minimap pixels per tile, so tile (3, 1) is the 2x2 block at (6, 2) in the box
+        for y in 0..12 {
+            for x in 0..12 {
+                let in_box = (2..10).contains(&x) && (1..9).contains(&y);
+                let expected = if (8..10).contains(&x) && (3..5).contains(&y) {
+                    MINIMAP_SOLID
+                } else if (x, y) == (5, 2) {
+                    red
+                } else if in_box {
+                    MINIMAP_BG
+                } else {
+                    Rgba(0, 0, 0, 0)
+                };
+                assert_eq!(at(x, y), expected, "at ({}, {})", x, y);
+            }
+        }
+    }
+    #[test]
+    fn minimap_shrinks_big_maps() {
+        let mut cells = vec![0; 16 * 16];
+        cells[8 * 16 + 8] = 1;
+        let map = Tilemap::new(Vec2i(0, 0), (16, 16), (8, 8), &open_and_solid(), cells);
+        let mut fb = vec![0_u8; 4 * 4 * 4];
+        let mut screen = Screen::wrap(&mut fb, 4, 4, 4, Vec2i(0, 0));
+        let dest = Rect {
+            x: 0,
+            y: 0,
+            w: 4,
+            h: 4,
+        };
+        draw_minimap(&mut screen, &map, &[], dest);
+        // A quarter of a pixel per tile; only the pixel sampling tile (8, 8) is solid
+        let solid: Vec<usize> = fb
+            .chunks_exact(4)
+            .enumerate()
+            .filter(|(_, p)| *p == [200, 200, 200, 255])
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(solid, vec![2 * 4 + 2]);
+    }
+    #[test]
+    fn big_and_non_square_tiles_map_pixels_to_cells() {
+        let set = open_and_solid();
+        #[rustfmt::skip]
+        let cells = vec![
+            0, 1, 0,
+            1, 0, 0,
+        ];
+        let map = Tilemap::new(Vec2i(0, 0), (3, 2), (32, 24), &set, cells);
+        // (33, 0) is just into the second 32 pixel column
+        assert_eq!(map.tile_id_at(Vec2i(33, 0)), TileID(1));
+        assert_eq!(map.tile_id_at(Vec2i(31, 0)), TileID(0));
+        // Rows are 24 tall
+        assert_eq!(map.tile_id_at(Vec2i(0, 23)), TileID(0));
+        assert_eq!(map.tile_id_at(Vec2i(0, 24)), TileID(1));
+        assert!(map.tile_at(Vec2i(40, 10)).solid);
+        assert!(!map.is_solid_at(Vec2i(40, 30)));
+        assert!(map.is_solid_at(Vec2i(10, 30)));
+    }
+    #[test]
+    fn diagonal_moves_slide_along_walls() {
+        // A wall down the right side of a room with a floor
+        #[rustfmt::skip]
+        let cells = vec![
+            0, 0, 0, 1,
+            0, 0, 0, 1,
+            0, 0, 0, 1,
+            0, 0, 0, 1,
+            1, 1, 1, 1,
+        ];
+        let map = Tilemap::new(Vec2i(0, 0), (4, 5), (8, 8), &open_and_solid(), cells);
+        let mut m = Mobile {
+            rect: collision::Rect {
+                x: 12,
+                y: 2,
+                w: 6,
+                h: 6,
+            },
+            vx: 3,
+            vy: 2,
+        };
+        map.move_and_collide(&mut m);
+        assert_eq!((m.rect.x, m.rect.y), (15, 4));
+        // Landing exactly flush with the wall isn't hitting it
+        map.move_and_collide(&mut m);
+        assert_eq!((m.rect.x, m.rect.y, m.vx, m.vy), (18, 6, 3, 2));
+        // Into the wall: x stays put, but y keeps going
+        map.move_and_collide(&mut m);
+        assert_eq!((m.rect.x, m.rect.y, m.vx, m.vy), (18, 8, 0, 2));
+        m.vx = 3;
+        for _ in 0..20 {
+            map.move_and_collide(&mut m);
+            m.vx = 3;
+            m.vy = 2;
+        }
+        // Wedged in the corner, resting exactly on the tile edges without jittering
+        assert_eq!((m.rect.x, m.rect.y), (18, 26));
+        map.move_and_collide(&mut m);
+        assert_eq!((m.rect.x, m.rect.y), (18, 26));
+    }
+    #[test]
+    fn one_marked_mask_cell_makes_one_solid_tile() {
+        // Six 4x4 tiles, three across and two down
+        let texture = Rc::new(Texture::placeholder(12, 8));
+        let mut mask = image::RgbaImage::from_pixel(12, 8, image::Rgba([0, 0, 0, 255]));
+        // One dim pixel in the middle cell of the bottom row
+        mask.put_pixel(6, 7, image::Rgba([0, 0, 1, 255]));
+        let path = std::env::temp_dir().join(format!("game2-mask-{}.png", std::process::id()));
+        mask.save(&path).unwrap();
+        let set = Tileset::solids_from_mask(&texture, &path, (4, 4)).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let solid: Vec<usize> = (0..6).filter(|&i| set[TileID(i)].solid).collect();
+        assert_eq!(solid, vec![4]);
+    }
+    #[test]
+    fn sprite_straddling_four_tiles_finds_all_four() {
+        let set = tileset(1, (8, 8));
+        // 4x3 tiles of 8x8, with the map's corner at (100, 50)
+        let map = Tilemap::new(Vec2i(100, 50), (4, 3), (8, 8), &set, vec![0; 12]);
+        let cell = |col: i32, row: i32| Rect {
+            x: 100 + col * 8,
+            y: 50 + row * 8,
+            w: 8,
+            h: 8,
+        };
+        // 6x6 around the corner where (1, 0), (2, 0), (1, 1), and (2, 1) meet
+        let sprite = Rect {
+            x: 100 + 13,
+            y: 50 + 5,
+            w: 6,
+            h: 6,
+        };
+        assert_eq!(
+            map.tiles_under(sprite),
+            vec![
+                (1, cell(1, 0)),
+                (2, cell(2, 0)),
+                (5, cell(1, 1)),
+                (6, cell(2, 1))
+            ]
+        );
+        // Sharing an edge with the next column over doesn't count
+        let flush = Rect { w: 3, ..sprite };
+        assert_eq!(map.tiles_under(flush).len(), 2);
+        // Bigger than a tile: everything it covers, not just the corners
+        let big = Rect {
+            x: 100,
+            y: 50,
+            w: 24,
+            h: 24,
+        };
+        assert_eq!(map.tiles_under(big).len(), 9);
+        // Off the map entirely
+        assert!(map.tiles_under(Rect { x: 0, ..sprite }).is_empty());
+    }
+    #[test]
+    fn half_black_tint_halves_the_brightness() {
+        let tex = Rc::new(Texture::new(image::RgbaImage::from_pixel(
+            4,
+            4,
+            image::Rgba([200, 100, 40, 255]),
+        )));
+        let tiles = vec![Tile {
+            solid: false,
+            hitbox: None,
+        }];
+        let tileset = Rc::new(Tileset::new(tiles, &tex));
+        let map = Tilemap::new(Vec2i(0, 0), (2, 2), (4, 4), &tileset, vec![0; 4]);
+        let render = |tint: Option<Rgba>| {
+            let mut fb = vec![0_u8; 8 * 8 * 4];
+            let mut screen = Screen::wrap(&mut fb, 8, 8, 4, Vec2i(0, 0));
+            match tint {
+                Some(tint) => map.draw_tinted(&mut screen, tint),
+                None => map.draw(&mut screen),
+            }
+            fb
+        };
+        let plain = render(None);
+        assert_eq!(render(Some(Rgba(255, 255, 255, 255))), plain);
+        // Halfway to black
+        let dim = render(Some(Rgba(128, 128, 128, 255)));
+        let sample = (5 * 8 + 6) * 4;
+        for (c, &full) in [200_i32, 100, 40].iter().enumerate() {
+            let half = dim[sample + c] as i32;
+            assert!((half - full / 2).abs() <= 1, "{} vs {}", half, full);
+        }
+        assert_eq!(dim[sample + 3], 255);
+    }
+    #[test]
+    fn tiles_blit_from_their_own_sheet() {
+        let sheet = |colors: &[[u8; 4]]| {
+            let colors = colors.to_vec();
+            Rc::new(Texture::new(image::RgbaImage::from_fn(
+                colors.len() as u32,
+                1,
+                |x, _| image::Rgba(colors[x as usize]),
+            )))
+        };
+        let open = |n| {
+            vec![
+                Tile {
+                    solid: false,
+                    hitbox: None,
+                };
+                n
+            ]
+        };
+        // Two 1x1 terrain tiles, then one object tile on a sheet of its own
+        let terrain = sheet(&[[255, 0, 0, 255], [0, 255, 0, 255]]);
+        let objects = sheet(&[[0, 0, 255, 255]]);
+        let set = Rc::new(Tileset::with_sheets(vec![
+            (open(2), terrain),
+            (open(1), objects),
+        ]));
+        let map = Tilemap::new(Vec2i(0, 0), (4, 1), (1, 1), &set, vec![2, 0, 1, 2]);
+        let mut fb = vec![0_u8; 4 * 4];
+        map.draw(&mut Screen::wrap(&mut fb, 4, 1, 4, Vec2i(0, 0)));
+        let drawn: Vec<&[u8]> = fb.chunks_exact(4).collect();
+        assert_eq!(
+            drawn,
+            vec![
+                &[0, 0, 255, 255][..],
+                &[255, 0, 0, 255],
+                &[0, 255, 0, 255],
+                &[0, 0, 255, 255],
+            ]
+        );
+    }
+    #[test]
+    fn solidity_queries_treat_the_edge_as_wall() {
+        // A 3x3 map of 8x8 tiles at (16, 16), solid only in the top middle
+        #[rustfmt::skip]
+        let cells = vec![
+            0, 1, 0,
+            0, 0, 0,
+            0, 0, 0,
+        ];
+        let map = Tilemap::new(Vec2i(16, 16), (3, 3), (8, 8), &open_and_solid(), cells);
+        // Up, down, left, right
+        assert_eq!(map.neighbors_solid((1, 1)), [true, false, false, false]);
+        // Corners are walled in on their two outside edges
+        assert_eq!(map.neighbors_solid((0, 0)), [true, false, true, true]);
+        assert_eq!(map.neighbors_solid((2, 2)), [false, true, false, true]);
+        assert!(map.is_solid_at(Vec2i(16 + 8, 16)));
+        assert!(map.is_solid_at(Vec2i(16 + 15, 16 + 7)));
+        assert!(!map.is_solid_at(Vec2i(16 + 16, 16 + 7)));
+        assert!(!map.is_solid_at(Vec2i(16, 16 + 8)));
+        // Just off the map on every side
+        for &p in &[Vec2i(15, 20), Vec2i(20, 15), Vec2i(40, 20), Vec2i(20, 40)] {
+            assert!(map.is_solid_at(p), "{:?}", p);
+        }
+    }
+    #[test]
+    fn mixed_drawables_render_in_order() {
+        use crate::animation::Animation;
+        use crate::sprite::Sprite;
+        let solid = |col: [u8; 4]| {
+            let img = image::RgbaImage::from_pixel(1, 1, image::Rgba(col));
+            Rc::new(Texture::new(img))
+        };
+        let tiles = vec![Tile {
+            solid: false,
+            hitbox: None,
+        }];
+        let set = Rc::new(Tileset::new(tiles, &solid([255, 0, 0, 255])));
+        let frame = Rect {
+            x: 0,
+            y: 0,
+            w: 1,
+            h: 1,
+        };
+        let anim = Rc::new(Animation::new(vec![frame]));
+        let blue = solid([0, 0, 255, 255]);
+        // A row of three red tiles, and a blue sprite over the middle one
+        let map = || Box::new(Tilemap::new(Vec2i(0, 0), (3, 1), (1, 1), &set, vec![0; 3]));
+        let sprite = || Box::new(Sprite::new(&blue, &anim, frame, 0.0, Vec2i(1, 0)));
+        // The blue channel of each pixel after drawing steps in order
+        let render = |steps: Vec<Box<dyn Drawable>>| {
+            let mut fb = vec![0_u8; 3 * 4];
+            let mut screen = Screen::wrap(&mut fb, 3, 1, 4, Vec2i(0, 0));
+            for step in steps.iter() {
+                step.draw(&mut screen);
+            }
+            fb.chunks_exact(4).map(|p| p[2]).collect::<Vec<u8>>()
+        };
+        assert_eq!(render(vec![map(), sprite()]), vec![0, 255, 0]);
+        assert_eq!(render(vec![sprite(), map()]), vec![0, 0, 0]);
+    }
+    #[test]
+    fn player_marker_becomes_one_spawn_and_an_empty_tile() {
+        let mut cells = vec![0; 4 * 3];
+        cells[4 + 2] = 2;
+        let mut map = Tilemap::new(Vec2i(0, 0), (4, 3), (8, 8), &tileset(3, (8, 8)), cells);
+        let spawns = map.take_markers(&[(2, "player")], 0);
+        assert_eq!(spawns, vec![("player", 2, 1)]);
+        assert_eq!(map.tile_id_at(Vec2i(16, 8)), TileID(0));
+        // The marker is gone, so there's nothing left to find
+        assert!(map.take_markers(&[(2, "player")], 0).is_empty());
+    }
+    #[test]
+    fn flooding_one_room_stops_at_the_wall() {
+        // Two rooms with a wall between them
+        #[rustfmt::skip]
+        let cells = vec![
+            0, 0, 1, 0,
+            0, 0, 1, 0,
+            0, 0, 1, 0,
+        ];
+        let map = Tilemap::new(Vec2i(0, 0), (4, 3), (8, 8), &open_and_solid(), cells);
+        let mut left = map.flood_region(Vec2i(0, 0));
+        assert_eq!(left.len(), 6);
+        assert_eq!(left[0], Vec2i(0, 0));
+        left.sort_by_key(|&Vec2i(x, y)| (y, x));
+        let expected: Vec<Vec2i> = (0..3)
+            .flat_map(|y| (0..2).map(move |x| Vec2i(x, y)))
+            .collect();
+        assert_eq!(left, expected);
+        let right = map.flood_region(Vec2i(3, 1));
+        assert_eq!(right.len(), 3);
+        assert!(right.iter().all(|c| c.0 == 3));
+        assert!(map.flood_region(Vec2i(2, 0)).is_empty());
+    }
+    #[test]
+    fn fog_lifts_in_sight_and_settles_to_seen() {
+        // One wall tile in an open 5x5 room
+        let mut cells = vec![0; 5 * 5];
+        cells[2 * 5 + 1] = 1;
+        let map = Tilemap::new(Vec2i(0, 0), (5, 5), (8, 8), &open_and_solid(), cells);
+        let mut fog = FogOfWar::new(&map);
+        assert_eq!(fog.state_at(Vec2i(0, 2)), FogState::Hidden);
+        fog.update_visibility(&map, Vec2i(0, 2), 2);
+        // In range and in sight, including the wall itself
+        for &(x, y) in &[(0, 2), (0, 0), (1, 1), (1, 2), (0, 4)] {
+            assert_eq!(fog.state_at(Vec2i(x, y)), FogState::Visible, "{:?}", (x, y));
+        }
+        // Behind the wall, and out of range
+        assert_eq!(fog.state_at(Vec2i(2, 2)), FogState::Hidden);
+        assert_eq!(fog.state_at(Vec2i(2, 0)), FogState::Hidden);
+        // Walking off leaves what we saw dimmed
+        fog.update_visibility(&map, Vec2i(4, 4), 1);
+        assert_eq!(fog.state_at(Vec2i(0, 2)), FogState::Seen);
+        assert_eq!(fog.state_at(Vec2i(1, 2)), FogState::Seen);
+        assert_eq!(fog.state_at(Vec2i(4, 3)), FogState::Visible);
+        assert_eq!(fog.state_at(Vec2i(2, 2)), FogState::Hidden);
+    }
+    #[test]
+    fn fog_draws_hidden_black_and_seen_tinted() {
+        // Three plain white 2x2 cells in a row
+        let white = image::RgbaImage::from_pixel(2, 2, image::Rgba([255, 255, 255, 255]));
+        let tile = Tile {
+            solid: false,
+            hitbox: None,
+        };
+        let set = Rc::new(Tileset::new(vec![tile], &Rc::new(Texture::new(white))));
+        let map = Tilemap::new(Vec2i(0, 0), (3, 1), (2, 2), &set, vec![0; 3]);
+        // Looking from the right end leaves the left cell Hidden,
+        // then stepping back out of sight of the middle one leaves it Seen
+        let mut fog = FogOfWar::new(&map);
+        fog.update_visibility(&map, Vec2i(2, 0), 1);
+        fog.update_visibility(&map, Vec2i(2, 0), 0);
+        let states: Vec<FogState> = (0..3).map(|x| fog.state_at(Vec2i(x, 0))).collect();
+        assert_eq!(
+            states,
+            [FogState::Hidden, FogState::Seen, FogState::Visible]
+        );
+        let mut fb = vec![0_u8; 6 * 2 * 4];
+        fog.draw(&mut Screen::wrap(&mut fb, 6, 2, 4, Vec2i(0, 0)), &map);
+        let at = |x: usize, y: usize| {
+            let i = (y * 6 + x) * 4;
+            Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+        };
+        for y in 0..2 {
+            for x in 0..2 {
+                assert_eq!(at(x, y), FOG_HIDDEN);
+                // White times the tint is just the tint
+                assert_eq!(at(x + 2, y), FOG_SEEN_TINT);
+                assert_eq!(at(x + 4, y), Rgba(255, 255, 255, 255));
+            }
+        }
+    }
+    #[test]
+    fn half_height_ledges_only_block_their_lower_half() {
+        let tex = Rc::new(Texture::placeholder(32, 16));
+        let ledge = Tile {
+            solid: true,
+            hitbox: Some(Rect {
+                x: 0,
+                y: 8,
+                w: 16,
+                h: 8,
+            }),
+        };
+        let open = Tile {
+            solid: false,
+            hitbox: None,
+        };
+        let set = Rc::new(Tileset::new(vec![open, ledge], &tex));
+        let map = Tilemap::new(Vec2i(0, 0), (3, 1), (16, 16), &set, vec![0, 1, 0]);
+        assert!(!map.is_solid_at(Vec2i(20, 7)));
+        assert!(map.is_solid_at(Vec2i(20, 8)));
+        let body = |x, y, vx, vy| Mobile {
+            rect: collision::Rect { x, y, w: 4, h: 4 },
+            vx,
+            vy,
+        };
+        // Walking through the top half goes right over it
+        let mut m = body(8, 0, 8, 0);
+        map.move_and_collide(&mut m);
+        assert_eq!((m.rect.x, m.vx), (16, 8));
+        // but the bottom half is a wall
+        let mut m = body(8, 10, 8, 0);
+        map.move_and_collide(&mut m);
+        assert_eq!((m.rect.x, m.vx), (12, 0));
+        // and something falling onto it lands on the hitbox, not the top of the cell
+        let mut m = body(18, 0, 0, 6);
+        map.move_and_collide(&mut m);
+        assert_eq!((m.rect.y, m.vy), (4, 0));
+    }
+    #[test]
+    fn autotiling_picks_interior_edge_and_corner_variants() {
+        // Variant for neighbor mask m is tile 4 + m
+        let mut variants = [0; 16];
+        for (m, v) in variants.iter_mut().enumerate() {
+            *v = 4 + m;
+        }
+        let rules = AutoTileRules {
+            variants,
+            edges_connect: false,
+        };
+        // A 3x3 block of wall (tile 1) in a 5x5 room
+        #[rustfmt::skip]
+        let cells = vec![
+            0, 0, 0, 0, 0,
+            0, 1, 1, 1, 0,
+            0, 1, 1, 1, 0,
+            0, 1, 1, 1, 0,
+            0, 0, 0, 0, 0,
+        ];
+        let mut map = Tilemap::new(Vec2i(0, 0), (5, 5), (8, 8), &tileset(20, (8, 8)), cells);
+        map.autotile(1, &rules);
+        let id = |x: i32, y: i32| map.tile_id_at(Vec2i(x * 8, y * 8));
+        // Walls all around
+        assert_eq!(id(2, 2), TileID(4 + 15));
+        // Top edge: right, down, and left
+        assert_eq!(id(2, 1), TileID(4 + 0b1110));
+        // Top-left corner: right and down
+        assert_eq!(id(1, 1), TileID(4 + 0b0110));
+        // Bottom-right corner: up and left
+        assert_eq!(id(3, 3), TileID(4 + 0b1001));
+        // The floor is left alone
+        assert_eq!(id(0, 0), TileID(0));
+    }
+    #[test]
+    fn a_sprite_between_layers_is_over_the_floor_and_under_the_treetops() {
+        use crate::animation::Animation;
+        use crate::screen::Scene;
+        use crate::sprite::Sprite;
+        let open = Tile {
+            solid: false,
+            hitbox: None,
+        };
+        let texture = |pixels: &[[u8; 4]]| {
+            let mut img = image::RgbaImage::new(pixels.len() as u32, 1);
+            for (x, &p) in pixels.iter().enumerate() {
+                img.put_pixel(x as u32, 0, image::Rgba(p));
+            }
+            Rc::new(Texture::new(img))
+        };
+        // The floor is all red; the treetop layer is see-through except for green over the end
+        let floor_set = Rc::new(Tileset::new(vec![open], &texture(&[[255, 0, 0, 255]])));
+        let tree_tex = texture(&[[0, 0, 0, 0], [0, 255, 0, 255]]);
+        let tree_set = Rc::new(Tileset::new(vec![open, open], &tree_tex));
+        let floor = Tilemap::new(Vec2i(0, 0), (3, 1), (1, 1), &floor_set, vec![0; 3]);
+        let trees = Tilemap::new(Vec2i(0, 0), (3, 1), (1, 1), &tree_set, vec![0, 0, 1]);
+        // Two pixels wide, from the middle cell to the end
+        let frame = Rect {
+            x: 0,
+            y: 0,
+            w: 2,
+            h: 1,
+        };
+        let anim = Rc::new(Animation::new(vec![frame]));
+        let blue = texture(&[[0, 0, 255, 255], [0, 0, 255, 255]]);
+        let player = Sprite::new(&blue, &anim, frame, 0.0, Vec2i(1, 0));
+
+        let mut fb = vec![0_u8; 3 * 4];
+        let mut screen = Screen::wrap(&mut fb, 3, 1, 4, Vec2i(0, 0));
+        Scene::new()
+            .push(&floor)
+            .push(&player)
+            .push(&trees)
+            .draw(&mut screen);
+        let colors: Vec<&[u8]> = fb.chunks_exact(4).collect();
+        assert_eq!(
+            colors,
+            vec![&[255, 0, 0, 255][..], &[0, 0, 255, 255], &[0, 255, 0, 255]]
+        );
+    }
+    #[test]
+    fn mask_the_wrong_size_is_an_error() {
+        let texture = Rc::new(Texture::placeholder(12, 8));
+        let mask = image::RgbaImage::from_pixel(8, 8, image::Rgba([0, 0, 0, 255]));
+        let path = std::env::temp_dir().join(format!("game2-bad-mask-{}.png", std::process::id()));
+        mask.save(&path).unwrap();
+        let result = Tileset::solids_from_mask(&texture, &path, (4, 4));
+        std::fs::remove_file(&path).unwrap();
+        let err = result.err().expect("a mismatched mask should be refused");
+        assert_eq!(err.path, path);
+        // And a mask that isn't there at all
+        let missing = std::env::temp_dir().join("game2-no-such-mask.png");
+        assert!(Tileset::solids_from_mask(&texture, &missing, (4, 4)).is_err());
+    }
+}
@@ -0,0 +1,64 @@
+/// Counts simulated frames for cooldowns and timed events.
+/// Call tick once per update_game; it's ready once `duration` ticks have gone by.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Timer {
+    duration: usize,
+    elapsed: usize,
+}
+
+#[allow(dead_code)]
+impl Timer {
+    pub fn new(duration: usize) -> Self {
+        Self {
+            duration,
+            elapsed: 0,
+        }
+    }
+    pub fn tick(&mut self) {
+        // No need to keep counting once we're ready
+        if self.elapsed < self.duration {
+            self.elapsed += 1;
+        }
+    }
+    pub fn is_ready(&self) -> bool {
+        self.elapsed >= self.duration
+    }
+    /// Start counting from zero again
+    pub fn reset(&mut self) {
+        self.elapsed = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ready_after_exactly_its_duration() {
+        let mut t = Timer::new(5);
+        for _ in 0..4 {
+            t.tick();
+            assert!(!t.is_ready());
+        }
+        t.tick();
+        assert!(t.is_ready());
+        // Stays ready until it's reset
+        t.tick();
+        assert!(t.is_ready());
+    }
+    #[test]
+    fn reset_starts_the_count_over() {
+        let mut t = Timer::new(5);
+        for _ in 0..7 {
+            t.tick();
+        }
+        t.reset();
+        assert!(!t.is_ready());
+        for _ in 0..4 {
+            t.tick();
+        }
+        assert!(!t.is_ready());
+        t.tick();
+        assert!(t.is_ready());
+    }
+}
@@ -6,10 +6,118 @@ pub struct Rect {
     pub h: u16,
 }
 
+impl Rect {
+    /// Grow the rect by dx on the left and right and dy on the top and bottom,
+    /// keeping the same center. Negative margins shrink it; width and height
+    /// bottom out at zero instead of wrapping around.
+    #[allow(dead_code)]
+    pub fn inflate(&self, dx: i32, dy: i32) -> Rect {
+        let w = (self.w as i32 + 2 * dx).max(0).min(u16::MAX as i32);
+        let h = (self.h as i32 + 2 * dy).max(0).min(u16::MAX as i32);
+        Rect {
+            // Recenter using the size we actually ended up with
+            x: self.x + (self.w as i32 - w) / 2,
+            y: self.y + (self.h as i32 - h) / 2,
+            w: w as u16,
+            h: h as u16,
+        }
+    }
+}
+
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub struct Vec2i(pub i32, pub i32);
 
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub struct Vec2f(pub f32, pub f32);
+
+/// Red, green, blue, alpha, in that order.
+/// That's also the byte order pixels wants in its rgba8888 framebuffer,
+/// so a color goes into the buffer as [r, g, b, a] with no shuffling.
 #[derive(PartialEq, Eq, Clone, Copy, Hash, Debug)]
 pub struct Rgba(pub u8, pub u8, pub u8, pub u8);
 
+impl Rgba {
+    /// Pack into 0xAARRGGBB, the usual hex color layout.
+    /// Note this is NOT the framebuffer's byte order (see above).
+    #[allow(dead_code)]
+    pub fn to_u32(self) -> u32 {
+        (self.3 as u32) << 24 | (self.0 as u32) << 16 | (self.1 as u32) << 8 | self.2 as u32
+    }
+    /// Unpack from 0xAARRGGBB
+    #[allow(dead_code)]
+    pub fn from_u32(argb: u32) -> Self {
+        Self(
+            (argb >> 16) as u8,
+            (argb >> 8) as u8,
+            argb as u8,
+            (argb >> 24) as u8,
+        )
+    }
+    /// Same brightness, no color (Rec. 601 luma weights). Alpha is kept.
+    #[allow(dead_code)]
+    pub fn grayscale(&self) -> Rgba {
+        let luma = 0.299 * self.0 as f32 + 0.587 * self.1 as f32 + 0.114 * self.2 as f32;
+        let l = luma.round().min(255.0) as u8;
+        Rgba(l, l, l, self.3)
+    }
+    /// Scale the color channels by factor (2.0 is twice as bright), saturating at 255.
+    /// Alpha is kept.
+    #[allow(dead_code)]
+    pub fn brighten(&self, factor: f32) -> Rgba {
+        let scale = |c: u8| (c as f32 * factor.max(0.0)).round().min(255.0) as u8;
+        Rgba(scale(self.0), scale(self.1), scale(self.2), self.3)
+    }
+}
+
 // Feel free to add impl blocks with convenience functions
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inflate_grows_about_the_center() {
+        let r = Rect { x: 10, y: 20, w: 8, h: 4 };
+        assert_eq!(r.inflate(2, 3), Rect { x: 8, y: 17, w: 12, h: 10 });
+    }
+    #[test]
+    fn deflate_bottoms_out_at_zero() {
+        let r = Rect { x: 10, y: 20, w: 8, h: 4 };
+        assert_eq!(r.inflate(-1, -1), Rect { x: 11, y: 21, w: 6, h: 2 });
+        let gone = r.inflate(-4, -2);
+        assert_eq!((gone.w, gone.h), (0, 0));
+        // The empty rect sits at the old center
+        assert_eq!((gone.x, gone.y), (14, 22));
+    }
+    #[test]
+    fn inflate_never_wraps_the_size() {
+        let r = Rect { x: 0, y: 0, w: 3, h: 3 };
+        let shrunk = r.inflate(-100, -100);
+        assert_eq!((shrunk.w, shrunk.h), (0, 0));
+        let big = Rect { x: 0, y: 0, w: u16::MAX - 1, h: 1 }.inflate(10, 0);
+        assert_eq!(big.w, u16::MAX);
+    }
+    #[test]
+    fn packed_colors_round_trip() {
+        for &c in &[
+            Rgba(0, 0, 0, 0),
+            Rgba(255, 255, 255, 255),
+            Rgba(12, 34, 56, 78),
+            Rgba(255, 0, 128, 1),
+        ] {
+            assert_eq!(Rgba::from_u32(c.to_u32()), c);
+        }
+        assert_eq!(Rgba(0x11, 0x22, 0x33, 0x44).to_u32(), 0x4411_2233);
+        assert_eq!(Rgba::from_u32(0xFF80_4020), Rgba(0x80, 0x40, 0x20, 0xFF));
+    }
+    #[test]
+    fn red_grays_to_its_luminance() {
+        // 0.299 * 255 = 76.2
+        assert_eq!(Rgba(255, 0, 0, 200).grayscale(), Rgba(76, 76, 76, 200));
+    }
+    #[test]
+    fn brighten_saturates_without_overflow() {
+        assert_eq!(Rgba(100, 100, 100, 255).brighten(2.0), Rgba(200, 200, 200, 255));
+        assert_eq!(Rgba(200, 10, 128, 9).brighten(2.0), Rgba(255, 20, 255, 9));
+    }
+}
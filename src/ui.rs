@@ -0,0 +1,102 @@
+use crate::screen::Screen;
+use crate::types::{Rect, Rgba, Vec2i};
+
+const BUTTON_COL: Rgba = Rgba(80, 80, 120, 255);
+const BUTTON_HOVER_COL: Rgba = Rgba(120, 120, 180, 255);
+const BUTTON_PRESSED_COL: Rgba = Rgba(50, 50, 80, 255);
+const BUTTON_EDGE_COL: Rgba = Rgba(220, 220, 240, 255);
+
+/// A clickable rectangle. rect is in the same coordinates as the mouse position you give it
+/// (framebuffer pixels for a menu, or world space if you're drawing it scrolled).
+pub struct Button {
+    pub rect: Rect,
+}
+
+#[allow(dead_code)]
+impl Button {
+    pub fn new(rect: Rect) -> Self {
+        Self { rect }
+    }
+    /// Is the mouse over the button?
+    pub fn hovered(&self, Vec2i(x, y): Vec2i) -> bool {
+        let r = self.rect;
+        r.x <= x && x < r.x + r.w as i32 && r.y <= y && y < r.y + r.h as i32
+    }
+    /// Did a click just land on the button? pressed should be true only on the frame
+    /// the mouse button went down (like Input::mouse_pressed), so holding doesn't repeat.
+    pub fn clicked(&self, mouse: Vec2i, pressed: bool) -> bool {
+        pressed && self.hovered(mouse)
+    }
+    /// Draw the button, lighter when hovered and darker while held down
+    pub fn draw(&self, screen: &mut Screen, mouse: Vec2i, held: bool) {
+        let col = match (self.hovered(mouse), held) {
+            (true, true) => BUTTON_PRESSED_COL,
+            (true, false) => BUTTON_HOVER_COL,
+            _ => BUTTON_COL,
+        };
+        screen.rect(self.rect, col);
+        let Rect { x, y, w, h } = self.rect;
+        if w == 0 || h == 0 {
+            return;
+        }
+        let (x1, y1) = (x + w as i32 - 1, y + h as i32 - 1);
+        screen.line(Vec2i(x, y), Vec2i(x1, y), BUTTON_EDGE_COL);
+        screen.line(Vec2i(x1, y), Vec2i(x1, y1), BUTTON_EDGE_COL);
+        screen.line(Vec2i(x1, y1), Vec2i(x, y1), BUTTON_EDGE_COL);
+        screen.line(Vec2i(x, y1), Vec2i(x, y), BUTTON_EDGE_COL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn button() -> Button {
+        Button::new(Rect {
+            x: 10,
+            y: 20,
+            w: 30,
+            h: 10,
+        })
+    }
+
+    #[test]
+    fn hover_means_inside_the_rect() {
+        let b = button();
+        assert!(b.hovered(Vec2i(10, 20)));
+        assert!(b.hovered(Vec2i(39, 29)));
+        // The right and bottom edges are just outside
+        assert!(!b.hovered(Vec2i(40, 25)));
+        assert!(!b.hovered(Vec2i(20, 30)));
+        assert!(!b.hovered(Vec2i(9, 25)));
+    }
+    #[test]
+    fn clicks_need_a_press_inside() {
+        let b = button();
+        assert!(b.clicked(Vec2i(25, 25), true));
+        // Hovering without pressing, and pressing somewhere else, do nothing
+        assert!(!b.clicked(Vec2i(25, 25), false));
+        assert!(!b.clicked(Vec2i(0, 0), true));
+    }
+    #[test]
+    fn looks_change_with_hover_and_press() {
+        let b = button();
+        // The color in the middle of the button
+        let middle = |mouse, held| {
+            let mut fb = vec![0_u8; 50 * 40 * 4];
+            b.draw(
+                &mut Screen::wrap(&mut fb, 50, 40, 4, Vec2i(0, 0)),
+                mouse,
+                held,
+            );
+            let i = (25 * 50 + 25) * 4;
+            Rgba(fb[i], fb[i + 1], fb[i + 2], fb[i + 3])
+        };
+        let (inside, outside) = (Vec2i(25, 25), Vec2i(0, 0));
+        assert_eq!(middle(outside, false), BUTTON_COL);
+        // Held somewhere else isn't pressing this button
+        assert_eq!(middle(outside, true), BUTTON_COL);
+        assert_eq!(middle(inside, false), BUTTON_HOVER_COL);
+        assert_eq!(middle(inside, true), BUTTON_PRESSED_COL);
+    }
+}